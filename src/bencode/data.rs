@@ -6,6 +6,8 @@ pub enum Data {
 	UInt(u64),
 	/// signed integer type. will only be decoded when the value is negative
 	Int(i64),
+	/// integer too large for 64 bits, kept as its ascii digits (with any sign) so it round-trips
+	BigInt(Vec<u8>),
 	/// byte string or binary data
 	Bytes(Vec<u8>),
 	/// list of data
@@ -16,6 +18,66 @@ pub enum Data {
 	End,
 }
 
+fn decimal_len(u: u64) -> usize {
+	u.checked_ilog10().unwrap_or(0) as usize + 1
+}
+
+impl Data {
+	/// exact length of the bencoded form of this data
+	pub fn encoded_len(&self) -> usize {
+		match self {
+			Self::UInt(u) => decimal_len(*u) + 2,
+			Self::Int(i) => decimal_len(i.unsigned_abs()) + (*i < 0) as usize + 2,
+			Self::BigInt(digits) => digits.len() + 2,
+			Self::Bytes(bytes) => decimal_len(bytes.len() as u64) + 1 + bytes.len(),
+			Self::List(list) => list.iter().map(Self::encoded_len).sum::<usize>() + 2,
+			Self::Dict(dict) => {
+				dict.iter()
+					.map(|(k, v)| decimal_len(k.len() as u64) + 1 + k.len() + v.encoded_len())
+					.sum::<usize>() + 2
+			}
+			Self::End => 0,
+		}
+	}
+
+	/// calls `f` on this data and everything in it, parents before their children, with how deeply
+	/// each is nested (0 for this data).  dictionary keys are byte strings, not data, so only the
+	/// values are visited.
+	pub fn walk(&self, f: &mut impl FnMut(&Data, usize)) {
+		self.walk_at(0, f)
+	}
+
+	fn walk_at(&self, depth: usize, f: &mut impl FnMut(&Data, usize)) {
+		f(self, depth);
+		match self {
+			Self::List(list) => list.iter().for_each(|item| item.walk_at(depth + 1, f)),
+			Self::Dict(dict) => dict
+				.iter()
+				.for_each(|(_, value)| value.walk_at(depth + 1, f)),
+			_ => (),
+		}
+	}
+
+	/// like `==`, but an `Int` is never equal to a `UInt`, even of the same value.  they encode
+	/// the same, so use this to check which one decoding gave.
+	pub fn eq_strict(&self, other: &Data) -> bool {
+		match (self, other) {
+			(Self::Int(_), Self::UInt(_)) | (Self::UInt(_), Self::Int(_)) => false,
+			(Self::List(l0), Self::List(r0)) => {
+				l0.len() == r0.len() && l0.iter().zip(r0).all(|(l, r)| l.eq_strict(r))
+			}
+			(Self::Dict(l0), Self::Dict(r0)) => {
+				l0.len() == r0.len()
+					&& l0
+						.iter()
+						.zip(r0)
+						.all(|((lk, lv), (rk, rv))| lk == rk && lv.eq_strict(rv))
+			}
+			_ => self == other,
+		}
+	}
+}
+
 impl PartialEq for Data {
 	fn eq(&self, other: &Self) -> bool {
 		match (self, other) {
@@ -27,6 +89,7 @@ impl PartialEq for Data {
 				}
 			}
 			(Self::UInt(l0), Self::UInt(r0)) => l0 == r0,
+			(Self::BigInt(l0), Self::BigInt(r0)) => l0 == r0,
 			(Self::Bytes(l0), Self::Bytes(r0)) => l0 == r0,
 			(Self::List(l0), Self::List(r0)) => l0 == r0,
 			(Self::Dict(l0), Self::Dict(r0)) => l0 == r0,
@@ -86,6 +149,19 @@ impl From<u64> for Data {
 	}
 }
 
+impl From<u16> for Data {
+	fn from(u: u16) -> Self {
+		Data::UInt(u.into())
+	}
+}
+
+/// bencode has no booleans, so these are encoded as `0` and `1`
+impl From<bool> for Data {
+	fn from(b: bool) -> Self {
+		Data::UInt(b.into())
+	}
+}
+
 impl From<i64> for Data {
 	fn from(i: i64) -> Self {
 		Data::Int(i)
@@ -122,6 +198,23 @@ impl TryFrom<Data> for i64 {
 	}
 }
 
+macro_rules! impl_try_from_data_narrow {
+	($($T: ident)+) => {
+		$(
+			impl TryFrom<Data> for $T {
+				type Error = ();
+
+				fn try_from(value: Data) -> Result<Self, Self::Error> {
+					u64::try_from(value)?.try_into().map_err(|_| ())
+				}
+			}
+		)+
+	};
+}
+
+// e.g. ports
+impl_try_from_data_narrow!(u16 u32);
+
 macro_rules! impl_try_from_data {
 	($T: ident, $path: path) => {
 		impl TryFrom<Data> for $T {
@@ -144,22 +237,35 @@ impl_try_from_data!(Dictionary, Data::Dict);
 type Vecu8 = Vec<u8>;
 impl_try_from_data!(Vecu8, Data::Bytes);
 
+/// `TryFrom<Data>` through the type's `TryFrom<Dictionary>`, failing with `$err` (by default `()`)
+/// if the data isn't a dictionary
 macro_rules! impl_try_from_data_dict {
 	($T: ident) => {
+		impl_try_from_data_dict!($T, ());
+	};
+	($T: ident, $err: expr) => {
 		impl TryFrom<Data> for $T {
-			type Error = ();
+			type Error = <Self as TryFrom<Dictionary>>::Error;
 
 			fn try_from(data: Data) -> Result<Self, Self::Error> {
 				if let Data::Dict(dict) = data {
 					Self::try_from(dict)
 				} else {
-					Err(())
+					Err($err)
 				}
 			}
 		}
 	};
 }
 
+impl<const N: usize> TryFrom<Data> for [u8; N] {
+	type Error = ();
+
+	fn try_from(value: Data) -> Result<Self, Self::Error> {
+		Vecu8::try_from(value)?.try_into().map_err(|_| ())
+	}
+}
+
 impl<T> TryFrom<Data> for Vec<T>
 where
 	T: TryFrom<Data>,
@@ -179,3 +285,81 @@ where
 }
 
 pub(crate) use impl_try_from_data_dict;
+
+#[cfg(test)]
+mod tests {
+	use super::Data;
+	use crate::bencode::{decode, Dictionary};
+
+	#[test]
+	fn test_walk() {
+		let data =
+			decode("d5:filesld6:lengthi1e4:pathl1:aeed6:lengthi2e4:pathl1:b1:ceee4:name3:dire")
+				.unwrap();
+		let mut nodes = 0;
+		let mut max_depth = 0;
+		let mut lengths = 0;
+		data.walk(&mut |node, depth| {
+			nodes += 1;
+			max_depth = max_depth.max(depth);
+			if let Data::UInt(length) = node {
+				lengths += length;
+			}
+		});
+		// dict, files, 2 * (file, length, path) + 3 path components, name
+		assert_eq!(nodes, 12);
+		// dict > files > file > path > component
+		assert_eq!(max_depth, 4);
+		assert_eq!(lengths, 3);
+
+		let mut visited = Vec::new();
+		Data::from("leaf").walk(&mut |node, depth| visited.push((node.clone(), depth)));
+		assert_eq!(visited, vec![(Data::from("leaf"), 0)]);
+	}
+
+	#[test]
+	fn test_try_from_narrow() {
+		assert_eq!(u16::try_from(Data::UInt(8080)), Ok(8080));
+		assert_eq!(u16::try_from(Data::Int(65535)), Ok(65535));
+		assert_eq!(u32::try_from(Data::UInt(70000)), Ok(70000));
+
+		// out of range
+		assert_eq!(u16::try_from(Data::UInt(65536)), Err(()));
+		assert_eq!(u16::try_from(Data::Int(-1)), Err(()));
+		assert_eq!(u32::try_from(Data::UInt(u32::MAX as u64 + 1)), Err(()));
+		// wrong type
+		assert_eq!(u16::try_from(Data::from("8080")), Err(()));
+	}
+
+	#[test]
+	fn test_try_from_array() {
+		assert_eq!(<[u8; 20]>::try_from(Data::from([7; 20])), Ok([7; 20]));
+		assert_eq!(<[u8; 4]>::try_from(Data::from("spam")), Ok(*b"spam"));
+
+		// wrong length
+		assert_eq!(<[u8; 20]>::try_from(Data::from([7; 6])), Err(()));
+		assert_eq!(<[u8; 20]>::try_from(Data::from(vec![0u8; 21])), Err(()));
+		// wrong type
+		assert_eq!(<[u8; 1]>::try_from(Data::UInt(1)), Err(()));
+	}
+
+	#[test]
+	fn test_eq_strict() {
+		assert_eq!(Data::Int(3), Data::UInt(3));
+		assert!(!Data::Int(3).eq_strict(&Data::UInt(3)));
+		assert!(Data::UInt(3).eq_strict(&Data::UInt(3)));
+		assert!(!Data::UInt(3).eq_strict(&Data::UInt(4)));
+
+		// nested too
+		let uint = decode("d1:ali3eee").unwrap();
+		let int = Data::Dict(Dictionary::from(vec![(
+			"a",
+			Data::List(vec![Data::Int(3)]),
+		)]));
+		assert_eq!(uint, int);
+		assert!(!uint.eq_strict(&int));
+		assert!(uint.eq_strict(&uint.clone()));
+		assert!(!uint.eq_strict(&decode("d1:ali3ei4eee").unwrap()));
+		assert!(!uint.eq_strict(&decode("d1:bli3eee").unwrap()));
+	}
+}
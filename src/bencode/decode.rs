@@ -1,7 +1,19 @@
+use std::fmt;
+
 use super::{Data, Dictionary};
 
 #[derive(Debug, PartialEq)]
-pub struct DataParseError(&'static str);
+pub struct DataParseError {
+	pub msg: &'static str,
+	/// index of the byte the error was found at (the length of the input if it ended early)
+	pub offset: usize,
+}
+
+impl fmt::Display for DataParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.msg)
+	}
+}
 
 fn to_dec_digit(byte: u8) -> Option<u8> {
 	match byte {
@@ -10,28 +22,114 @@ fn to_dec_digit(byte: u8) -> Option<u8> {
 	}
 }
 
+/// limits on what `decode_with` accepts from untrusted input
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+	/// longest byte string allowed, checked before reading it.  also the most digits an integer may
+	/// have, as ones past 64 bits are kept as digits
+	pub max_string_len: u64,
+	/// reject dictionaries whose keys aren't in sorted order, as the spec requires
+	pub sorted_keys: bool,
+}
+
+impl Default for DecodeOptions {
+	fn default() -> Self {
+		Self {
+			max_string_len: 64 * 1024 * 1024,
+			sorted_keys: false,
+		}
+	}
+}
+
+/// counts the bytes taken from the input, for error offsets
+struct Reader<'a, I> {
+	bytes: &'a mut I,
+	offset: usize,
+	options: &'a DecodeOptions,
+}
+
+impl<I: Iterator<Item = u8>> Reader<'_, I> {
+	fn next(&mut self) -> Option<u8> {
+		let byte = self.bytes.next();
+		if byte.is_some() {
+			self.offset += 1;
+		}
+		byte
+	}
+
+	/// error at the byte just read
+	fn error(&self, msg: &'static str) -> DataParseError {
+		DataParseError {
+			msg,
+			offset: self.offset.saturating_sub(1),
+		}
+	}
+
+	/// error for running out of input
+	fn end(&self, msg: &'static str) -> DataParseError {
+		DataParseError {
+			msg,
+			offset: self.offset,
+		}
+	}
+}
+
 pub fn decode_iter(bytes: &mut impl Iterator<Item = u8>) -> Result<Data, DataParseError> {
+	decode_iter_with(bytes, &DecodeOptions::default())
+}
+
+pub fn decode_iter_with(
+	bytes: &mut impl Iterator<Item = u8>,
+	options: &DecodeOptions,
+) -> Result<Data, DataParseError> {
+	decode_reader(&mut Reader {
+		bytes,
+		offset: 0,
+		options,
+	})
+}
+
+fn decode_reader<I: Iterator<Item = u8>>(bytes: &mut Reader<I>) -> Result<Data, DataParseError> {
 	let start = match bytes.next() {
 		Some(b) => b,
-		None => return Err(DataParseError("Empty string.")),
+		None => return Err(bytes.end("Empty string.")),
 	};
 
 	if let Some(i) = to_dec_digit(start) {
 		let mut len: u64 = i as u64;
-		while let Some(byte) = bytes.next() {
-			if byte == b':' {
-				break;
+		loop {
+			// checked as each digit is read, before reading any of the string
+			if len > bytes.options.max_string_len {
+				return Err(bytes.error("String longer than the maximum length."));
+			}
+			match bytes.next() {
+				None | Some(b':') => break,
+				Some(byte) => match to_dec_digit(byte) {
+					Some(i) => {
+						// only reachable with a maximum near u64::MAX
+						len = len
+							.checked_mul(10)
+							.and_then(|len| len.checked_add(i as u64))
+							.ok_or_else(|| bytes.error("String length overflow."))?
+					}
+					None => return Err(bytes.error("Unexpected non-number.")),
+				},
 			}
-			match to_dec_digit(byte) {
-				Some(i) => len = len * 10 + i as u64,
-				None => return Err(DataParseError("Unexpected non-number.")),
-			};
 		}
-		let mut buf = Vec::new();
+		// fail before reading (and allocating for) a string the input can't hold
+		if let (_, Some(remaining)) = bytes.bytes.size_hint() {
+			if len > remaining as u64 {
+				return Err(DataParseError {
+					msg: "Unexpected end of data.",
+					offset: bytes.offset + remaining,
+				});
+			}
+		}
+		let mut buf = Vec::with_capacity(len as usize);
 		for _ in 0..len {
 			match bytes.next() {
 				Some(byte) => buf.push(byte),
-				None => return Err(DataParseError("Unexpected end of data.")),
+				None => return Err(bytes.end("Unexpected end of data.")),
 			}
 		}
 		return Ok(Data::Bytes(buf));
@@ -40,62 +138,51 @@ pub fn decode_iter(bytes: &mut impl Iterator<Item = u8>) -> Result<Data, DataPar
 	match start {
 		b'e' => Ok(Data::End),
 		b'i' => {
-			match bytes.next() {
-				Some(n @ b'0'..=b'9') => {
-					let mut u = (n - b'0') as u64;
-					let mut completed = false;
-					while let Some(byte @ b'0'..=b'9' | byte @ b'e') = bytes.next() {
-						if byte == b'e' {
-							completed = true;
-							break;
-						}
-						u = u
-							.checked_mul(10)
-							.map(|x| x.checked_add((byte - b'0') as u64))
-							.flatten()
-							.ok_or(DataParseError("Integer overflow (unsigned 64-bit)"))?;
+			let mut digits = Vec::new();
+			let mut completed = false;
+			while let Some(byte) = bytes.next() {
+				match byte {
+					b'e' => {
+						completed = true;
+						break;
 					}
-					if completed {
-						Ok(Data::UInt(u))
-					} else {
-						return Err(DataParseError("Unexpected non-digit character."));
+					// kept as digits if it's too big for 64 bits, so it's limited like a string
+					_ if digits.len() as u64 >= bytes.options.max_string_len => {
+						return Err(bytes.error("Integer longer than the maximum length."))
 					}
-					// TODO check for -0 and leading zero which are invalid per spec
+					b'-' if digits.is_empty() => digits.push(byte),
+					b'0'..=b'9' => digits.push(byte),
+					_ => return Err(bytes.error("Unexpected non-digit character.")),
 				}
-				// only use signed integers when it's necessary (i.e., when it's negative)
-				Some(b'-') => {
-					let mut i = match bytes.next() {
-						Some(byte @ b'0'..=b'9') => -((byte - b'0') as i64),
-						_ => return Err(DataParseError("Unexpected non-digit character.")),
-					};
-
-					let mut completed = false;
-					while let Some(byte @ (b'0'..=b'9' | b'e')) = bytes.next() {
-						if byte == b'e' {
-							completed = true;
-							break;
-						}
-						i = i
-							.checked_mul(10)
-							// it's negative so you subtract the numbers
-							.map(|x| x.checked_sub((byte - b'0') as i64))
-							.flatten()
-							.ok_or(DataParseError("Integer overflow (signed 64-bit)."))?;
-					}
-					if completed {
-						Ok(Data::Int(i))
-					} else {
-						Err(DataParseError("Unexpected non-digit character."))
-					}
-				}
-				_ => Err(DataParseError("Unexpected non-digit character")),
 			}
+
+			if !completed {
+				return Err(bytes.end("Unexpected end of data."));
+			}
+			if matches!(&digits[..], [] | [b'-']) {
+				return Err(bytes.error("Unexpected non-digit character."));
+			}
+			// each number has only one encoding, so a BigInt's digits encode back the same
+			if matches!(&digits[..], [b'-', b'0', ..] | [b'0', _, ..]) {
+				return Err(bytes.error("Invalid leading zero."));
+			}
+
+			// digits are all ascii, so the only way parsing fails is overflow
+			let string = std::str::from_utf8(&digits).expect("digits are ascii");
+			// only use signed integers when it's necessary (i.e., when it's negative)
+			let parsed = if digits[0] == b'-' {
+				string.parse().map(Data::Int).ok()
+			} else {
+				string.parse().map(Data::UInt).ok()
+			};
+
+			Ok(parsed.unwrap_or(Data::BigInt(digits)))
 		}
 		b'l' => {
 			let mut vec = Vec::new();
 
 			loop {
-				match decode_iter(bytes) {
+				match decode_reader(bytes) {
 					Ok(Data::End) => break,
 					Ok(it) => vec.push(it),
 					Err(err) => return Err(err),
@@ -105,29 +192,50 @@ pub fn decode_iter(bytes: &mut impl Iterator<Item = u8>) -> Result<Data, DataPar
 		}
 		b'd' => {
 			let mut map = Dictionary::new();
+			let mut last_key: Option<Vec<u8>> = None;
 
 			loop {
-				let key = match decode_iter(bytes) {
+				let key_offset = bytes.offset;
+				let key = match decode_reader(bytes) {
 					Ok(Data::End) => break,
 					Ok(Data::Bytes(k)) => k,
-					Ok(_) => return Err(DataParseError("Unexpected non-key type.")),
+					Ok(_) => {
+						return Err(DataParseError {
+							msg: "Unexpected non-key type.",
+							offset: key_offset,
+						})
+					}
 					err => return err,
 				};
 
-				let value = match decode_iter(bytes) {
-					Ok(Data::End) => return Err(DataParseError("Unexpected end of dictionary.")),
+				// equal keys are left to the duplicate check
+				if bytes.options.sorted_keys && last_key.as_ref().is_some_and(|last| key < *last) {
+					return Err(DataParseError {
+						msg: "Dictionary keys out of order.",
+						offset: key_offset,
+					});
+				}
+				if bytes.options.sorted_keys {
+					last_key = Some(key.clone());
+				}
+
+				let value = match decode_reader(bytes) {
+					Ok(Data::End) => return Err(bytes.error("Unexpected end of dictionary.")),
 					Ok(val) => val,
 					err => return err,
 				};
 
 				if let Some(_) = map.insert(key, value) {
-					return Err(DataParseError("Duplicate key in dictionary."));
+					return Err(DataParseError {
+						msg: "Duplicate key in dictionary.",
+						offset: key_offset,
+					});
 				};
 			}
 
 			Ok(Data::Dict(map))
 		}
-		_ => Err(DataParseError("Unexpected data type.")),
+		_ => Err(bytes.error("Unexpected data type.")),
 	}
 }
 
@@ -135,12 +243,43 @@ pub fn decode(data: impl Into<Vec<u8>>) -> Result<Data, DataParseError> {
 	decode_iter(&mut data.into().into_iter())
 }
 
+/// like `decode`, for data borrowed from a buffer, which isn't copied first
+pub fn decode_slice(data: &[u8]) -> Result<Data, DataParseError> {
+	decode_iter(&mut data.iter().copied())
+}
+
+pub fn decode_with(
+	data: impl Into<Vec<u8>>,
+	options: &DecodeOptions,
+) -> Result<Data, DataParseError> {
+	decode_iter_with(&mut data.into().into_iter(), options)
+}
+
+/// like `decode`, but rejects dictionaries with out of order keys, which wouldn't encode back to
+/// the same bytes (and so the same info hash)
+#[allow(dead_code)]
+pub fn decode_strict(data: impl Into<Vec<u8>>) -> Result<Data, DataParseError> {
+	decode_with(
+		data,
+		&DecodeOptions {
+			sorted_keys: true,
+			..DecodeOptions::default()
+		},
+	)
+}
+
 pub fn try_decode_from<T: TryFrom<Data>, D: Into<Vec<u8>>>(
 	data: D,
 ) -> Result<Result<T, T::Error>, DataParseError> {
 	Ok(decode(data)?.try_into())
 }
 
+pub fn try_decode_from_slice<T: TryFrom<Data>>(
+	data: &[u8],
+) -> Result<Result<T, T::Error>, DataParseError> {
+	Ok(decode_slice(data)?.try_into())
+}
+
 #[cfg(test)]
 mod tests {
 	use crate::bencode::*;
@@ -154,6 +293,10 @@ mod tests {
 		assert_decode(format!("i{}e", i64::MIN), i64::MIN);
 		assert_decode("i-3e", -3 as i64);
 		assert_decode("i0e", 0 as i64);
+		// only negatives decode as Int
+		assert!(decode("i3e").unwrap().eq_strict(&Data::UInt(3)));
+		assert!(decode("i-3e").unwrap().eq_strict(&Data::Int(-3)));
+		assert!(!decode("i0e").unwrap().eq_strict(&Data::Int(0)));
 
 		// empty
 		assert_decode_err("ie");
@@ -163,6 +306,65 @@ mod tests {
 
 		// negative sign in invalid place
 		assert_decode_err("i1-e");
+
+		// unterminated
+		assert_decode_err("i12");
+
+		// negative zero and leading zeros
+		assert_decode_err("i-0e");
+		assert_decode_err("i03e");
+		assert_decode_err("i-03e");
+		assert_decode_err("i00e");
+	}
+
+	#[test]
+	fn test_decode_bigint() {
+		let big = "i123456789012345678901234567890e";
+		assert_decode(big, Data::BigInt("123456789012345678901234567890".into()));
+		assert_eq!(encode(decode(big).unwrap()), big.as_bytes());
+
+		let negative = format!("i-{}0e", u64::MAX);
+		assert_eq!(
+			decode(negative.clone()),
+			Ok(Data::BigInt(negative[1..negative.len() - 1].into()))
+		);
+		assert_eq!(
+			encode(decode(negative.clone()).unwrap()),
+			negative.as_bytes()
+		);
+
+		// just past the 64 bit limits
+		assert!(matches!(
+			decode(format!("i{}0e", u64::MAX)),
+			Ok(Data::BigInt(_))
+		));
+		assert!(matches!(
+			decode("i-9223372036854775809e"),
+			Ok(Data::BigInt(_))
+		));
+
+		// doesn't convert to native integers
+		assert_eq!(u64::try_from(decode(big).unwrap()), Err(()));
+
+		// leading zeros would keep it from encoding back the same
+		assert_eq!(
+			decode("i000123456789012345678901234567890e").map_err(|e| e.msg),
+			Err("Invalid leading zero.")
+		);
+
+		// as long as a string may be
+		let options = DecodeOptions {
+			max_string_len: 30,
+			..DecodeOptions::default()
+		};
+		assert!(decode_with(big, &options).is_ok());
+		assert_eq!(
+			decode_with("i-123456789012345678901234567890e", &options),
+			Err(DataParseError {
+				msg: "Integer longer than the maximum length.",
+				offset: 31
+			})
+		);
 	}
 
 	#[test]
@@ -226,6 +428,135 @@ mod tests {
 		// invalid inner data
 		assert_decode_err("d0:a0:e");
 	}
+
+	#[test]
+	fn test_max_string_len() {
+		// absurd lengths fail before reading any of the string
+		assert_eq!(
+			decode("99999999999999999999999:abc"),
+			Err(DataParseError {
+				msg: "String longer than the maximum length.",
+				offset: 7
+			})
+		);
+		// longer than the input, but under the maximum
+		assert_eq!(
+			decode("1000:abc"),
+			Err(DataParseError {
+				msg: "Unexpected end of data.",
+				offset: 8
+			})
+		);
+
+		let options = DecodeOptions {
+			max_string_len: 3,
+			..DecodeOptions::default()
+		};
+		assert_eq!(decode_with("3:abc", &options), Ok(Data::from("abc")));
+		assert!(decode_with("4:abcd", &options).is_err());
+		assert!(decode_with("l3:abc4:abcde", &options).is_err());
+
+		// with no maximum, a length past u64::MAX doesn't wrap
+		let unlimited = DecodeOptions {
+			max_string_len: u64::MAX,
+			..DecodeOptions::default()
+		};
+		assert_eq!(
+			decode_with("99999999999999999999:abc", &unlimited),
+			Err(DataParseError {
+				msg: "String length overflow.",
+				offset: 19
+			})
+		);
+		// 20 digits, but fits
+		assert_eq!(
+			decode_with("18446744073709551615:abc", &unlimited).map_err(|e| e.msg),
+			Err("Unexpected end of data.")
+		);
+	}
+
+	#[test]
+	fn test_decode_strict() {
+		let sorted = "d1:ai1e1:bd1:xi2e1:yi3eee";
+		assert_eq!(decode_strict(sorted), decode(sorted));
+		assert!(decode_strict(sorted).is_ok());
+
+		let unsorted = "d1:bi1e1:ai2ee";
+		assert!(decode(unsorted).is_ok());
+		assert_eq!(
+			decode_strict(unsorted),
+			Err(DataParseError {
+				msg: "Dictionary keys out of order.",
+				offset: 7
+			})
+		);
+		// nested dictionaries are checked too
+		assert!(decode_strict("d1:ad1:yi1e1:xi2eee").is_err());
+		// keys sort as raw bytes
+		assert!(decode_strict("d1:Bi1e1:ai2ee").is_ok());
+		assert!(decode_strict("d1:ai1e1:Bi2ee").is_err());
+		// duplicates are still duplicates
+		assert_eq!(
+			decode_strict("d1:ai1e1:ai2ee").unwrap_err().msg,
+			"Duplicate key in dictionary."
+		);
+	}
+
+	#[test]
+	fn test_decode_err_offset() {
+		let err = |msg, offset| Err(DataParseError { msg, offset });
+
+		// truncated string: runs out at the end of the input
+		assert_eq!(decode("l5:abce"), err("Unexpected end of data.", 7));
+		assert_eq!(decode("i42"), err("Unexpected end of data.", 3));
+		// bad integer: points at the bad byte
+		assert_eq!(
+			decode("d1:ai4x2ee"),
+			err("Unexpected non-digit character.", 6)
+		);
+		assert_eq!(decode("l1:ax"), err("Unexpected data type.", 4));
+		// dictionary errors point at the key
+		assert_eq!(
+			decode("d1:ai1e1:ai2ee"),
+			err("Duplicate key in dictionary.", 7)
+		);
+		assert_eq!(decode("d1:a0:i1e0:e"), err("Unexpected non-key type.", 6));
+
+		// display is just the message
+		assert_eq!(
+			decode("i42").unwrap_err().to_string(),
+			"Unexpected end of data."
+		);
+	}
+
+	#[test]
+	fn test_try_decode_from_runtime_string() {
+		// built at runtime, so neither the String nor a &str of it is 'static
+		let owned = format!("i{}e", 40 + 2);
+		assert_eq!(try_decode_from::<u64, _>(owned.as_str()), Ok(Ok(42)));
+		assert_eq!(try_decode_from::<u64, _>(owned.clone()), Ok(Ok(42)));
+		assert_eq!(try_decode_from::<u64, _>(owned.as_bytes()), Ok(Ok(42)));
+	}
+
+	#[test]
+	fn test_decode_slice() {
+		let buf: [u8; 16] = *b"d1:ai1e1:bli2eee";
+		assert_eq!(
+			decode_slice(&buf[..]),
+			Ok(Data::Dict(Dictionary::from(vec![
+				("a", Data::from(1u64)),
+				("b", Data::List(vec![Data::from(2u64)]))
+			])))
+		);
+		// a prefix of the buffer, which doesn't decode to anything whole
+		assert!(decode_slice(&buf[..5]).is_err());
+
+		assert_eq!(try_decode_from_slice::<u64>(&buf[4..7]), Ok(Ok(1)));
+		assert_eq!(
+			try_decode_from_slice::<u64>(b"i-1e").map(|r| r.is_err()),
+			Ok(true)
+		);
+	}
 }
 
 #[cfg(test)]
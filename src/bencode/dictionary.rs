@@ -1,7 +1,23 @@
-use std::collections::BTreeMap;
+use std::{
+	collections::{btree_map, BTreeMap},
+	ops::Index,
+};
 
 use super::Data;
 
+/// what `Dictionary::merge` does with a key both dictionaries have
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Conflict {
+	/// keep this dictionary's value
+	Keep,
+	/// take the other dictionary's value
+	Overwrite,
+	/// fail without merging anything
+	Error,
+}
+
+/// keys are kept sorted as raw bytes, which is the canonical bencode order, so iterating (and
+/// encoding) always yields them sorted regardless of insertion order.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Dictionary(BTreeMap<Vec<u8>, Data>);
 
@@ -17,10 +33,59 @@ impl Dictionary {
 		)
 	}
 
+	/// whether the keys are strictly increasing, i.e. this will encode canonically.
+	/// always true for a `Dictionary`, kept as a check on that invariant.
+	pub fn is_canonical(&self) -> bool {
+		self.0.keys().zip(self.0.keys().skip(1)).all(|(a, b)| a < b)
+	}
+
+	/// borrows every entry, in key order
+	pub fn iter(&self) -> Iter<'_> {
+		Iter(self.0.iter())
+	}
+
+	/// the value of `key`, leaving it in the dictionary (unlike `remove`)
+	pub fn get(&self, key: &str) -> Option<&Data> {
+		self.0.get(key.as_bytes())
+	}
+
+	pub fn contains_key(&self, key: &str) -> bool {
+		self.0.contains_key(key.as_bytes())
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
 	pub fn append(&mut self, other: &mut Self) {
 		self.0.append(&mut other.0)
 	}
 
+	/// moves every entry of `other` into this dictionary.  with `Conflict::Error`, the first key
+	/// both have is returned and neither is changed.
+	pub fn merge(&mut self, other: Self, on_conflict: Conflict) -> Result<(), Vec<u8>> {
+		if on_conflict == Conflict::Error {
+			if let Some(key) = other.0.keys().find(|key| self.0.contains_key(*key)) {
+				return Err(key.clone());
+			}
+		}
+		for (key, value) in other {
+			match on_conflict {
+				Conflict::Keep => {
+					self.0.entry(key).or_insert(value);
+				}
+				Conflict::Overwrite | Conflict::Error => {
+					self.0.insert(key, value);
+				}
+			}
+		}
+		Ok(())
+	}
+
 	pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Data>) -> Option<Data> {
 		self.0.insert(key.into(), value.into())
 	}
@@ -66,9 +131,135 @@ impl Dictionary {
 impl IntoIterator for Dictionary {
 	type Item = (Vec<u8>, Data);
 
-	type IntoIter = std::collections::btree_map::IntoIter<Vec<u8>, Data>;
+	type IntoIter = btree_map::IntoIter<Vec<u8>, Data>;
 
 	fn into_iter(self) -> Self::IntoIter {
 		self.0.into_iter()
 	}
 }
+
+/// The entries of a borrowed `Dictionary`, in key order.
+pub struct Iter<'a>(btree_map::Iter<'a, Vec<u8>, Data>);
+
+impl<'a> Iterator for Iter<'a> {
+	type Item = (&'a [u8], &'a Data);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		self.0.next().map(|(key, value)| (&key[..], value))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.0.size_hint()
+	}
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a Dictionary {
+	type Item = (&'a [u8], &'a Data);
+
+	type IntoIter = Iter<'a>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.iter()
+	}
+}
+
+/// panics if `key` isn't in the dictionary, see `get`
+impl Index<&str> for Dictionary {
+	type Output = Data;
+
+	fn index(&self, key: &str) -> &Data {
+		self.get(key)
+			.unwrap_or_else(|| panic!("No key {:?} in dictionary.", key))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Conflict, Dictionary};
+	use crate::bencode::Data;
+
+	fn dicts() -> (Dictionary, Dictionary) {
+		(
+			Dictionary::from(vec![("a", 1u64), ("b", 2u64)]),
+			Dictionary::from(vec![("b", 3u64), ("c", 4u64)]),
+		)
+	}
+
+	#[test]
+	fn test_merge() {
+		let (mut dict, other) = dicts();
+		dict.merge(other, Conflict::Keep).unwrap();
+		assert_eq!(
+			dict,
+			Dictionary::from(vec![("a", 1u64), ("b", 2u64), ("c", 4u64)])
+		);
+
+		let (mut dict, other) = dicts();
+		dict.merge(other, Conflict::Overwrite).unwrap();
+		assert_eq!(
+			dict,
+			Dictionary::from(vec![("a", 1u64), ("b", 3u64), ("c", 4u64)])
+		);
+
+		let (mut dict, other) = dicts();
+		assert_eq!(dict.merge(other, Conflict::Error), Err(b"b".to_vec()));
+		// nothing was merged
+		assert_eq!(dict, dicts().0);
+
+		let (mut dict, _) = dicts();
+		dict.merge(Dictionary::from(vec![("c", 4u64)]), Conflict::Error)
+			.unwrap();
+		assert_eq!(
+			dict,
+			Dictionary::from(vec![("a", 1u64), ("b", 2u64), ("c", 4u64)])
+		);
+	}
+
+	#[test]
+	fn test_get() {
+		let dict = Dictionary::from(vec![("a", 1u64), ("b", 2u64)]);
+
+		assert_eq!(dict.get("a"), Some(&Data::Int(1)));
+		assert_eq!(dict.get("c"), None);
+		assert!(dict.contains_key("b"));
+		assert!(!dict.contains_key("c"));
+		assert_eq!(dict.len(), 2);
+		assert!(!dict.is_empty());
+		assert!(Dictionary::new().is_empty());
+
+		// nothing was removed
+		assert_eq!(dict.get("a"), Some(&Data::Int(1)));
+		assert_eq!(dict, Dictionary::from(vec![("a", 1u64), ("b", 2u64)]));
+	}
+
+	#[test]
+	fn test_borrowed_iter() {
+		let dict = Dictionary::from(vec![("b", 2u64), ("c", 3u64), ("a", 1u64)]);
+
+		let mut entries = Vec::new();
+		for (key, value) in &dict {
+			entries.push((key, value.clone()));
+		}
+		assert_eq!(
+			entries,
+			vec![
+				(&b"a"[..], Data::from(1u64)),
+				(&b"b"[..], Data::from(2u64)),
+				(&b"c"[..], Data::from(3u64))
+			]
+		);
+		assert_eq!(dict.iter().len(), 3);
+
+		assert_eq!(dict["b"], Data::from(2u64));
+		// still usable, and the owned iterator still works
+		assert_eq!(dict.into_iter().count(), 3);
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_index_missing() {
+		let _ = &Dictionary::new()["a"];
+	}
+}
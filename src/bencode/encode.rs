@@ -1,39 +1,77 @@
-use super::Data;
+use std::io::{self, Write};
 
+use super::{Data, Dictionary};
+
+/// encodes `data` as bencode.  dictionary keys are always emitted in sorted order.
 pub fn encode(data: impl Into<Data>) -> Vec<u8> {
 	let data = data.into();
-	match data {
-		Data::Bytes(mut s) => {
-			let mut vec = format!("{}:", s.len()).into_bytes();
-			vec.append(&mut s);
-			vec
+	let mut buf = Vec::with_capacity(data.encoded_len());
+	encode_into(data, &mut buf);
+	buf
+}
+
+/// encodes `data` onto the end of `buf`, without any intermediate allocations.
+pub fn encode_into(data: impl Into<Data>, buf: &mut Vec<u8>) {
+	encode_to_writer(data, buf).expect("Writing to a Vec doesn't fail.")
+}
+
+/// encodes `data` straight to `w`, e.g. a file, without building it in memory first.  `w` gets a
+/// lot of small writes, so buffer it.
+pub fn encode_to_writer<W: Write + ?Sized>(data: impl Into<Data>, w: &mut W) -> io::Result<()> {
+	match data.into() {
+		Data::Bytes(s) => {
+			write!(w, "{}:", s.len())?;
+			w.write_all(&s)
+		}
+		Data::UInt(u) => write!(w, "i{}e", u),
+		Data::Int(i) => write!(w, "i{}e", i),
+		Data::BigInt(digits) => {
+			w.write_all(b"i")?;
+			w.write_all(&digits)?;
+			w.write_all(b"e")
 		}
-		Data::UInt(u) => format!("i{}e", u).into_bytes(),
-		Data::Int(i) => format!("i{}e", i).into_bytes(),
 		Data::List(list) => {
-			let mut buf = vec![b'l'];
+			w.write_all(b"l")?;
 			for pt in list {
-				buf.append(&mut encode(pt));
+				encode_to_writer(pt, w)?;
 			}
-			buf.push(b'e');
-			buf
+			w.write_all(b"e")
 		}
 		Data::Dict(dict) => {
-			let mut buf = vec![b'd'];
+			w.write_all(b"d")?;
 			for (k, v) in dict {
-				buf.append(&mut encode(k));
-				buf.append(&mut encode(v));
+				encode_to_writer(k, w)?;
+				encode_to_writer(v, w)?;
 			}
-			buf.push(b'e');
-			buf
+			w.write_all(b"e")
 		}
 		Data::End => panic!("Don't use Data::End to encode"),
 	}
 }
 
+/// encodes `dict` with the keys in `order` first, in that order, then the rest sorted.  this isn't
+/// canonical bencode, so only use it for clients that need it.
+pub fn encode_ordered(mut dict: Dictionary, order: &[&str]) -> Vec<u8> {
+	let mut buf = vec![b'd'];
+	for key in order {
+		if let Some(value) = dict.remove(key) {
+			encode_into(*key, &mut buf);
+			encode_into(value, &mut buf);
+		}
+	}
+	for (key, value) in dict {
+		encode_into(key, &mut buf);
+		encode_into(value, &mut buf);
+	}
+	buf.push(b'e');
+	buf
+}
+
 #[cfg(test)]
 mod tests {
-	use super::encode;
+	use std::io::{self, Write};
+
+	use super::{encode, encode_into, encode_ordered, encode_to_writer};
 	use crate::bencode::{Data, Dictionary};
 
 	#[test]
@@ -48,6 +86,19 @@ mod tests {
 		assert_eq!(encode(3 as i64), b"i3e");
 		assert_eq!(encode(-3 as i64), b"i-3e");
 		assert_eq!(encode(0 as i64), b"i0e");
+		assert_eq!(encode(8080u16), b"i8080e");
+	}
+
+	#[test]
+	fn test_encode_bool() {
+		assert_eq!(encode(true), b"i1e");
+		assert_eq!(encode(false), b"i0e");
+
+		let mut dict = Dictionary::new();
+		dict.insert("private", true);
+		dict.insert_some("public", Some(false));
+		dict.insert_some("unset", None::<bool>);
+		assert_eq!(encode(dict), b"d7:privatei1e6:publici0ee");
 	}
 
 	#[test]
@@ -78,5 +129,100 @@ mod tests {
 		);
 
 		assert_eq!(encode(Dictionary::new()), b"de");
+
+		// insertion order doesn't matter
+		let mut dict = Dictionary::new();
+		dict.insert("zebra", 1u64);
+		dict.insert("mule", 2u64);
+		dict.insert("aardvark", 3u64);
+		assert!(dict.is_canonical());
+		assert_eq!(encode(dict), b"d8:aardvarki3e4:mulei2e5:zebrai1ee");
+	}
+
+	#[test]
+	fn test_encoded_len() {
+		let cases: Vec<Data> = vec![
+			"".into(),
+			"spam".into(),
+			vec![0u8; 1234].into(),
+			0u64.into(),
+			u64::MAX.into(),
+			(-3i64).into(),
+			i64::MIN.into(),
+			Data::BigInt("123456789012345678901234567890".into()),
+			Vec::<Data>::new().into(),
+			vec!["spam", "eggs"].into(),
+			Dictionary::from(vec![
+				("publisher", Data::from("bob")),
+				("list", vec![vec![1u64, 22u64], vec![333u64]].into()),
+				(
+					"nested",
+					Dictionary::from(vec![("deeper", Dictionary::from(vec![("x", -10i64)]))])
+						.into(),
+				),
+			])
+			.into(),
+		];
+
+		for data in cases {
+			assert_eq!(data.encoded_len(), encode(data.clone()).len(), "{:?}", data);
+		}
+	}
+
+	#[test]
+	fn test_encode_into() {
+		let data = Dictionary::from(vec![
+			("cow", Data::from("moo")),
+			("spam", vec!["a", "b"].into()),
+			("n", (-4i64).into()),
+		]);
+
+		let mut buf = Vec::new();
+		encode_into(data.clone(), &mut buf);
+		assert_eq!(buf, encode(data.clone()));
+
+		// appends to existing contents
+		let mut buf = b"prefix".to_vec();
+		encode_into(data.clone(), &mut buf);
+		assert_eq!(buf, [&b"prefix"[..], &encode(data)].concat());
+	}
+
+	#[test]
+	fn test_encode_to_writer() {
+		let data = Dictionary::from(vec![
+			("cow", Data::from("moo")),
+			("spam", vec!["a", "b"].into()),
+			("n", (-4i64).into()),
+			("big", Data::BigInt("123456789012345678901234567890".into())),
+		]);
+
+		let mut buf = Vec::new();
+		encode_to_writer(data.clone(), &mut buf).unwrap();
+		assert_eq!(buf, encode(data.clone()));
+
+		// errors from the writer come back
+		struct Full;
+		impl Write for Full {
+			fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+				Err(io::ErrorKind::WriteZero.into())
+			}
+			fn flush(&mut self) -> io::Result<()> {
+				Ok(())
+			}
+		}
+		assert_eq!(
+			encode_to_writer(data, &mut Full).unwrap_err().kind(),
+			io::ErrorKind::WriteZero
+		);
+	}
+
+	#[test]
+	fn test_encode_ordered() {
+		let dict = Dictionary::from(vec![("a", 1u64), ("b", 2u64), ("c", 3u64)]);
+		assert_eq!(encode_ordered(dict.clone(), &[]), encode(dict.clone()));
+		assert_eq!(
+			encode_ordered(dict.clone(), &["c", "x", "a"]),
+			b"d1:ci3e1:ai1e1:bi2ee"
+		);
 	}
 }
@@ -0,0 +1,103 @@
+use std::fmt::Write;
+
+use super::Data;
+use crate::bytes::BytesExt;
+
+/// byte strings that aren't UTF-8 become hex, since JSON strings can't hold arbitrary bytes
+fn write_bytes(out: &mut String, bytes: &[u8]) {
+	let s = match std::str::from_utf8(bytes) {
+		Ok(s) => s.to_string(),
+		Err(_) => bytes.to_hex_string(),
+	};
+
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			c if (c as u32) < 0x20 => {
+				write!(out, "\\u{:04x}", c as u32).expect("writing to a String can't fail")
+			}
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+}
+
+fn write_json(out: &mut String, data: &Data) {
+	match data {
+		Data::UInt(u) => write!(out, "{}", u).expect("writing to a String can't fail"),
+		Data::Int(i) => write!(out, "{}", i).expect("writing to a String can't fail"),
+		// JSON numbers have no size limit
+		Data::BigInt(digits) => out.push_str(&digits.to_string()),
+		Data::Bytes(bytes) => write_bytes(out, bytes),
+		Data::List(list) => {
+			out.push('[');
+			for (i, item) in list.iter().enumerate() {
+				if i > 0 {
+					out.push(',');
+				}
+				write_json(out, item);
+			}
+			out.push(']');
+		}
+		Data::Dict(dict) => {
+			out.push('{');
+			for (i, (key, value)) in dict.iter().enumerate() {
+				if i > 0 {
+					out.push(',');
+				}
+				write_bytes(out, key);
+				out.push(':');
+				write_json(out, value);
+			}
+			out.push('}');
+		}
+		Data::End => out.push_str("null"),
+	}
+}
+
+impl Data {
+	/// renders the data as compact JSON.  dictionary keys keep their (sorted) bencode order.
+	pub fn to_json(&self) -> String {
+		let mut out = String::new();
+		write_json(&mut out, self);
+		out
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::bencode::*;
+
+	#[test]
+	fn test_to_json() {
+		let json = |s: &str| decode(s).unwrap().to_json();
+
+		assert_eq!(json("i42e"), "42");
+		assert_eq!(json("i-42e"), "-42");
+		assert_eq!(
+			json("i123456789012345678901234567890e"),
+			"123456789012345678901234567890"
+		);
+		assert_eq!(json("le"), "[]");
+		assert_eq!(json("de"), "{}");
+		assert_eq!(
+			json("d1:bli1e1:xe1:ad1:c0:ee"),
+			r#"{"a":{"c":""},"b":[1,"x"]}"#
+		);
+		// escapes
+		assert_eq!(json("7:\"\\\n\t\x01\u{e9}"), r#""\"\\\n\t\u0001é""#);
+	}
+
+	#[test]
+	fn test_to_json_binary() {
+		assert_eq!(Data::Bytes(vec![0xff, 0x00, 0x10]).to_json(), r#""ff0010""#);
+		let mut dict = Dictionary::new();
+		dict.insert(vec![0xfe], 1u64);
+		assert_eq!(Data::Dict(dict).to_json(), r#"{"fe":1}"#);
+	}
+}
@@ -2,11 +2,14 @@ mod data;
 mod decode;
 mod dictionary;
 mod encode;
+mod json;
 
 pub(crate) use data::impl_try_from_data_dict;
 pub use data::Data;
 pub use decode::*;
-pub use dictionary::Dictionary;
-pub use encode::encode;
+pub use dictionary::{Conflict, Dictionary};
+#[allow(unused_imports)]
+pub use encode::encode_into;
+pub use encode::{encode, encode_ordered, encode_to_writer};
 
 // see https://wiki.theory.org/BitTorrentSpecification#Bencoding
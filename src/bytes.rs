@@ -2,6 +2,9 @@ pub trait BytesExt {
 	fn to_hex_string(&self) -> String;
 	fn to_string(&self) -> String;
 	fn to_alphanumeric_or_hex(&self) -> String;
+	/// unpadded RFC 4648 base32, e.g. 32 characters for an info hash
+	#[allow(dead_code)]
+	fn to_base32(&self) -> String;
 }
 
 impl BytesExt for [u8] {
@@ -23,6 +26,51 @@ impl BytesExt for [u8] {
 		}
 		string
 	}
+
+	fn to_base32(&self) -> String {
+		const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+		let mut string = String::new();
+		let mut bits: u32 = 0;
+		let mut count = 0;
+		for &byte in self {
+			bits = bits << 8 | byte as u32;
+			count += 8;
+			while count >= 5 {
+				count -= 5;
+				string.push(ALPHABET[(bits >> count) as usize & 0x1f] as char);
+			}
+		}
+		if count > 0 {
+			string.push(ALPHABET[(bits << (5 - count)) as usize & 0x1f] as char);
+		}
+		string
+	}
+}
+
+/// RFC 4648 base32, which older clients use for 20 byte hashes (32 characters)
+pub fn from_base32(base32: &[u8]) -> Option<[u8; 20]> {
+	if base32.len() != 32 {
+		return None;
+	}
+	let mut bytes = [0; 20];
+	let mut bits: u64 = 0;
+	let mut count = 0;
+	let mut i = 0;
+	for &c in base32 {
+		let value = match c.to_ascii_uppercase() {
+			c @ b'A'..=b'Z' => c - b'A',
+			c @ b'2'..=b'7' => c - b'2' + 26,
+			_ => return None,
+		};
+		bits = bits << 5 | value as u64;
+		count += 5;
+		if count >= 8 {
+			count -= 8;
+			bytes[i] = (bits >> count) as u8;
+			i += 1;
+		}
+	}
+	Some(bytes)
 }
 
 #[cfg(test)]
@@ -38,3 +86,24 @@ pub fn assert_bytes_eq<'a, 'b>(lhs: impl Into<Vec<u8>>, rhs: impl Into<Vec<u8>>)
 		)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{from_base32, BytesExt};
+
+	#[test]
+	fn test_base32() {
+		let hash = [
+			0x93, 0x62, 0x07, 0xe0, 0x49, 0xdc, 0x6f, 0x3d, 0x8b, 0xa6, 0x13, 0x38, 0xb6, 0x7c,
+			0xb2, 0x10, 0x07, 0x4c, 0xae, 0x60,
+		];
+		assert_eq!(hash.to_base32(), "SNRAPYCJ3RXT3C5GCM4LM7FSCADUZLTA");
+		assert_eq!(from_base32(hash.to_base32().as_bytes()), Some(hash));
+
+		// RFC 4648 test vectors, without the padding
+		assert_eq!(b"".to_base32(), "");
+		assert_eq!(b"f".to_base32(), "MY");
+		assert_eq!(b"fooba".to_base32(), "MZXW6YTB");
+		assert_eq!(b"foobar".to_base32(), "MZXW6YTBOI");
+	}
+}
@@ -1,9 +1,12 @@
 use std::{
-	net::IpAddr,
-	path::PathBuf,
+	collections::HashSet,
+	net::{IpAddr, Ipv4Addr, Ipv6Addr},
+	path::{Path, PathBuf},
 	process::{self, Child, Command},
 };
 
+use crate::{bytes::from_base32, log::LogLevel};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
 	String(String),
@@ -49,10 +52,13 @@ impl TryFrom<String> for Action {
 	}
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Clone)]
 pub enum PeerHost {
 	IP(IpAddr),
 	HOST,
+	/// look up `host` and advertise its address
+	RESOLVE,
 	INFER,
 }
 
@@ -63,9 +69,77 @@ pub struct Config {
 	pub server_port: u16,
 	pub peer_port: u16,
 	pub info_hash: [u8; 20],
-	pub file: Option<PathBuf>,
+	/// files to create torrents for, in the order given
+	pub files: Vec<PathBuf>,
 	pub expected_ip: IpAddr,
 	pub peer_host: PeerHost,
+	pub with_md5: bool,
+	pub dry_run: bool,
+	pub piece_length: u32,
+	pub no_creation_date: bool,
+	pub comment: Option<String>,
+	/// name of the file in the generated torrent, required when reading it from stdin
+	pub name: Option<String>,
+	pub announce: Vec<String>,
+	/// seconds a peer must wait between announces, 0 to allow any rate
+	pub min_announce_interval: u64,
+	/// connections accepted per source ip each minute, 0 for no limit
+	pub max_conns_per_min: u32,
+	/// serve the peer protocol on `server_port` too
+	pub combined: bool,
+	/// list seeders before leechers in peer lists sent to leechers
+	pub prefer_seeders: bool,
+	/// if set, the peer only answers handshakes from these peer ids
+	pub allowed_peer_ids: Option<HashSet<[u8; 20]>>,
+	/// `Content-Type` of tracker responses
+	pub response_content_type: String,
+	/// addresses to advertise our peer at instead of the one from `peer_host`, for dual-stack hosts
+	pub advertise_ipv4: Option<Ipv4Addr>,
+	pub advertise_ipv6: Option<Ipv6Addr>,
+	/// write announce responses with `interval` first, for clients that expect it, instead of sorted
+	pub legacy_key_order: bool,
+	/// leechers whose total uploaded / downloaded is below this get `insufficient ratio` instead of peers
+	pub min_ratio: Option<f64>,
+	/// if set, announces must carry one of these passkeys, in the path (`/announce/<passkey>`) or as
+	/// `passkey` in the query
+	pub passkeys: Option<HashSet<String>>,
+	/// how much is logged to stderr
+	pub log_level: LogLevel,
+	/// peers tracked per torrent before the least recently seen is dropped, 0 for no limit
+	pub max_peers_per_torrent: usize,
+	/// fail announces that don't ask for a compact peer list
+	pub require_compact: bool,
+	/// announce over https in generated torrents, e.g. behind a TLS proxy
+	pub tls: bool,
+	/// fail a peer's first announce to a torrent unless it has `event=started`
+	pub require_started_first: bool,
+	/// seeds the shuffling of peers in announce responses, so it is reproducible.  `None` seeds from
+	/// entropy.
+	pub rng_seed: Option<u64>,
+	/// path the tracker answers announces at, from the leading `/`
+	pub announce_path: String,
+	/// path the tracker answers scrapes at, from the leading `/`
+	pub scrape_path: String,
+	/// seconds peers are told to wait between announces, unless their torrent has its own
+	pub announce_interval: u64,
+	/// local address `tracker::announce` connects to trackers from, for hosts with more than one
+	/// interface.  `None` lets the OS pick.
+	pub announce_bind: Option<IpAddr>,
+}
+
+/// hex digits to bytes, ignoring a trailing odd digit
+fn parse_hex(arg: &str) -> Option<Vec<u8>> {
+	let mut chars = arg.chars();
+	let mut bytes = Vec::new();
+	while let (Some(a), Some(b)) = (chars.next(), chars.next()) {
+		// max of a and b is both 15, so the max of this expression is (15 * 16) + 15 = 255 < 2^8
+		bytes.push(
+			((a.to_digit(16)? * 16) + b.to_digit(16)?)
+				.try_into()
+				.unwrap(),
+		)
+	}
+	Some(bytes)
 }
 
 fn next_arg(args: &mut impl Iterator<Item = String>) -> Result<String, &'static str> {
@@ -75,8 +149,213 @@ fn next_arg(args: &mut impl Iterator<Item = String>) -> Result<String, &'static
 	}
 }
 
+/// every option `load_all` accepts: the flags, the name of its value (if it takes one), and what it does
+const OPTIONS: &[(&[&str], &str, &str)] = &[
+	(
+		&["-i", "--info"],
+		"info_hash",
+		"SHA1 hash of the info dictionary of the torrent, in hex or base32",
+	),
+	(
+		&["-n", "--notify"],
+		"notify",
+		"command to run when an unexpected IP is detected, with %IP replaced by the IP",
+	),
+	(
+		&["-e", "--expected-ip"],
+		"ip",
+		"IP that is expected to connect",
+	),
+	(
+		&["-f", "--file"],
+		"file",
+		"create a torrent and info hash for this file, or stdin if `-`, can be given more than once",
+	),
+	(
+		&["-h", "--host"],
+		"host",
+		"host of the tracker (default: 127.0.0.1)",
+	),
+	(
+		&["--peer-host"],
+		"host",
+		"address the peer is advertised at: infer, host, resolve, or an IP (default: infer)",
+	),
+	(
+		&["-s", "--server-port"],
+		"port",
+		"port the tracker listens on (default: 3000)",
+	),
+	(
+		&["-p", "--peer-port"],
+		"port",
+		"port the peer listens on (default: 16384)",
+	),
+	(
+		&["--with-md5"],
+		"",
+		"store the md5sum of the file in the generated torrent",
+	),
+	(
+		&["--dry-run"],
+		"",
+		"print the info hash and torrent size for -f, then exit",
+	),
+	(
+		&["--piece-length"],
+		"length",
+		"piece length of the generated torrent, a power of two from 16384 to 16777216",
+	),
+	(
+		&["--no-creation-date"],
+		"",
+		"leave the creation date out of the generated torrent",
+	),
+	(
+		&["--comment"],
+		"comment",
+		"comment of the generated torrent",
+	),
+	(
+		&["--name"],
+		"name",
+		"file name in the generated torrent, required for stdin",
+	),
+	(
+		&["--announce"],
+		"url",
+		"announce url of the generated torrent, can be given more than once",
+	),
+	(
+		&["--min-announce-interval"],
+		"seconds",
+		"fail announces sooner than this after the last (default: 0, no limit)",
+	),
+	(
+		&["--max-conns-per-min"],
+		"count",
+		"close connections from an IP opening more than this a minute (default: 0, no limit)",
+	),
+	(
+		&["--combined"],
+		"",
+		"serve the tracker and the peer on the server port",
+	),
+	(
+		&["--prefer-seeders"],
+		"",
+		"list seeders before leechers in peers sent to leechers",
+	),
+	(
+		&["--allow-peer-id"],
+		"peer_id",
+		"only answer handshakes from this peer id, can be given more than once",
+	),
+	(
+		&["--content-type"],
+		"type",
+		"Content-Type of tracker responses (default: text/plain)",
+	),
+	(
+		&["--advertise-ipv4"],
+		"ip",
+		"IPv4 address the peer is advertised at",
+	),
+	(
+		&["--advertise-ipv6"],
+		"ip",
+		"IPv6 address the peer is advertised at",
+	),
+	(
+		&["--legacy-key-order"],
+		"",
+		"write announce responses in the key order old clients expect",
+	),
+	(
+		&["--min-ratio"],
+		"ratio",
+		"fail announces from peers with a lower upload to download ratio",
+	),
+	(
+		&["--passkey"],
+		"passkey",
+		"only answer announces with this passkey, can be given more than once",
+	),
+	(
+		&["--log-level"],
+		"level",
+		"how much is logged: error, warn, info, or debug (default: info)",
+	),
+	(
+		&["--max-peers-per-torrent"],
+		"count",
+		"drop the least recently seen peer past this many per torrent (default: 1000, 0: no limit)",
+	),
+	(
+		&["--require-compact"],
+		"",
+		"fail announces that don't ask for a compact peer list",
+	),
+	(
+		&["--tls"],
+		"",
+		"use https in the announce url of generated torrents",
+	),
+	(
+		&["--require-started-first"],
+		"",
+		"fail a peer's first announce unless it has event=started",
+	),
+	(
+		&["--rng-seed"],
+		"seed",
+		"seed the shuffling of peers in announce responses (default: random)",
+	),
+	(
+		&["--announce-path"],
+		"path",
+		"answer announces at this path (default: /announce)",
+	),
+	(
+		&["--scrape-path"],
+		"path",
+		"answer scrapes at this path (default: /scrape)",
+	),
+	(
+		&["--announce-interval"],
+		"seconds",
+		"tell peers to announce this often (default: 300)",
+	),
+	(
+		&["--announce-bind"],
+		"ip",
+		"local address to announce to other trackers from",
+	),
+	(&["-?", "--help"], "", "print this help"),
+];
+
+/// the usage listing printed for `--help`, one line per option
+pub fn help(program: &str) -> String {
+	let mut help = format!("usage: {} [options]\n\noptions:\n", program);
+	let usages: Vec<_> = OPTIONS
+		.iter()
+		.map(|(flags, value, _)| match *value {
+			"" => flags.join(", "),
+			value => format!("{} <{}>", flags.join(", "), value),
+		})
+		.collect();
+	let width = usages.iter().map(String::len).max().unwrap_or(0);
+	for (usage, (_, _, description)) in usages.iter().zip(OPTIONS) {
+		help += &format!("  {:width$}  {}\n", usage, description, width = width);
+	}
+	help
+}
+
 impl Config {
-	pub fn load(mut args: impl Iterator<Item = String>) -> Result<Self, &'static str> {
+	/// goes on after an invalid argument, erroring with every problem in the order they were found
+	/// instead of only the first.  errors with only `"help"` if `-?` or `--help` is given, so the
+	/// caller can print `help`
+	pub fn load_all(mut args: impl Iterator<Item = String>) -> Result<Self, Vec<&'static str>> {
 		let mut command = Err("Missing command.");
 		let mut info_hash = Err("Missing info hash.");
 		let mut expected_ip = Err("Missing expected ip.");
@@ -85,86 +364,278 @@ impl Config {
 		let mut host = "127.0.0.1".to_string();
 		let mut server_port = 3000;
 		let mut peer_port = 16384;
-		let mut file = None;
+		let mut files = Vec::new();
 		let mut peer_host = PeerHost::INFER;
+		let mut with_md5 = false;
+		let mut dry_run = false;
+		let mut piece_length = 16384;
+		let mut no_creation_date = false;
+		let mut comment = None;
+		let mut name = None;
+		let mut announce = Vec::new();
+		let mut min_announce_interval = 0;
+		let mut max_conns_per_min = 0;
+		let mut combined = false;
+		let mut prefer_seeders = false;
+		let mut allowed_peer_ids: Option<HashSet<_>> = None;
+		let mut response_content_type = "text/plain".to_string();
+		let mut advertise_ipv4 = None;
+		let mut advertise_ipv6 = None;
+		let mut legacy_key_order = false;
+		let mut min_ratio = None;
+		let mut passkeys = None;
+		let mut log_level = LogLevel::Info;
+		let mut max_peers_per_torrent = 1000;
+		let mut require_compact = false;
+		let mut tls = false;
+		let mut require_started_first = false;
+		let mut rng_seed = None;
+		let mut announce_path = "/announce".to_string();
+		let mut scrape_path = "/scrape".to_string();
+		let mut announce_interval = 300;
+		let mut announce_bind = None;
 
-		loop {
-			match args.next().as_deref() {
-				Some("-n" | "--notify") => match args.next() {
-					Some(c) => command = Action::try_from(c),
-					None => return Err("Missing value for \"notify\""),
-				},
-				Some("-i" | "--info") => {
-					let arg = next_arg(&mut args)?;
-					let mut chars = arg.chars();
-					let mut info_vec: Vec<u8> = Vec::new();
-					while let (Some(a), Some(b)) = (chars.next(), chars.next()) {
-						match (a.to_digit(16), b.to_digit(16)) {
-							(Some(a), Some(b)) => {
-								// max of a and b is both 15, so the max of this expression is (15 * 16) + 15 = 255 < 2^8
-								info_vec.push(((a * 16) + b).try_into().unwrap())
-							}
-							_ => return Err("Invalid info hash."),
-						}
-					}
-					info_hash = info_vec
-						.try_into()
-						.map_err(|_| "Invalid length of info hash.");
+		let mut errors = Vec::new();
+		while let Some(arg) = args.next() {
+			let mut parse_arg = || -> Result<(), &'static str> {
+				// the help lists every flag, so only those are parsed
+				if !OPTIONS
+					.iter()
+					.any(|(flags, _, _)| flags.contains(&arg.as_str()))
+				{
+					return Err("Unexpected token.");
 				}
-				Some("-h" | "--host") => host = next_arg(&mut args)?,
-				Some("-s" | "--server-port") => {
-					server_port = next_arg(&mut args)?
-						.parse()
-						.map_err(|_| "Invalid server port (must be a number 0 < port < 65536)")?
-				}
-				Some("-p" | "--peer-port") => {
-					peer_port = next_arg(&mut args)?
-						.parse()
-						.map_err(|_| "Invalid peer port (must be a number 0 < port < 65536)")?
-				}
-				Some("-f" | "--file") => match args.next() {
-					Some(f) => {
-						let path = PathBuf::from(f);
-						if path.is_file() {
-							file = Some(path);
-							info_hash = Ok([0; 20]); // placeholder: if file is set, info_hash will always be overwritten
-							 // TODO find a more elegant solution
+				match arg.as_str() {
+					"-n" | "--notify" => match args.next() {
+						Some(c) => command = Action::try_from(c),
+						None => return Err("Missing value for \"notify\""),
+					},
+					"-i" | "--info" => {
+						let arg = next_arg(&mut args)?;
+						// 32 characters is base32, anything else should be 40 hex digits
+						info_hash = if arg.len() == 32 {
+							from_base32(arg.as_bytes()).ok_or("Invalid info hash.")
 						} else {
-							return Err("Argument is not a file.");
+							parse_hex(&arg)
+								.ok_or("Invalid info hash.")
+								.and_then(|hash| {
+									hash.try_into().map_err(|_| "Invalid length of info hash.")
+								})
+						};
+					}
+					"-h" | "--host" => host = next_arg(&mut args)?,
+					"-s" | "--server-port" => {
+						server_port = next_arg(&mut args)?.parse().map_err(|_| {
+							"Invalid server port (must be a number 0 < port < 65536)"
+						})?
+					}
+					"-p" | "--peer-port" => {
+						peer_port = next_arg(&mut args)?
+							.parse()
+							.map_err(|_| "Invalid peer port (must be a number 0 < port < 65536)")?
+					}
+					"-f" | "--file" => match args.next() {
+						Some(f) => {
+							let path = PathBuf::from(f);
+							// `-` reads the file from stdin
+							if path.is_file() || path == Path::new("-") {
+								files.push(path);
+								info_hash = Ok([0; 20]); // placeholder: if file is set, info_hash will always be overwritten
+								 // TODO find a more elegant solution
+							} else {
+								return Err("Argument is not a file.");
+							}
 						}
+						None => return Err("Missing value for \"file\""),
+					},
+					"-e" | "--expected-ip" => {
+						expected_ip = next_arg(&mut args)?
+							.parse()
+							.map_err(|_| "Invalid IP address.")
 					}
-					None => return Err("Missing value for \"file\""),
-				},
-				Some("-e" | "--expected-ip") => {
-					expected_ip = next_arg(&mut args)?
+					"--peer-host" => {
+						peer_host = match next_arg(&mut args).as_deref() {
+							Ok("infer") => Ok(PeerHost::INFER),
+							Ok("host") => Ok(PeerHost::HOST),
+							Ok("resolve") => Ok(PeerHost::RESOLVE),
+							Ok(ip) => ip
+								.parse()
+								.map(|ip| PeerHost::IP(ip))
+								.map_err(|_| "Invalid IP address"),
+							_ => Err("Invalid peer host."),
+						}?
+					}
+					"--with-md5" => with_md5 = true,
+					"--dry-run" => dry_run = true,
+					"--combined" => combined = true,
+					"--prefer-seeders" => prefer_seeders = true,
+					"--content-type" => response_content_type = next_arg(&mut args)?,
+					"--advertise-ipv4" => {
+						advertise_ipv4 = Some(
+							next_arg(&mut args)?
+								.parse()
+								.map_err(|_| "Invalid IPv4 address.")?,
+						)
+					}
+					"--advertise-ipv6" => {
+						advertise_ipv6 = Some(
+							next_arg(&mut args)?
+								.parse()
+								.map_err(|_| "Invalid IPv6 address.")?,
+						)
+					}
+					"--allow-peer-id" => {
+						let peer_id = parse_hex(&next_arg(&mut args)?)
+							.and_then(|id| id.try_into().ok())
+							.ok_or("Invalid peer id (must be 40 hex digits)")?;
+						allowed_peer_ids
+							.get_or_insert_with(HashSet::new)
+							.insert(peer_id);
+					}
+					"--no-creation-date" => no_creation_date = true,
+					"--comment" => comment = Some(next_arg(&mut args)?),
+					"--name" => name = Some(next_arg(&mut args)?),
+					"--announce" => announce.push(next_arg(&mut args)?),
+					"--piece-length" => piece_length = next_arg(&mut args)?
 						.parse()
-						.map_err(|_| "Invalid IP address.")
-				}
-				Some("--peer-host") => {
-					peer_host = match next_arg(&mut args).as_deref() {
-						Ok("infer") => Ok(PeerHost::INFER),
-						Ok("host") => Ok(PeerHost::HOST),
-						Ok(ip) => ip
+						.ok()
+						.filter(|len: &u32| {
+							len.is_power_of_two() && (1 << 14..=1 << 24).contains(len)
+						})
+						.ok_or(
+							"Invalid piece length (must be a power of two from 16384 to 16777216)",
+						)?,
+					"--min-announce-interval" => {
+						min_announce_interval = next_arg(&mut args)?.parse().map_err(|_| {
+							"Invalid minimum announce interval (must be a number of seconds)"
+						})?
+					}
+					"--max-conns-per-min" => {
+						max_conns_per_min = next_arg(&mut args)?.parse().map_err(|_| {
+							"Invalid maximum connections per minute (must be a number)"
+						})?
+					}
+					"--legacy-key-order" => legacy_key_order = true,
+					"--min-ratio" => {
+						min_ratio = Some(
+							next_arg(&mut args)?
+								.parse()
+								.ok()
+								.filter(|ratio: &f64| ratio.is_finite() && *ratio >= 0.0)
+								.ok_or("Invalid minimum ratio (must be a non-negative number)")?,
+						)
+					}
+					"--passkey" => {
+						passkeys
+							.get_or_insert_with(HashSet::new)
+							.insert(next_arg(&mut args)?);
+					}
+					"--log-level" => log_level = next_arg(&mut args)?.parse()?,
+					"--max-peers-per-torrent" => {
+						max_peers_per_torrent = next_arg(&mut args)?
+							.parse()
+							.map_err(|_| "Invalid maximum peers per torrent (must be a number)")?
+					}
+					"--require-compact" => require_compact = true,
+					"--tls" => tls = true,
+					"--require-started-first" => require_started_first = true,
+					"--rng-seed" => {
+						rng_seed = Some(
+							next_arg(&mut args)?
+								.parse()
+								.map_err(|_| "Invalid random seed (must be a number)")?,
+						)
+					}
+					"--announce-path" => {
+						announce_path = Some(next_arg(&mut args)?)
+							.filter(|path| path.starts_with('/'))
+							.ok_or("Invalid announce path (must start with /)")?
+					}
+					"--scrape-path" => {
+						scrape_path = Some(next_arg(&mut args)?)
+							.filter(|path| path.starts_with('/'))
+							.ok_or("Invalid scrape path (must start with /)")?
+					}
+					"--announce-interval" => {
+						announce_interval = next_arg(&mut args)?
 							.parse()
-							.map(|ip| PeerHost::IP(ip))
-							.map_err(|_| "Invalid IP address"),
-						_ => Err("Invalid peer host."),
-					}?
+							.ok()
+							.filter(|&interval| interval > 0)
+							.ok_or("Invalid announce interval (must be a positive number)")?
+					}
+					"--announce-bind" => {
+						announce_bind = Some(
+							next_arg(&mut args)?
+								.parse()
+								.map_err(|_| "Invalid IP address.")?,
+						)
+					}
+					"-?" | "--help" => return Err("help"),
+					_ => return Err("Unexpected token."),
 				}
-				Some(_) => return Err("Unexpected token."),
-				None => break,
+				Ok(())
+			};
+			match parse_arg() {
+				Ok(()) => {}
+				Err("help") => return Err(vec!["help"]),
+				Err(e) => errors.push(e),
+			}
+		}
+
+		if files.iter().any(|file| file == Path::new("-")) && name.is_none() {
+			errors.push("Missing name for file from stdin.");
+		}
+		for result in [
+			command.as_ref().map(|_| ()),
+			info_hash.as_ref().map(|_| ()),
+			expected_ip.as_ref().map(|_| ()),
+		] {
+			if let Err(e) = result {
+				errors.push(e);
 			}
 		}
+		if !errors.is_empty() {
+			return Err(errors);
+		}
 
+		// all three are `Ok`, or they'd be in `errors`
 		Ok(Self {
-			notify: command?,
-			info_hash: info_hash?,
+			notify: command.unwrap(),
+			info_hash: info_hash.unwrap(),
 			host,
 			peer_host,
 			server_port,
 			peer_port,
-			file,
-			expected_ip: expected_ip?,
+			files,
+			expected_ip: expected_ip.unwrap(),
+			with_md5,
+			dry_run,
+			piece_length,
+			no_creation_date,
+			comment,
+			name,
+			announce,
+			min_announce_interval,
+			max_conns_per_min,
+			combined,
+			prefer_seeders,
+			allowed_peer_ids,
+			response_content_type,
+			advertise_ipv4,
+			advertise_ipv6,
+			legacy_key_order,
+			min_ratio,
+			passkeys,
+			log_level,
+			max_peers_per_torrent,
+			require_compact,
+			tls,
+			require_started_first,
+			rng_seed,
+			announce_path,
+			scrape_path,
+			announce_interval,
+			announce_bind,
 		})
 	}
 
@@ -172,15 +643,21 @@ impl Config {
 		let mut args = std::env::args();
 		let filename = args.next().unwrap();
 
-		match Self::load(args) {
+		match Self::load_all(args) {
 			Ok(c) => c,
-			Err(e) => {
+			Err(errors) if errors == ["help"] => {
+				print!("{}", help(&filename));
+				process::exit(0)
+			}
+			Err(errors) => {
+				for e in errors {
+					println!("ERROR: {:?}", e);
+				}
 				println!(
-					r#"ERROR: {:?}
-					
+					r#"
 run {} --help to print a help menu.
 				"#,
-					e, filename,
+					filename,
 				);
 				process::exit(1)
 			}
@@ -201,17 +678,48 @@ impl Default for Config {
 			server_port: 3000,
 			peer_port: 16384,
 			info_hash: [1; 20],
-			file: None,
+			files: vec![],
 			expected_ip: "127.0.0.1".parse().unwrap(),
+			with_md5: false,
+			dry_run: false,
+			piece_length: 16384,
+			no_creation_date: false,
+			comment: None,
+			name: None,
+			announce: vec![],
+			min_announce_interval: 0,
+			max_conns_per_min: 0,
+			combined: false,
+			prefer_seeders: false,
+			allowed_peer_ids: None,
+			response_content_type: "text/plain".into(),
+			advertise_ipv4: None,
+			advertise_ipv6: None,
+			legacy_key_order: false,
+			min_ratio: None,
+			passkeys: None,
+			log_level: LogLevel::Info,
+			max_peers_per_torrent: 1000,
+			require_compact: false,
+			tls: false,
+			require_started_first: false,
+			rng_seed: None,
+			announce_path: "/announce".into(),
+			scrape_path: "/scrape".into(),
+			announce_interval: 300,
+			announce_bind: None,
 		}
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use std::net::IpAddr;
+	use std::{collections::HashSet, net::IpAddr, path::PathBuf};
 
-	use crate::config::{Config, PeerHost};
+	use crate::{
+		config::{help, Config, PeerHost, OPTIONS},
+		log::LogLevel,
+	};
 
 	use super::{Action, Token};
 
@@ -228,6 +736,23 @@ mod tests {
 		}};
 	}
 
+	/// loads a config with only the required arguments, and `extra`
+	fn load_with(extra: &[&str]) -> Result<Config, Vec<&'static str>> {
+		Config::load_all(
+			[
+				"-n",
+				"true",
+				"-i",
+				"0000000000000000000000000000000000000000",
+				"-e",
+				"127.0.0.1",
+			]
+			.iter()
+			.chain(extra)
+			.map(|s| s.to_string()),
+		)
+	}
+
 	#[test]
 	fn test_action_from() {
 		assert_eq!(
@@ -268,10 +793,250 @@ mod tests {
 		)
 	}
 
+	#[test]
+	fn test_piece_length() {
+		let load = |len: &str| load_with(&["--piece-length", len]).map(|c| c.piece_length);
+
+		assert_eq!(load("16384"), Ok(16384));
+		assert_eq!(load("262144"), Ok(262144));
+		assert_eq!(load("16777216"), Ok(16777216));
+
+		let err = Err(vec![
+			"Invalid piece length (must be a power of two from 16384 to 16777216)",
+		]);
+		// not a power of two
+		assert_eq!(load("20000"), err);
+		// out of range
+		assert_eq!(load("8192"), err);
+		assert_eq!(load("33554432"), err);
+		assert_eq!(load("abc"), err);
+	}
+
+	#[test]
+	fn test_help() {
+		assert_eq!(Config::load_all(args!("--help")), Err(vec!["help"]));
+		assert_eq!(
+			Config::load_all(args!("-n", "true", "-?", "-i", "00")),
+			Err(vec!["help"])
+		);
+
+		let help = help("parrot");
+		assert!(help.starts_with("usage: parrot [options]\n"));
+		assert!(help.contains("  -s, --server-port <port>"));
+	}
+
+	#[test]
+	fn test_load_all() {
+		assert_eq!(
+			Config::load_all(args!()).err(),
+			Some(vec![
+				"Missing command.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
+		);
+		// invalid arguments first, in order, then the missing ones
+		assert_eq!(
+			Config::load_all(args!(
+				"-i",
+				"00",
+				"-s",
+				"port",
+				"--bogus",
+				"-e",
+				"127.0.0.1"
+			))
+			.err(),
+			Some(vec![
+				"Invalid server port (must be a number 0 < port < 65536)",
+				"Unexpected token.",
+				"Missing command.",
+				"Invalid length of info hash."
+			])
+		);
+		assert_eq!(
+			Config::load_all(args!("-s", "port", "--bogus")).err(),
+			Some(vec![
+				"Invalid server port (must be a number 0 < port < 65536)",
+				"Unexpected token.",
+				"Missing command.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
+		);
+		assert_eq!(
+			Config::load_all(args!("--bogus", "--help")).err(),
+			Some(vec!["help"])
+		);
+	}
+
+	#[test]
+	fn test_options_in_sync() {
+		// every flag in the help is one `load_all` knows, and `load_all` rejects any other
+		for (flags, _, _) in OPTIONS {
+			for flag in *flags {
+				assert!(
+					!Config::load_all(args!(*flag))
+						.unwrap_err()
+						.contains(&"Unexpected token."),
+					"{}",
+					flag
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_min_ratio() {
+		let load = |ratio: &str| load_with(&["--min-ratio", ratio]).map(|c| c.min_ratio);
+
+		assert_eq!(load("0.5"), Ok(Some(0.5)));
+		assert_eq!(load("1"), Ok(Some(1.0)));
+
+		let err = Err(vec![
+			"Invalid minimum ratio (must be a non-negative number)",
+		]);
+		assert_eq!(load("-1"), err);
+		assert_eq!(load("NaN"), err);
+		assert_eq!(load("lots"), err);
+	}
+
+	#[test]
+	fn test_paths() {
+		let load = |flag: &str, path: &str| {
+			load_with(&[flag, path]).map(|c| (c.announce_path, c.scrape_path))
+		};
+
+		assert_eq!(
+			load("--announce-path", "/tracker/announce"),
+			Ok(("/tracker/announce".into(), "/scrape".into()))
+		);
+		assert_eq!(
+			load("--scrape-path", "/tracker/scrape"),
+			Ok(("/announce".into(), "/tracker/scrape".into()))
+		);
+		assert_eq!(
+			load("--announce-path", "announce"),
+			Err(vec!["Invalid announce path (must start with /)"])
+		);
+	}
+
+	#[test]
+	fn test_announce_bind() {
+		let load = |ip: &str| load_with(&["--announce-bind", ip]).map(|c| c.announce_bind);
+
+		assert_eq!(load("10.0.0.2"), Ok(Some("10.0.0.2".parse().unwrap())));
+		assert_eq!(load("::1"), Ok(Some("::1".parse().unwrap())));
+		assert_eq!(load("eth0"), Err(vec!["Invalid IP address."]));
+	}
+
+	#[test]
+	fn test_announce_comment() {
+		let config = load_with(&[
+			"--announce",
+			"http://a.example/announce",
+			"--comment",
+			"hello world",
+			"--announce",
+			"http://b.example/announce",
+		])
+		.unwrap();
+
+		assert_eq!(config.comment.as_deref(), Some("hello world"));
+		assert_eq!(
+			config.announce,
+			vec!["http://a.example/announce", "http://b.example/announce"]
+		);
+	}
+
+	#[test]
+	fn test_allow_peer_id() {
+		let load = |extra: &[&str]| load_with(extra).map(|c| c.allowed_peer_ids);
+
+		assert_eq!(load(&[]), Ok(None));
+		assert_eq!(
+			load(&[
+				"--allow-peer-id",
+				"0101010101010101010101010101010101010101",
+				"--allow-peer-id",
+				"ffffffffffffffffffffffffffffffffffffffff"
+			]),
+			Ok(Some(HashSet::from([[1; 20], [0xff; 20]])))
+		);
+		let err = Err(vec!["Invalid peer id (must be 40 hex digits)"]);
+		assert_eq!(load(&["--allow-peer-id", "0101"]), err);
+		assert_eq!(load(&["--allow-peer-id", "not hex"]), err);
+	}
+
+	#[test]
+	fn test_info_hash_encodings() {
+		let load = |info_hash: &str| {
+			Config::load_all(args!("-n", "true", "-e", "127.0.0.1", "-i", info_hash))
+				.map(|c| c.info_hash)
+		};
+		let hash = [
+			0x93, 0x62, 0x07, 0xe0, 0x49, 0xdc, 0x6f, 0x3d, 0x8b, 0xa6, 0x13, 0x38, 0xb6, 0x7c,
+			0xb2, 0x10, 0x07, 0x4c, 0xae, 0x60,
+		];
+
+		assert_eq!(load("936207e049dc6f3d8ba61338b67cb210074cae60"), Ok(hash));
+		assert_eq!(load("SNRAPYCJ3RXT3C5GCM4LM7FSCADUZLTA"), Ok(hash));
+		assert_eq!(load("snrapycj3rxt3c5gcm4lm7fscaduzlta"), Ok(hash));
+
+		assert_eq!(
+			load("936207e049dc6f3d8ba61338b67cb210074cae"),
+			Err(vec!["Invalid length of info hash."])
+		);
+		assert_eq!(
+			load("SNRAPYCJ3RXT3C5GCM4LM7FSCADUZLT1"),
+			Err(vec!["Invalid info hash."])
+		);
+	}
+
+	#[test]
+	fn test_files() {
+		let load = |files: &[&str]| load_with(files).map(|c| c.files);
+
+		assert_eq!(load(&[]), Ok(vec![]));
+		assert_eq!(
+			load(&["-f", "Cargo.toml"]),
+			Ok(vec![PathBuf::from("Cargo.toml")])
+		);
+		assert_eq!(
+			load(&["-f", "Cargo.toml", "--file", "README.md"]),
+			Ok(vec![
+				PathBuf::from("Cargo.toml"),
+				PathBuf::from("README.md")
+			])
+		);
+		assert_eq!(
+			load(&["-f", "Cargo.toml", "-f", "src"]),
+			Err(vec!["Argument is not a file."])
+		);
+	}
+
+	#[test]
+	fn test_stdin_file() {
+		let load = |extra: &[&str]| {
+			Config::load_all(
+				["-n", "true", "-e", "127.0.0.1", "-f", "-"]
+					.iter()
+					.chain(extra)
+					.map(|s| s.to_string()),
+			)
+		};
+
+		let config = load(&["--name", "data.bin"]).unwrap();
+		assert_eq!(config.files, vec![PathBuf::from("-")]);
+		assert_eq!(config.name.as_deref(), Some("data.bin"));
+
+		assert_eq!(load(&[]), Err(vec!["Missing name for file from stdin."]));
+	}
+
 	#[test]
 	fn test_config_from() {
 		assert_eq!(
-			Config::load(
+			Config::load_all(
 				vec![
 					"-n",
 					"ls -la",
@@ -293,72 +1058,135 @@ mod tests {
 				peer_host: PeerHost::INFER,
 				server_port: 3000,
 				peer_port: 16384,
-				file: None,
+				files: vec![],
 				expected_ip: "127.0.0.1".parse().unwrap(),
+				with_md5: false,
+				dry_run: false,
+				piece_length: 16384,
+				no_creation_date: false,
+				comment: None,
+				name: None,
+				announce: vec![],
+				min_announce_interval: 0,
+				max_conns_per_min: 0,
+				combined: false,
+				prefer_seeders: false,
+				allowed_peer_ids: None,
+				response_content_type: "text/plain".into(),
+				advertise_ipv4: None,
+				advertise_ipv6: None,
+				legacy_key_order: false,
+				min_ratio: None,
+				passkeys: None,
+				log_level: LogLevel::Info,
+				max_peers_per_torrent: 1000,
+				require_compact: false,
+				tls: false,
+				require_started_first: false,
+				rng_seed: None,
+				announce_path: "/announce".into(),
+				scrape_path: "/scrape".into(),
+				announce_interval: 300,
+				announce_bind: None,
 			})
 		);
 
-		assert_eq!(Config::load([].into_iter()), Err("Missing command."));
+		assert!(load_with(&["--with-md5"]).unwrap().with_md5);
+
+		assert_eq!(
+			load_with(&["--min-announce-interval", "30"]).map(|c| c.min_announce_interval),
+			Ok(30)
+		);
+
+		assert_eq!(
+			Config::load_all([].into_iter()),
+			Err(vec![
+				"Missing command.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
+		);
 
 		assert_eq!(
-			Config::load(["-n"].into_iter().map(&str::to_string)),
-			Err("Missing value for \"notify\"")
+			Config::load_all(["-n"].into_iter().map(&str::to_string)),
+			Err(vec![
+				"Missing value for \"notify\"",
+				"Missing command.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
 		);
 
 		assert_eq!(
-			Config::load(["-n", ""].into_iter().map(&str::to_string)),
-			Err("Empty action field.")
+			Config::load_all(["-n", ""].into_iter().map(&str::to_string)),
+			Err(vec![
+				"Empty action field.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
 		);
 
 		assert_eq!(
-			Config::load(["-n", "ls -la"].into_iter().map(&str::to_string)),
-			Err("Missing info hash.")
+			Config::load_all(["-n", "ls -la"].into_iter().map(&str::to_string)),
+			Err(vec!["Missing info hash.", "Missing expected ip."])
 		);
 
 		assert_eq!(
-			Config::load(["-n", "ls -la", "-i"].into_iter().map(&str::to_string)),
-			Err("Missing expected argument.")
+			Config::load_all(["-n", "ls -la", "-i"].into_iter().map(&str::to_string)),
+			Err(vec![
+				"Missing expected argument.",
+				"Missing info hash.",
+				"Missing expected ip."
+			])
 		);
 
 		assert_eq!(
-			Config::load(
+			Config::load_all(
 				["-n", "ls -la", "-i", "abc"]
 					.into_iter()
 					.map(&str::to_string)
 			),
-			Err("Invalid length of info hash.")
+			Err(vec!["Invalid length of info hash.", "Missing expected ip."])
 		);
 
 		assert_eq!(
-			Config::load(
+			Config::load_all(
 				["-n", "ls -la", "-i", "####"]
 					.into_iter()
 					.map(&str::to_string)
 			),
-			Err("Invalid info hash.")
+			Err(vec!["Invalid info hash.", "Missing expected ip."])
 		);
 
 		assert_eq!(
-			Config::load(
+			Config::load_all(
 				["-n", "ls -la", "-i", "00000000000000000000000000000000000000000000000000000000000000000000000000000000", "-f", "this file doesn't exist"]
 					.into_iter()
 					.map(&str::to_string)
 			),
-			Err("Argument is not a file.")
+			Err(vec![
+				"Argument is not a file.",
+				"Invalid length of info hash.",
+				"Missing expected ip."
+			])
 		);
 
 		// directory
 		assert_eq!(
-			Config::load(
+			Config::load_all(
 				["-n", "ls -la", "-i", "00000000000000000000000000000000000000000000000000000000000000000000000000000000", "-f", "src"]
 					.into_iter()
 					.map(&str::to_string)
 			),
-			Err("Argument is not a file.")
+			Err(vec![
+				"Argument is not a file.",
+				"Invalid length of info hash.",
+				"Missing expected ip."
+			])
 		);
 
 		assert_eq!(
-			Config::load(args!(
+			Config::load_all(args!(
 				"-n",
 				"true",
 				"-i",
@@ -366,7 +1194,7 @@ mod tests {
 				"-e",
 				"127.3"
 			)),
-			Err("Invalid IP address.")
+			Err(vec!["Invalid IP address."])
 		)
 	}
 }
@@ -0,0 +1,122 @@
+// see https://www.ietf.org/rfc/rfc1321.txt
+
+const S: [u32; 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9,
+	14, 20, 5, 9, 14, 20, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 6, 10, 15,
+	21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+// floor(abs(sin(i + 1)) * 2^32)
+const K: [u32; 64] = [
+	0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+	0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+	0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+	0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+	0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+	0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+	0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+	0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+/// incremental md5 hasher
+#[derive(Clone)]
+pub struct Md5 {
+	state: [u32; 4],
+	buf: Vec<u8>,
+	length: u64,
+}
+
+impl Md5 {
+	pub fn new() -> Self {
+		Self {
+			state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+			buf: Vec::with_capacity(64),
+			length: 0,
+		}
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		self.length = self.length.wrapping_add(data.len() as u64);
+		self.buf.extend_from_slice(data);
+
+		let blocks = self.buf.len() / 64;
+		for i in 0..blocks {
+			let block: [u8; 64] = self.buf[i * 64..(i + 1) * 64].try_into().unwrap();
+			self.process(&block);
+		}
+		self.buf.drain(..blocks * 64);
+	}
+
+	pub fn digest(&self) -> [u8; 16] {
+		let mut hasher = self.clone();
+		let bits = self.length.wrapping_mul(8);
+
+		let mut padding = vec![0x80];
+		padding.resize((119 - self.buf.len()) % 64 + 1, 0);
+		padding.extend_from_slice(&bits.to_le_bytes());
+		hasher.update(&padding);
+
+		let mut out = [0; 16];
+		for (chunk, word) in out.chunks_mut(4).zip(hasher.state) {
+			chunk.copy_from_slice(&word.to_le_bytes());
+		}
+		out
+	}
+
+	fn process(&mut self, block: &[u8; 64]) {
+		let mut m = [0u32; 16];
+		for (word, bytes) in m.iter_mut().zip(block.chunks(4)) {
+			*word = u32::from_le_bytes(bytes.try_into().unwrap());
+		}
+
+		let [mut a, mut b, mut c, mut d] = self.state;
+		for i in 0..64 {
+			let (f, g) = match i {
+				0..=15 => ((b & c) | (!b & d), i),
+				16..=31 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+				32..=47 => (b ^ c ^ d, (3 * i + 5) % 16),
+				_ => (c ^ (b | !d), (7 * i) % 16),
+			};
+			let f = f.wrapping_add(a).wrapping_add(K[i]).wrapping_add(m[g]);
+			a = d;
+			d = c;
+			c = b;
+			b = b.wrapping_add(f.rotate_left(S[i]));
+		}
+
+		for (state, x) in self.state.iter_mut().zip([a, b, c, d]) {
+			*state = state.wrapping_add(x);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Md5;
+	use crate::bytes::BytesExt;
+
+	fn md5_hex(data: &[u8]) -> String {
+		let mut md5 = Md5::new();
+		md5.update(data);
+		md5.digest().to_hex_string()
+	}
+
+	#[test]
+	fn test_md5() {
+		assert_eq!(md5_hex(b""), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(md5_hex(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(
+			md5_hex(b"The quick brown fox jumps over the lazy dog"),
+			"9e107d9d372bb6826bd81d3542a419d6"
+		);
+		// crosses a block boundary
+		assert_eq!(md5_hex(&[b'a'; 100]), "36a92cc94a9e0fa21f625f8bfb007adf");
+
+		// incremental updates match a single update
+		let mut md5 = Md5::new();
+		for chunk in [b'a'; 100].chunks(7) {
+			md5.update(chunk);
+		}
+		assert_eq!(md5.digest().to_hex_string(), md5_hex(&[b'a'; 100]));
+	}
+}
@@ -0,0 +1,7 @@
+mod md5;
+mod piece_hasher;
+mod sha256;
+
+pub use md5::Md5;
+pub use piece_hasher::PieceHasher;
+pub use sha256::Sha256;
@@ -0,0 +1,79 @@
+use sha1_smol::Sha1;
+
+/// Hashes data into the SHA-1 piece hashes of a torrent, however it's split up: a piece is only
+/// hashed once `piece_length` bytes of it have been given, and the last, shorter one on `finish`.
+pub struct PieceHasher {
+	piece_length: usize,
+	piece: Sha1,
+	/// bytes of the current piece given so far
+	filled: usize,
+	pieces: Vec<u8>,
+}
+
+impl PieceHasher {
+	pub fn new(piece_length: usize) -> Self {
+		assert!(piece_length > 0, "Piece length must not be 0.");
+		Self {
+			piece_length,
+			piece: Sha1::new(),
+			filled: 0,
+			pieces: Vec::new(),
+		}
+	}
+
+	pub fn update(&mut self, mut data: &[u8]) {
+		while !data.is_empty() {
+			let (head, rest) = data.split_at(data.len().min(self.piece_length - self.filled));
+			self.piece.update(head);
+			self.filled += head.len();
+			data = rest;
+
+			if self.filled == self.piece_length {
+				self.pieces.extend_from_slice(&self.piece.digest().bytes());
+				self.piece.reset();
+				self.filled = 0;
+			}
+		}
+	}
+
+	/// the hashes of every piece, concatenated (the torrent's `pieces`)
+	pub fn finish(mut self) -> Vec<u8> {
+		if self.filled > 0 {
+			self.pieces.extend_from_slice(&self.piece.digest().bytes());
+		}
+		self.pieces
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use sha1_smol::Sha1;
+
+	use super::PieceHasher;
+
+	#[test]
+	fn test_chunk_boundaries() {
+		let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+		let expected: Vec<u8> = data
+			.chunks(64)
+			.flat_map(|piece| Sha1::from(piece).digest().bytes())
+			.collect();
+
+		for chunk_size in [1, 3, 63, 64, 65, 128, 999, 1000, 4096] {
+			let mut hasher = PieceHasher::new(64);
+			for chunk in data.chunks(chunk_size) {
+				hasher.update(chunk);
+			}
+			assert_eq!(hasher.finish(), expected, "chunks of {}", chunk_size);
+		}
+
+		// empty updates do nothing, and an exact multiple has no partial piece
+		let mut hasher = PieceHasher::new(64);
+		hasher.update(&[]);
+		hasher.update(&data[..128]);
+		hasher.update(&[]);
+		assert_eq!(hasher.finish(), expected[..40]);
+
+		assert_eq!(PieceHasher::new(64).finish(), Vec::<u8>::new());
+	}
+}
@@ -0,0 +1,350 @@
+use std::io;
+
+// see RFC 1952 (gzip) and RFC 1951 (deflate)
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+/// base lengths and extra bits of length symbols 257..=285
+const LENGTH_BASE: [u16; 29] = [
+	3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+	163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+	0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// base distances and extra bits of distance symbols 0..=29
+const DIST_BASE: [u16; 30] = [
+	1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+	2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+	0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+	13,
+];
+/// order code length code lengths are sent in a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [
+	16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid(msg: &'static str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// reads deflate's least-significant-bit-first bit stream
+struct BitReader<'a> {
+	data: &'a [u8],
+	/// position in bits
+	pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+	fn bits(&mut self, n: u8) -> io::Result<u32> {
+		let mut value = 0;
+		for i in 0..n {
+			let byte = self
+				.data
+				.get(self.pos / 8)
+				.ok_or(invalid("Unexpected end of deflate stream."))?;
+			value |= (((byte >> (self.pos % 8)) & 1) as u32) << i;
+			self.pos += 1;
+		}
+		Ok(value)
+	}
+
+	/// skips to the next byte boundary and takes `len` bytes
+	fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+		let start = self.pos.div_ceil(8);
+		let bytes = self
+			.data
+			.get(start..start + len)
+			.ok_or(invalid("Unexpected end of deflate stream."))?;
+		self.pos = (start + len) * 8;
+		Ok(bytes)
+	}
+}
+
+/// canonical huffman code, stored as the number of codes of each length and the symbols in code order
+struct Huffman {
+	counts: [u16; 16],
+	symbols: Vec<u16>,
+}
+
+impl Huffman {
+	fn new(lengths: &[u8]) -> Self {
+		let mut counts = [0; 16];
+		for &len in lengths {
+			counts[len as usize] += 1;
+		}
+		counts[0] = 0;
+
+		let mut symbols = Vec::with_capacity(lengths.len());
+		for len in 1..16 {
+			symbols.extend(
+				(0..lengths.len() as u16).filter(|&symbol| lengths[symbol as usize] == len),
+			);
+		}
+
+		Self { counts, symbols }
+	}
+
+	fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+		// codes of each length are consecutive, starting at `first`
+		let mut code = 0;
+		let mut first = 0;
+		let mut index = 0;
+		for &count in &self.counts[1..] {
+			let count = count as u32;
+			code |= reader.bits(1)?;
+			if code - first < count {
+				return Ok(self.symbols[(index + code - first) as usize]);
+			}
+			index += count;
+			first = (first + count) << 1;
+			code <<= 1;
+		}
+		Err(invalid("Invalid huffman code."))
+	}
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+	let mut lengths = [0; 288];
+	lengths[..144].fill(8);
+	lengths[144..256].fill(9);
+	lengths[256..280].fill(7);
+	lengths[280..].fill(8);
+	(Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+	let literals = reader.bits(5)? as usize + 257;
+	let distances = reader.bits(5)? as usize + 1;
+	let code_lengths = reader.bits(4)? as usize + 4;
+
+	let mut lengths = [0; 19];
+	for &i in &CODE_LENGTH_ORDER[..code_lengths] {
+		lengths[i] = reader.bits(3)? as u8;
+	}
+	let code_length_code = Huffman::new(&lengths);
+
+	let mut lengths = Vec::with_capacity(literals + distances);
+	while lengths.len() < literals + distances {
+		let (len, repeat) = match code_length_code.decode(reader)? {
+			len @ 0..=15 => (len as u8, 1),
+			16 => (
+				*lengths
+					.last()
+					.ok_or(invalid("Repeated code length with no previous length."))?,
+				3 + reader.bits(2)?,
+			),
+			17 => (0, 3 + reader.bits(3)?),
+			_ => (0, 11 + reader.bits(7)?),
+		};
+		lengths.extend((0..repeat).map(|_| len));
+	}
+	if lengths.len() > literals + distances {
+		return Err(invalid("Code lengths overflow the dynamic block header."));
+	}
+
+	Ok((
+		Huffman::new(&lengths[..literals]),
+		Huffman::new(&lengths[literals..]),
+	))
+}
+
+fn too_long() -> io::Error {
+	invalid("Decompressed data is too long.")
+}
+
+fn inflate_block(
+	reader: &mut BitReader,
+	out: &mut Vec<u8>,
+	max_len: usize,
+	(literal, distance): (Huffman, Huffman),
+) -> io::Result<()> {
+	loop {
+		match literal.decode(reader)? {
+			byte @ 0..=255 if out.len() < max_len => out.push(byte as u8),
+			0..=255 => return Err(too_long()),
+			256 => return Ok(()),
+			symbol => {
+				let i = symbol as usize - 257;
+				if i >= LENGTH_BASE.len() {
+					return Err(invalid("Invalid length symbol."));
+				}
+				let len = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i])? as usize;
+
+				let i = distance.decode(reader)? as usize;
+				if i >= DIST_BASE.len() {
+					return Err(invalid("Invalid distance symbol."));
+				}
+				let dist = DIST_BASE[i] as usize + reader.bits(DIST_EXTRA[i])? as usize;
+				if dist > out.len() {
+					return Err(invalid("Distance is before the start of the output."));
+				}
+				if out.len() + len > max_len {
+					return Err(too_long());
+				}
+
+				// the copy can overlap what it's writing, so go byte by byte
+				let start = out.len() - dist;
+				for j in 0..len {
+					out.push(out[start + j]);
+				}
+			}
+		}
+	}
+}
+
+/// decompresses a raw deflate stream, returning the output and the number of bytes read.  errors
+/// if the output would be longer than `max_len`.
+pub fn inflate(data: &[u8], max_len: usize) -> io::Result<(Vec<u8>, usize)> {
+	let mut reader = BitReader { data, pos: 0 };
+	let mut out = Vec::new();
+
+	loop {
+		let last = reader.bits(1)? == 1;
+		match reader.bits(2)? {
+			0 => {
+				let header = reader.bytes(4)?;
+				let len = u16::from_le_bytes([header[0], header[1]]);
+				let nlen = u16::from_le_bytes([header[2], header[3]]);
+				if len != !nlen {
+					return Err(invalid("Stored block length doesn't match its complement."));
+				}
+				if out.len() + len as usize > max_len {
+					return Err(too_long());
+				}
+				out.extend_from_slice(reader.bytes(len as usize)?);
+			}
+			1 => inflate_block(&mut reader, &mut out, max_len, fixed_codes())?,
+			2 => {
+				let codes = dynamic_codes(&mut reader)?;
+				inflate_block(&mut reader, &mut out, max_len, codes)?
+			}
+			_ => return Err(invalid("Invalid deflate block type.")),
+		}
+		if last {
+			return Ok((out, reader.pos.div_ceil(8)));
+		}
+	}
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in data {
+		crc ^= byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 == 1 {
+				(crc >> 1) ^ 0xEDB88320
+			} else {
+				crc >> 1
+			};
+		}
+	}
+	!crc
+}
+
+/// decompresses a single gzip member, checking its crc and length.  errors without decompressing
+/// more than `max_len` bytes, so a small bomb can't use up memory.
+pub fn decompress(data: &[u8], max_len: usize) -> io::Result<Vec<u8>> {
+	let truncated = || invalid("Truncated gzip header.");
+
+	if data.len() < 10 || data[..3] != [0x1f, 0x8b, 8] {
+		return Err(invalid("Not gzip (deflate) data."));
+	}
+	let flags = data[3];
+	// skip mtime, extra flags, os
+	let mut rest = &data[10..];
+
+	if flags & FEXTRA != 0 {
+		let len = match rest {
+			[a, b, ..] => u16::from_le_bytes([*a, *b]) as usize,
+			_ => return Err(truncated()),
+		};
+		rest = rest.get(2 + len..).ok_or_else(truncated)?;
+	}
+	for flag in [FNAME, FCOMMENT] {
+		if flags & flag != 0 {
+			let end = rest.iter().position(|&b| b == 0).ok_or_else(truncated)?;
+			rest = &rest[end + 1..];
+		}
+	}
+	if flags & FHCRC != 0 {
+		rest = rest.get(2..).ok_or_else(truncated)?;
+	}
+
+	let (out, read) = inflate(rest, max_len)?;
+
+	let trailer = rest
+		.get(read..read + 8)
+		.ok_or(invalid("Truncated gzip trailer."))?;
+	let crc = u32::from_le_bytes(trailer[..4].try_into().expect("slice is 4 bytes"));
+	let size = u32::from_le_bytes(trailer[4..].try_into().expect("slice is 4 bytes"));
+
+	if crc != crc32(&out) {
+		return Err(invalid("Gzip checksum mismatch."));
+	}
+	if size != out.len() as u32 {
+		return Err(invalid("Gzip length mismatch."));
+	}
+
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_crc32() {
+		assert_eq!(crc32(b""), 0);
+		assert_eq!(crc32(b"123456789"), 0xCBF43926);
+	}
+
+	#[test]
+	fn test_decompress() {
+		// stored block
+		assert_eq!(
+			decompress(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x03\x00\xfc\xff\x61\x62\x63\xc2\x41\x24\x35\x03\x00\x00\x00", 1024).unwrap(),
+			b"abc"
+		);
+		// fixed huffman codes with back references
+		assert_eq!(
+			decompress(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\x01\xe3\x51\x3d\x8d\x17\x00\x00\x00", 1024).unwrap(),
+			b"hello hello hello hello"
+		);
+		// dynamic huffman codes
+		assert_eq!(
+			decompress(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x1d\x89\x31\x01\x00\x00\x0c\x82\xb2\x82\xfd\x3b\xcc\x29\x5e\x10\xe4\xa7\x64\x40\x6a\xfa\xac\x38\xd6\xe5\x00\x19\x15\x3c\x20\x2d\x00\x00\x00", 1024).unwrap(),
+			b"cabaaaaabbacacacaaacbaabaacabaaabababaaabbaba"
+		);
+		// file name in header
+		assert_eq!(
+			decompress(b"\x1f\x8b\x08\x08\x00\x00\x00\x00\x02\xff\x61\x2e\x74\x78\x74\x00\xcb\x4b\xcc\x4d\x4d\x01\x00\x87\xcc\xe0\x71\x05\x00\x00\x00", 1024).unwrap(),
+			b"named"
+		);
+	}
+
+	#[test]
+	fn test_decompress_err() {
+		// not gzip
+		assert!(decompress(b"d8:intervali900ee", 1024).is_err());
+		// truncated
+		assert!(decompress(
+			b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9",
+			1024
+		)
+		.is_err());
+		// bad checksum
+		assert!(decompress(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x03\x00\xfc\xff\x61\x62\x63\xc2\x41\x24\x36\x03\x00\x00\x00", 1024).is_err());
+		// longer than allowed, as a stored block and with back references
+		assert!(decompress(b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x03\x00\xfc\xff\x61\x62\x63\xc2\x41\x24\x35\x03\x00\x00\x00", 2).is_err());
+		let hello = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x27\x01\xe3\x51\x3d\x8d\x17\x00\x00\x00";
+		assert_eq!(decompress(hello, 23).unwrap().len(), 23);
+		assert!(decompress(hello, 22).is_err());
+		assert!(decompress(hello, 3).is_err());
+	}
+}
@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+/// An HTTP/1.1 response.  `Content-Length` is always sent, from the body, so clients that keep the
+/// connection alive know where the response ends.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Response {
+	pub status: u16,
+	/// headers other than `Content-Length`, in the order they're sent
+	pub headers: Vec<(String, String)>,
+	pub body: Vec<u8>,
+}
+
+/// reason phrase of the statuses we send
+fn reason(status: u16) -> &'static str {
+	match status {
+		200 => "OK",
+		400 => "BAD REQUEST",
+		404 => "NOT FOUND",
+		413 => "PAYLOAD TOO LARGE",
+		500 => "INTERNAL SERVER ERROR",
+		_ => "",
+	}
+}
+
+impl Response {
+	pub fn new(status: u16) -> Self {
+		Self {
+			status,
+			headers: Vec::new(),
+			body: Vec::new(),
+		}
+	}
+
+	pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+		self.headers.push((name.into(), value.into()));
+		self
+	}
+
+	pub fn body(mut self, body: Vec<u8>) -> Self {
+		self.body = body;
+		self
+	}
+
+	/// writes the status line and headers, but not the body, e.g. to answer a HEAD
+	pub fn write_head_to(&self, w: &mut impl Write) -> io::Result<()> {
+		let mut head = format!(
+			"HTTP/1.1 {} {}\r\nContent-Length: {}\r\n",
+			self.status,
+			reason(self.status),
+			self.body.len()
+		);
+		for (name, value) in &self.headers {
+			head.push_str(&format!("{}: {}\r\n", name, value));
+		}
+		head.push_str("\r\n");
+		w.write_all(head.as_bytes())
+	}
+
+	/// writes the whole response.  the body is exactly `Content-Length` long, so nothing follows it.
+	pub fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+		self.write_head_to(w)?;
+		w.write_all(&self.body)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Response;
+
+	fn bytes(response: &Response, head: bool) -> String {
+		let mut out = Vec::new();
+		if head {
+			response.write_head_to(&mut out).unwrap();
+		} else {
+			response.write_to(&mut out).unwrap();
+		}
+		String::from_utf8(out).unwrap()
+	}
+
+	#[test]
+	fn test_write_to() {
+		let ok = Response::new(200)
+			.header("Content-Type", "text/plain")
+			.body(b"d8:intervali300ee".to_vec());
+		assert_eq!(
+			bytes(&ok, false),
+			"HTTP/1.1 200 OK\r\nContent-Length: 17\r\nContent-Type: text/plain\r\n\r\nd8:intervali300ee"
+		);
+		assert_eq!(
+			bytes(&ok, true),
+			"HTTP/1.1 200 OK\r\nContent-Length: 17\r\nContent-Type: text/plain\r\n\r\n"
+		);
+
+		assert_eq!(
+			bytes(&Response::new(400), false),
+			"HTTP/1.1 400 BAD REQUEST\r\nContent-Length: 0\r\n\r\n"
+		);
+	}
+}
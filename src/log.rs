@@ -0,0 +1,135 @@
+use std::{
+	fmt,
+	io::{self, Write},
+	str::FromStr,
+	sync::atomic::{AtomicU8, Ordering},
+};
+
+/// How much is logged: each level includes the ones before it.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum LogLevel {
+	Error,
+	Warn,
+	Info,
+	Debug,
+}
+
+impl LogLevel {
+	fn prefix(self) -> &'static str {
+		match self {
+			Self::Error => "ERROR",
+			Self::Warn => "WARN",
+			Self::Info => "INFO",
+			Self::Debug => "DEBUG",
+		}
+	}
+}
+
+impl FromStr for LogLevel {
+	type Err = &'static str;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"error" => Ok(Self::Error),
+			"warn" => Ok(Self::Warn),
+			"info" => Ok(Self::Info),
+			"debug" => Ok(Self::Debug),
+			_ => Err("Invalid log level (must be error, warn, info, or debug)"),
+		}
+	}
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+pub fn set_level(level: LogLevel) {
+	LEVEL.store(level as u8, Ordering::Relaxed)
+}
+
+pub fn level() -> LogLevel {
+	match LEVEL.load(Ordering::Relaxed) {
+		0 => LogLevel::Error,
+		1 => LogLevel::Warn,
+		2 => LogLevel::Info,
+		_ => LogLevel::Debug,
+	}
+}
+
+/// writes the message to `sink` with its level, if `level` is within `max`.  logging is best
+/// effort, so write errors are ignored.
+pub fn write_log(sink: &mut impl Write, max: LogLevel, level: LogLevel, args: fmt::Arguments) {
+	if level <= max {
+		let _ = writeln!(sink, "[{}] {}", level.prefix(), args);
+	}
+}
+
+/// logs to stderr at the level set with `set_level`.  use the macros instead.
+pub fn log(level: LogLevel, args: fmt::Arguments) {
+	write_log(&mut io::stderr(), self::level(), level, args)
+}
+
+// `#[macro_use]` on the module makes these available to every module declared after it
+
+macro_rules! log {
+	($level: expr, $($arg: tt)*) => {
+		$crate::log::log($level, format_args!($($arg)*))
+	};
+}
+
+macro_rules! error {
+	($($arg: tt)*) => {
+		log!($crate::log::LogLevel::Error, $($arg)*)
+	};
+}
+
+macro_rules! warn {
+	($($arg: tt)*) => {
+		log!($crate::log::LogLevel::Warn, $($arg)*)
+	};
+}
+
+macro_rules! info {
+	($($arg: tt)*) => {
+		log!($crate::log::LogLevel::Info, $($arg)*)
+	};
+}
+
+macro_rules! debug {
+	($($arg: tt)*) => {
+		log!($crate::log::LogLevel::Debug, $($arg)*)
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{write_log, LogLevel};
+
+	#[test]
+	fn test_write_log() {
+		let logged = |max| {
+			let mut sink = Vec::new();
+			write_log(&mut sink, max, LogLevel::Error, format_args!("error {}", 1));
+			write_log(&mut sink, max, LogLevel::Warn, format_args!("warn"));
+			write_log(&mut sink, max, LogLevel::Info, format_args!("info"));
+			write_log(&mut sink, max, LogLevel::Debug, format_args!("debug"));
+			String::from_utf8(sink).unwrap()
+		};
+
+		assert_eq!(logged(LogLevel::Error), "[ERROR] error 1\n");
+		assert_eq!(logged(LogLevel::Warn), "[ERROR] error 1\n[WARN] warn\n");
+		assert_eq!(
+			logged(LogLevel::Info),
+			"[ERROR] error 1\n[WARN] warn\n[INFO] info\n"
+		);
+		assert_eq!(
+			logged(LogLevel::Debug),
+			"[ERROR] error 1\n[WARN] warn\n[INFO] info\n[DEBUG] debug\n"
+		);
+	}
+
+	#[test]
+	fn test_parse() {
+		assert_eq!("warn".parse(), Ok(LogLevel::Warn));
+		assert_eq!("debug".parse(), Ok(LogLevel::Debug));
+		assert!("loud".parse::<LogLevel>().is_err());
+	}
+}
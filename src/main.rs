@@ -1,29 +1,49 @@
 use std::{
 	fs::{self, File},
-	io::{self, Read, Write},
+	io::{self, BufWriter, Read, Write},
 	net::SocketAddr,
-	sync::mpsc,
+	path::{Path, PathBuf},
+	process,
+	sync::{
+		mpsc::{self, RecvTimeoutError},
+		Arc,
+	},
 	thread,
+	time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use config::Config;
 use metainfo::MetaInfo;
 use sha1_smol::Sha1;
-use tracker::Server;
+use tracker::{Combined, Server};
 
 use crate::{
 	bytes::BytesExt,
+	digest::{Md5, PieceHasher},
 	metainfo::{FileInfo, Info},
 };
 
 mod bencode;
 mod bytes;
 mod config;
+mod digest;
+mod gzip;
+mod http;
+#[macro_use]
+mod log;
 mod metainfo;
 mod peer;
+mod pruned_map;
+mod rate_limit;
+mod rng;
+mod socket;
 mod test;
 mod tracker;
 
+/// longest a connection is handled for before it's given up on, also used as the read timeout of
+/// its socket
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub trait Handler {
 	type Ok;
 	fn handle_connection(
@@ -32,95 +52,385 @@ pub trait Handler {
 		remote: SocketAddr,
 		stream: impl Read + Write,
 	) -> io::Result<Self::Ok>;
+
+	/// `handle_connection` on another thread, failing with `TimedOut` if it takes longer than
+	/// `timeout`.  the thread can't be stopped, so it keeps running (and holds the stream) until the
+	/// handler returns: give sockets a read timeout to make sure it does.
+	fn handle_connection_timed<S>(
+		self: Arc<Self>,
+		local: SocketAddr,
+		remote: SocketAddr,
+		stream: S,
+		timeout: Duration,
+	) -> io::Result<Self::Ok>
+	where
+		Self: Sized + Send + Sync + 'static,
+		Self::Ok: Send + 'static,
+		S: Read + Write + Send + 'static,
+	{
+		let (sx, rx) = mpsc::channel();
+		thread::spawn(move || {
+			// the receiver is gone if we timed out, and then nobody wants the result
+			let _ = sx.send(self.handle_connection(local, remote, stream));
+		});
+		match rx.recv_timeout(timeout) {
+			Ok(result) => result,
+			Err(RecvTimeoutError::Timeout) => Err(io::Error::new(
+				io::ErrorKind::TimedOut,
+				"Handling connection timed out.",
+			)),
+			Err(RecvTimeoutError::Disconnected) => {
+				Err(io::Error::other("Connection handler panicked."))
+			}
+		}
+	}
 }
 
-fn generate_torrent(config: &Config) -> io::Result<[u8; 20]> {
-	const PIECE_LENGTH: usize = 16384;
+/// reads `reader` to the end in `piece_length` pieces, returning its length, the concatenated SHA-1
+/// hashes of the pieces, and its md5sum if asked for
+fn hash_pieces(
+	mut reader: impl Read,
+	piece_length: usize,
+	with_md5: bool,
+) -> io::Result<(u64, Vec<u8>, Option<[u8; 32]>)> {
+	let mut length = 0;
+	let mut pieces = PieceHasher::new(piece_length);
+	let mut md5 = with_md5.then(Md5::new);
 
-	let path = match &config.file {
-		Some(p) => p,
-		None => return Err(io::Error::new(io::ErrorKind::NotFound, "No file in config")),
-	};
+	// pipes return short reads, which the hasher joins back into whole pieces
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let len = match reader.read(&mut buf) {
+			Ok(0) => break,
+			Ok(len) => len,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		};
+		length += len as u64;
+		pieces.update(&buf[..len]);
+		if let Some(md5) = &mut md5 {
+			md5.update(&buf[..len]);
+		}
+	}
+	let pieces = pieces.finish();
 
-	let mut file = File::open(path)?;
+	let md5sum = md5.map(|md5| {
+		md5.digest()
+			.to_hex_string()
+			.into_bytes()
+			.try_into()
+			.expect("md5 hex digest is 32 characters")
+	});
 
-	let mut length = 0;
-	let mut pieces = Vec::new();
+	Ok((length, pieces, md5sum))
+}
 
-	loop {
-		let mut piece = [0; PIECE_LENGTH];
-		let len = file.read(&mut piece)?;
-		if len == 0 {
-			break;
+/// reads `reader` in pieces and checks each against the torrent's piece hashes.  multi-file
+/// torrents are checked against their files concatenated in order.  pieces past the end of the data
+/// fail.
+fn verify_file(metainfo: &MetaInfo, mut reader: impl Read) -> io::Result<Vec<bool>> {
+	let piece_length = metainfo.info.piece_length;
+
+	metainfo
+		.info
+		.piece_hashes()
+		.map(|hash| {
+			let mut piece = Vec::new();
+			reader.by_ref().take(piece_length).read_to_end(&mut piece)?;
+			Ok(!piece.is_empty() && Sha1::from(&piece).digest().bytes() == hash)
+		})
+		.collect()
+}
+
+/// checks the files of a multi-file torrent in the folder `root`.  each file is read up to its
+/// length in the torrent, and the pieces of missing ones fail.  errors on paths with empty, `.` or
+/// `..` parts, which could point outside `root`.
+fn verify_files(
+	metainfo: &MetaInfo,
+	files: &[metainfo::File],
+	root: &Path,
+) -> io::Result<Vec<bool>> {
+	let mut reader: Box<dyn Read> = Box::new(io::empty());
+	let mut missing = Vec::new();
+	for file in files {
+		let invalid =
+			|| io::Error::new(io::ErrorKind::InvalidData, "Invalid file path in torrent.");
+		if file.path.is_empty() {
+			return Err(invalid());
 		}
-		length += len as u64;
-		pieces.extend_from_slice(&Sha1::from(&piece[..len]).digest().bytes());
-		if len < PIECE_LENGTH {
-			break;
+		let path = file
+			.path
+			.iter()
+			.try_fold(root.to_path_buf(), |path, part| {
+				path_part(part)
+					.map(|part| path.join(part))
+					.ok_or_else(invalid)
+			})?;
+		// stand in for a missing file so the ones after it still line up
+		let data: Box<dyn Read> = match File::open(&path) {
+			Ok(data) => Box::new(data.take(file.length)),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				warn!("Missing {}", path.display());
+				missing.push(file.path.iter().map(Vec::as_slice).collect::<Vec<_>>());
+				Box::new(io::repeat(0).take(file.length))
+			}
+			Err(e) => return Err(e),
+		};
+		reader = Box::new(reader.chain(data));
+	}
+
+	let mut results = verify_file(metainfo, reader)?;
+	for path in missing {
+		// only `None` if the file lengths overflow, since the path is one of the torrent's
+		let pieces = metainfo.info.pieces_for_path(&path).ok_or_else(|| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				"Invalid file lengths in torrent.",
+			)
+		})?;
+		for ok in results.iter_mut().take(pieces.end).skip(pieces.start) {
+			*ok = false;
 		}
 	}
+	Ok(results)
+}
+
+/// `parrot verify <torrent> [path]`: checks the data at `path` (by default, the torrent's name in the
+/// working directory) against `torrent`
+fn verify(mut args: impl Iterator<Item = String>) -> io::Result<bool> {
+	let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+	let torrent = args
+		.next()
+		.ok_or_else(|| invalid("Usage: parrot verify <torrent> [path]"))?;
+	let metainfo = MetaInfo::from_bytes_auto(&fs::read(torrent)?)?;
+
+	let path = match args.next() {
+		Some(path) => path.into(),
+		None => match &metainfo.info.file_info {
+			FileInfo::Single { name, .. }
+			| FileInfo::Multi { name, .. }
+			| FileInfo::V2 { name, .. } => {
+				PathBuf::from(path_part(name).ok_or_else(|| invalid("Invalid name in torrent."))?)
+			}
+		},
+	};
+
+	let mut md5_ok = true;
+	let results = match &metainfo.info.file_info {
+		FileInfo::Single { md5sum, .. } => {
+			if md5sum.is_some() {
+				md5_ok = metainfo.info.file_info.verify_md5(File::open(&path)?)?;
+				println!("md5sum {}", if md5_ok { "ok" } else { "failed" });
+			}
+			verify_file(&metainfo, File::open(path)?)?
+		}
+		FileInfo::Multi { files, .. } => verify_files(&metainfo, files, &path)?,
+		FileInfo::V2 { .. } => {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				"Verifying v2 torrents is unsupported.",
+			))
+		}
+	};
+
+	for (i, _) in results.iter().enumerate().filter(|(_, ok)| !**ok) {
+		println!("Piece {} failed", i);
+	}
+	let passed = results.iter().filter(|ok| **ok).count();
+	println!("{}/{} pieces ok", passed, results.len());
+
+	Ok(md5_ok && passed == results.len())
+}
 
-	let info = Info {
-		piece_length: PIECE_LENGTH as u64,
+/// builds the info dictionary of `path`, or of stdin if it is `-`
+fn create_info(path: &Path, config: &Config) -> io::Result<Info> {
+	let piece_length = config.piece_length as usize;
+
+	let (length, pieces, md5sum) = if path == Path::new("-") {
+		hash_pieces(io::stdin().lock(), piece_length, config.with_md5)?
+	} else {
+		hash_pieces(File::open(path)?, piece_length, config.with_md5)?
+	};
+
+	let name = match &config.name {
+		Some(name) => name.clone(),
+		None => path
+			.file_name()
+			.ok_or(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Path has no file name",
+			))?
+			.to_string_lossy()
+			.into_owned(),
+	};
+
+	Ok(Info {
+		piece_length: piece_length as u64,
 		pieces,
 		private: Some(true),
+		meta_version: None,
 		file_info: FileInfo::Single {
-			name: path
-				.file_name()
-				.ok_or(io::Error::new(
-					io::ErrorKind::InvalidInput,
-					"Path has no file name",
-				))?
-				.to_string_lossy()
-				.bytes()
-				.collect(),
+			name: name.into_bytes(),
 			length,
-			md5sum: None,
+			md5sum,
 		},
+	})
+}
+
+/// leaves out the port of an http or https url when it's the default for the scheme
+fn normalize_url(url: &str) -> String {
+	for (scheme, port) in [("http://", ":80"), ("https://", ":443")] {
+		if let Some(rest) = url.strip_prefix(scheme) {
+			let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+			if let Some(host) = authority.strip_suffix(port) {
+				return format!("{}{}{}", scheme, host, path);
+			}
+		}
+	}
+	url.to_string()
+}
+
+/// the announce url of the tracker, from `--tls`, `host`, `server_port` and `announce_path`
+fn announce_url(config: &Config) -> String {
+	let scheme = if config.tls { "https" } else { "http" };
+	normalize_url(&format!(
+		"{}://{}:{}{}",
+		scheme, config.host, config.server_port, config.announce_path
+	))
+}
+
+fn generate_torrent(config: &Config, path: &Path) -> io::Result<MetaInfo> {
+	let info = create_info(path, config)?;
+
+	let creation_date = if config.no_creation_date {
+		None
+	} else {
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.ok()
+			.map(|d| d.as_secs())
+	};
+
+	let announce = match config.announce.first() {
+		Some(url) => normalize_url(url),
+		None => announce_url(config),
 	};
 
-	let info_hash = Sha1::from(bencode::encode(info.clone())).digest().bytes();
+	// every url gets its own tier, tried in the order given
+	let announce_list = (config.announce.len() > 1).then(|| {
+		config
+			.announce
+			.iter()
+			.map(|url| vec![normalize_url(url).into_bytes()])
+			.collect()
+	});
+
+	let mut meta_info = MetaInfo::new(info, announce.into_bytes());
+	meta_info.announce_list = announce_list;
+	meta_info.comment = config.comment.clone().map(String::into_bytes);
+	meta_info.created_by = Some(format!("parrot {}", env!("CARGO_PKG_VERSION")).into_bytes());
+	meta_info.creation_date = creation_date;
+	Ok(meta_info)
+}
 
-	let meta_info = MetaInfo {
-		announce: format!("http://{}:{}/announce", config.host, config.server_port).into_bytes(),
-		announce_list: None,
-		comment: None,
-		created_by: None,
-		creation_date: None,
-		encoding: None,
-		info: info.clone(),
+/// writes `<file stem>.torrent` of `path` to the working directory, or with `--dry-run` only prints
+/// its size
+fn save_torrent(config: &Config, path: &Path, meta_info: MetaInfo) -> io::Result<()> {
+	let data: bencode::Data = meta_info.into();
+
+	if config.dry_run {
+		println!(
+			"Metainfo size: {} bytes (dry run, not written)",
+			data.encoded_len()
+		);
+		return Ok(());
+	}
+
+	// stdin has no name of its own
+	let path = match &config.name {
+		Some(name) if path == Path::new("-") => Path::new(name),
+		_ => path,
 	};
+	let stem = path
+		.file_stem()
+		.unwrap_or(path.file_name().expect("Path has no file name."))
+		.to_string_lossy();
+	let mut file = BufWriter::new(File::create(format!(
+		"{}.torrent",
+		sanitize_filename(&stem)
+	))?);
+	bencode::encode_to_writer(data, &mut file)?;
+	file.flush()
+}
+
+/// removes path separators and control characters, so the name stays in the working directory
+fn sanitize_filename(name: &str) -> String {
+	name.chars()
+		.filter(|&c| c != '/' && c != '\\' && !c.is_control())
+		.collect()
+}
 
-	fs::write(
-		format!(
-			"{}.torrent",
-			path.file_stem()
-				.unwrap_or(path.file_name().expect("Path has no file name."))
-				.to_string_lossy()
-		),
-		bencode::encode(meta_info),
-	)?;
-	Ok(info_hash)
+/// a file or folder name from a torrent as one part of a path, or `None` if it's empty, `.` or `..`,
+/// which would point outside the folder it's in
+fn path_part(name: &[u8]) -> Option<String> {
+	match sanitize_filename(&String::from_utf8_lossy(name)) {
+		part if part.is_empty() || part == "." || part == ".." => None,
+		part => Some(part),
+	}
 }
 
 fn main() {
+	if std::env::args().nth(1).as_deref() == Some("verify") {
+		match verify(std::env::args().skip(2)) {
+			Ok(true) => return,
+			Ok(false) => process::exit(1),
+			Err(e) => {
+				eprintln!("Error verifying torrent: {}", e);
+				process::exit(1)
+			}
+		}
+	}
+
 	let mut config = Config::load_or_exit();
-	if config.file.is_some() {
-		config.info_hash = generate_torrent(&config).expect("Error generating torrent.");
-		println!("Info Hash: {}", config.info_hash.to_hex_string())
+	log::set_level(config.log_level);
+	// (info hash, private) of each torrent
+	let mut torrents = Vec::new();
+	for path in &config.files {
+		let meta_info = generate_torrent(&config, path).expect("Error generating torrent.");
+		let info_hash = meta_info.info_hash();
+		let private = meta_info.info.is_private();
+		let magnet = meta_info.to_magnet();
+		save_torrent(&config, path, meta_info).expect("Error saving torrent.");
+		println!("Info Hash: {}", info_hash.to_hex_string());
+		println!("Magnet: {}", magnet);
+		torrents.push((info_hash, private));
+	}
+	if let Some(&(first, _)) = torrents.first() {
+		config.info_hash = first;
+		if config.dry_run {
+			return;
+		}
 	}
 	let (sender, reciever) = mpsc::channel();
 
-	let server = Server {
-		config: config.clone(),
-		sender,
-	};
-
-	thread::spawn(move || server.listen().unwrap());
+	if config.combined {
+		let combined = Combined::new(config.clone(), sender);
+		for &(info_hash, private) in &torrents {
+			combined.server.add_torrent(info_hash, None, private);
+		}
+		thread::spawn(move || Arc::new(combined).listen().unwrap());
+	} else {
+		let server = Server::new(config.clone(), sender);
+		for &(info_hash, private) in &torrents {
+			server.add_torrent(info_hash, None, private);
+		}
+		thread::spawn(move || Arc::new(server).listen().unwrap());
+	}
 
 	for addr in reciever {
 		if addr.ip() != config.expected_ip {
-			println!(
+			warn!(
 				"Unexpected IP {:?} (expected {:?})",
 				addr.ip(),
 				&config.expected_ip
@@ -131,7 +441,7 @@ fn main() {
 					thread::spawn(move || match c.wait() {
 						Ok(code) => {
 							if !code.success() {
-								eprintln!(
+								error!(
 									"{} exited with exit code {} (ip {})",
 									notify,
 									code.code().unwrap_or(-1),
@@ -140,12 +450,12 @@ fn main() {
 							}
 						}
 						Err(e) => {
-							eprintln!("Error running {} with ip {}: {}", notify, addr.ip(), e)
+							error!("Error running {} with ip {}: {}", notify, addr.ip(), e)
 						}
 					});
 				}
 				Err(e) => {
-					eprintln!(
+					error!(
 						"Error running {:?} with ip {}: {}",
 						config.notify,
 						addr.ip(),
@@ -156,3 +466,414 @@ fn main() {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		fs,
+		io::{self, Read, Write},
+		net::SocketAddr,
+		ops::Deref,
+		path::{Path, PathBuf},
+		sync::Arc,
+		thread,
+		time::Duration,
+	};
+
+	use sha1_smol::Sha1;
+
+	use super::{
+		create_info, generate_torrent, hash_pieces, normalize_url, path_part, sanitize_filename,
+		save_torrent, verify_file, verify_files,
+	};
+	use crate::{
+		config::Config,
+		metainfo::{self, FileInfo, Info, MetaInfo},
+		test::MockStream,
+		Handler,
+	};
+
+	/// `parrot-<pid>-<name>` in the temp dir, removed (with anything in it) when dropped, even if
+	/// the test fails
+	struct TempPath(PathBuf);
+
+	impl TempPath {
+		fn new(name: &str) -> Self {
+			Self(std::env::temp_dir().join(format!("parrot-{}-{}", std::process::id(), name)))
+		}
+	}
+
+	impl Deref for TempPath {
+		type Target = Path;
+
+		fn deref(&self) -> &Path {
+			&self.0
+		}
+	}
+
+	impl Drop for TempPath {
+		fn drop(&mut self) {
+			let _ = fs::remove_file(&self.0).or_else(|_| fs::remove_dir_all(&self.0));
+		}
+	}
+
+	#[test]
+	fn test_create_info_md5() {
+		let path = TempPath::new("md5.txt");
+		fs::write(&*path, "The quick brown fox jumps over the lazy dog").unwrap();
+
+		let mut config = Config::default();
+		let without = create_info(&path, &config).unwrap();
+		config.with_md5 = true;
+		let with = create_info(&path, &config).unwrap();
+
+		assert!(matches!(
+			without.file_info,
+			FileInfo::Single { md5sum: None, .. }
+		));
+		assert_eq!(
+			with.file_info,
+			FileInfo::Single {
+				length: 43,
+				md5sum: Some(*b"9e107d9d372bb6826bd81d3542a419d6"),
+				name: path
+					.file_name()
+					.unwrap()
+					.to_string_lossy()
+					.into_owned()
+					.into(),
+			}
+		);
+		assert_eq!(with.pieces, without.pieces);
+		assert!(with.is_private());
+	}
+
+	#[test]
+	fn test_provenance() {
+		let path = TempPath::new("date.txt");
+		fs::write(&*path, "date").unwrap();
+
+		let mut config = Config::default();
+		let dated = generate_torrent(&config, &path).unwrap();
+		config.no_creation_date = true;
+		let undated = generate_torrent(&config, &path).unwrap();
+
+		assert_eq!(
+			dated.created_by,
+			Some(format!("parrot {}", env!("CARGO_PKG_VERSION")).into_bytes())
+		);
+		// some time after this was written
+		assert!(dated.creation_date.unwrap() > 1_700_000_000);
+
+		assert_eq!(undated.created_by, dated.created_by);
+		assert_eq!(undated.creation_date, None);
+	}
+
+	#[test]
+	fn test_announce_comment() {
+		let path = TempPath::new("announce.txt");
+		fs::write(&*path, "announce").unwrap();
+
+		let mut config = Config::default();
+		let default = generate_torrent(&config, &path).unwrap();
+
+		config.comment = Some("a comment".into());
+		config.announce = vec!["http://a.example/announce".into()];
+		let single = generate_torrent(&config, &path).unwrap();
+
+		config.announce.push("udp://b.example:80".into());
+		let multiple = generate_torrent(&config, &path).unwrap();
+
+		assert_eq!(default.announce, b"http://127.0.0.1:3000/announce");
+		assert_eq!(default.announce_list, None);
+		assert_eq!(default.comment, None);
+
+		assert_eq!(single.announce, b"http://a.example/announce");
+		assert_eq!(single.announce_list, None);
+		assert_eq!(single.comment, Some("a comment".into()));
+
+		assert_eq!(multiple.announce, b"http://a.example/announce");
+		assert_eq!(
+			multiple.announce_list,
+			Some(vec![
+				vec!["http://a.example/announce".into()],
+				vec!["udp://b.example:80".into()]
+			])
+		);
+	}
+
+	#[test]
+	fn test_announce_url() {
+		let path = TempPath::new("url.txt");
+		fs::write(&*path, "url").unwrap();
+		let announce = |config: Config| generate_torrent(&config, &path).unwrap().announce;
+
+		assert_eq!(
+			announce(Config {
+				host: "tracker.example".into(),
+				server_port: 80,
+				..Config::default()
+			}),
+			b"http://tracker.example/announce"
+		);
+		assert_eq!(
+			announce(Config {
+				host: "tracker.example".into(),
+				server_port: 8080,
+				..Config::default()
+			}),
+			b"http://tracker.example:8080/announce"
+		);
+		assert_eq!(
+			announce(Config {
+				host: "tracker.example".into(),
+				server_port: 443,
+				tls: true,
+				..Config::default()
+			}),
+			b"https://tracker.example/announce"
+		);
+		assert_eq!(
+			announce(Config {
+				host: "tracker.example".into(),
+				server_port: 80,
+				tls: true,
+				..Config::default()
+			}),
+			b"https://tracker.example:80/announce"
+		);
+		assert_eq!(
+			announce(Config {
+				announce: vec!["https://a.example:443/announce".into()],
+				..Config::default()
+			}),
+			b"https://a.example/announce"
+		);
+
+		assert_eq!(normalize_url("http://a.example:80"), "http://a.example");
+		assert_eq!(
+			normalize_url("http://a.example:8080/announce"),
+			"http://a.example:8080/announce"
+		);
+		assert_eq!(
+			normalize_url("udp://a.example:80/announce"),
+			"udp://a.example:80/announce"
+		);
+	}
+
+	#[test]
+	fn test_dry_run() {
+		let path = TempPath::new("dry-run.txt");
+		fs::write(&*path, "dry").unwrap();
+
+		let config = Config {
+			dry_run: true,
+			..Config::default()
+		};
+
+		let meta_info = generate_torrent(&config, &path).unwrap();
+		save_torrent(&config, &path, meta_info).unwrap();
+
+		let stem = path.file_stem().unwrap().to_string_lossy();
+		assert!(!Path::new(&format!("{}.torrent", stem)).exists());
+	}
+
+	#[test]
+	fn test_sanitize_filename() {
+		assert_eq!(sanitize_filename("file"), "file");
+		assert_eq!(sanitize_filename("../../etc/passwd"), "....etcpasswd");
+		assert_eq!(sanitize_filename("C:\\Windows\\file"), "C:Windowsfile");
+		assert_eq!(sanitize_filename("bad\x00\x1b[31mname\n"), "bad[31mname");
+		assert_eq!(sanitize_filename("ünïcode name"), "ünïcode name");
+	}
+
+	#[test]
+	fn test_path_part() {
+		assert_eq!(path_part(b"file"), Some("file".into()));
+		assert_eq!(path_part(b"../file"), Some("..file".into()));
+		assert_eq!(path_part(b".."), None);
+		assert_eq!(path_part(b"."), None);
+		assert_eq!(path_part(b"/"), None);
+		assert_eq!(path_part(b""), None);
+	}
+
+	#[test]
+	fn test_hash_pieces() {
+		let data = [b'a'; 16384 + 3];
+		let (length, pieces, md5sum) = hash_pieces(&data[..], 16384, false).unwrap();
+		assert_eq!(length, 16387);
+		assert_eq!(
+			pieces,
+			[
+				Sha1::from(&data[..16384]).digest().bytes(),
+				Sha1::from(b"aaa").digest().bytes()
+			]
+			.concat()
+		);
+		assert_eq!(md5sum, None);
+
+		// a short read (like from a pipe) doesn't end the piece
+		let (length, pieces, md5sum) = hash_pieces(b"abc".chain(&b"def"[..]), 16384, true).unwrap();
+		assert_eq!(length, 6);
+		assert_eq!(pieces, Sha1::from(b"abcdef").digest().bytes());
+		assert_eq!(md5sum, Some(*b"e80b5017098950fc58aad83c8c14978e"));
+
+		assert_eq!(
+			hash_pieces(&b""[..], 16384, false).unwrap(),
+			(0, vec![], None)
+		);
+	}
+
+	#[test]
+	fn test_verify_file() {
+		let data: Vec<u8> = (0..40000).map(|i| i as u8).collect();
+		let (length, pieces, _) = hash_pieces(&data[..], 16384, false).unwrap();
+		let metainfo = MetaInfo::new(
+			Info {
+				piece_length: 16384,
+				pieces,
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Single {
+					length,
+					md5sum: None,
+					name: "data".into(),
+				},
+			},
+			"".into(),
+		);
+
+		assert_eq!(verify_file(&metainfo, &data[..]).unwrap(), vec![true; 3]);
+
+		let mut corrupted = data.clone();
+		corrupted[20000] ^= 1;
+		assert_eq!(
+			verify_file(&metainfo, &corrupted[..]).unwrap(),
+			vec![true, false, true]
+		);
+
+		assert_eq!(
+			verify_file(&metainfo, &data[..30000]).unwrap(),
+			vec![true, false, false]
+		);
+
+		// multi-file torrents are hashed across file boundaries
+		assert_eq!(
+			verify_file(&metainfo, data[..100].chain(&data[100..])).unwrap(),
+			vec![true; 3]
+		);
+	}
+
+	#[test]
+	fn test_verify_files() {
+		let root = TempPath::new("verify");
+		fs::create_dir_all(&*root).unwrap();
+		let file = |name: &str, length| metainfo::File {
+			length,
+			md5sum: None,
+			path: vec![name.into()],
+		};
+		let files = vec![file("a", 6), file("b", 4), file("c", 2)];
+		let metainfo = MetaInfo::new(
+			Info {
+				piece_length: 4,
+				pieces: ["abcd", "efgh", "ijkl"]
+					.map(|piece| Sha1::from(piece).digest().bytes())
+					.concat(),
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Multi {
+					name: "folder".into(),
+					files: files.clone(),
+				},
+			},
+			"".into(),
+		);
+		let verify = || verify_files(&metainfo, &files, &root).unwrap();
+
+		fs::write(root.join("a"), "abcdef").unwrap();
+		fs::write(root.join("b"), "ghij").unwrap();
+		fs::write(root.join("c"), "kl").unwrap();
+		assert_eq!(verify(), vec![true; 3]);
+
+		// only the file's length is read
+		fs::write(root.join("b"), "ghijXX").unwrap();
+		assert_eq!(verify(), vec![true; 3]);
+
+		fs::remove_file(root.join("a")).unwrap();
+		assert_eq!(verify(), vec![false, false, true]);
+
+		// nothing outside the folder is read
+		for path in [vec!["..", "a"], vec!["."], vec![""], vec![]] {
+			let files = vec![metainfo::File {
+				length: 4,
+				md5sum: None,
+				path: path.into_iter().map(Vec::from).collect(),
+			}];
+			assert_eq!(
+				verify_files(&metainfo, &files, &root).unwrap_err().kind(),
+				io::ErrorKind::InvalidData
+			);
+		}
+
+		// a missing file past a u64 of data has no pieces to fail
+		let files = vec![file("a", u64::MAX), file("d", 1)];
+		let metainfo = MetaInfo::new(
+			Info {
+				file_info: FileInfo::Multi {
+					name: "folder".into(),
+					files: files.clone(),
+				},
+				..metainfo.info
+			},
+			"".into(),
+		);
+		assert_eq!(
+			verify_files(&metainfo, &files, &root).unwrap_err().kind(),
+			io::ErrorKind::InvalidData
+		);
+	}
+
+	/// waits before answering
+	struct Slow(Duration);
+
+	impl Handler for Slow {
+		type Ok = ();
+
+		fn handle_connection(
+			&self,
+			_: SocketAddr,
+			_: SocketAddr,
+			mut stream: impl Read + Write,
+		) -> io::Result<Self::Ok> {
+			thread::sleep(self.0);
+			stream.write_all(b"done")
+		}
+	}
+
+	#[test]
+	fn test_handle_connection_timed() {
+		let addr: SocketAddr = "127.0.0.1:3000".parse().unwrap();
+
+		let slow = Arc::new(Slow(Duration::from_secs(5)));
+		let err = slow
+			.handle_connection_timed(
+				addr,
+				addr,
+				MockStream::create(vec![]),
+				Duration::from_millis(10),
+			)
+			.unwrap_err();
+		assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+		let fast = Arc::new(Slow(Duration::ZERO));
+		assert!(fast
+			.handle_connection_timed(
+				addr,
+				addr,
+				MockStream::create(vec![]),
+				Duration::from_secs(5)
+			)
+			.is_ok());
+	}
+}
@@ -0,0 +1,149 @@
+use super::{FileInfo, MetaInfo};
+
+/// A field two metainfos don't agree on, from `MetaInfo::diff`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FieldDiff {
+	Announce,
+	AnnounceList,
+	CreationDate,
+	Comment,
+	CreatedBy,
+	Encoding,
+	PieceLength,
+	Private,
+	MetaVersion,
+	Name,
+	/// the files' lengths, paths or md5sums, or whether it's single or multi-file
+	FileLayout,
+	/// the hash of the piece at this index, or only one of them has it
+	Piece(usize),
+}
+
+/// the file info without its name, which is compared on its own
+fn layout(file_info: &FileInfo) -> FileInfo {
+	let mut file_info = file_info.clone();
+	match &mut file_info {
+		FileInfo::Single { name, .. }
+		| FileInfo::Multi { name, .. }
+		| FileInfo::V2 { name, .. } => name.clear(),
+	}
+	file_info
+}
+
+impl MetaInfo {
+	/// the fields that differ from `other`, top-level ones first, then the info dictionary's in
+	/// key order, then each differing piece.  empty if the torrents are the same.
+	#[allow(dead_code)]
+	pub fn diff(&self, other: &MetaInfo) -> Vec<FieldDiff> {
+		let (info, other_info) = (&self.info, &other.info);
+		let mut diffs: Vec<_> = [
+			(self.announce == other.announce, FieldDiff::Announce),
+			(
+				self.announce_list == other.announce_list,
+				FieldDiff::AnnounceList,
+			),
+			(
+				self.creation_date == other.creation_date,
+				FieldDiff::CreationDate,
+			),
+			(self.comment == other.comment, FieldDiff::Comment),
+			(self.created_by == other.created_by, FieldDiff::CreatedBy),
+			(self.encoding == other.encoding, FieldDiff::Encoding),
+			(
+				layout(&info.file_info) == layout(&other_info.file_info),
+				FieldDiff::FileLayout,
+			),
+			(
+				info.meta_version == other_info.meta_version,
+				FieldDiff::MetaVersion,
+			),
+			(
+				info.file_info.name() == other_info.file_info.name(),
+				FieldDiff::Name,
+			),
+			(
+				info.piece_length == other_info.piece_length,
+				FieldDiff::PieceLength,
+			),
+			(info.private == other_info.private, FieldDiff::Private),
+		]
+		.into_iter()
+		.filter(|(same, _)| !same)
+		.map(|(_, diff)| diff)
+		.collect();
+
+		let hashes: Vec<_> = info.piece_hashes().collect();
+		let other_hashes: Vec<_> = other_info.piece_hashes().collect();
+		diffs.extend(
+			(0..hashes.len().max(other_hashes.len()))
+				.filter(|&i| hashes.get(i) != other_hashes.get(i))
+				.map(FieldDiff::Piece),
+		);
+		diffs
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::FieldDiff;
+	use crate::metainfo::{File, FileInfo, Info, MetaInfo};
+
+	fn metainfo() -> MetaInfo {
+		let mut metainfo = MetaInfo::new(
+			Info {
+				piece_length: 16384,
+				pieces: [[b'a'; 20], [b'b'; 20], [b'c'; 20]].concat(),
+				private: Some(true),
+				meta_version: None,
+				file_info: FileInfo::Multi {
+					name: "folder".into(),
+					files: vec![File {
+						length: 40000,
+						md5sum: None,
+						path: vec!["file".into()],
+					}],
+				},
+			},
+			"http://127.0.0.1:3000/announce".into(),
+		);
+		metainfo.creation_date = Some(1700000000);
+		metainfo
+	}
+
+	#[test]
+	fn test_same() {
+		assert_eq!(metainfo().diff(&metainfo()), vec![]);
+	}
+
+	#[test]
+	fn test_diff() {
+		let original = metainfo();
+
+		let mut announce = metainfo();
+		announce.announce = "http://tracker.example/announce".into();
+		assert_eq!(original.diff(&announce), vec![FieldDiff::Announce]);
+
+		let mut piece = metainfo();
+		piece.info.pieces[20] = b'x';
+		assert_eq!(original.diff(&piece), vec![FieldDiff::Piece(1)]);
+
+		// a renamed file is a different layout, a renamed folder only a different name
+		let mut renamed = metainfo();
+		if let FileInfo::Multi { name, files } = &mut renamed.info.file_info {
+			*name = "other".into();
+			files[0].path = vec!["other".into()];
+		}
+		assert_eq!(
+			original.diff(&renamed),
+			vec![FieldDiff::FileLayout, FieldDiff::Name]
+		);
+
+		let mut shorter = metainfo();
+		shorter.info.pieces.truncate(20);
+		shorter.comment = Some("shorter".into());
+		assert_eq!(
+			original.diff(&shorter),
+			vec![FieldDiff::Comment, FieldDiff::Piece(1), FieldDiff::Piece(2)]
+		);
+	}
+}
@@ -0,0 +1,74 @@
+use crate::bencode::{Data, Dictionary};
+
+/// Why a metainfo dictionary couldn't be decoded, naming the offending key.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MetaInfoError {
+	MissingKey(&'static str),
+	WrongType(&'static str),
+	/// a byte string (e.g. `md5sum`) isn't the length it must be
+	BadLength(&'static str),
+	/// a value the type allows, but that can't be right, e.g. a `piece length` of 0
+	BadValue(&'static str),
+}
+
+/// `Dictionary::remove_as` only falls back to the default when the key is missing, but doesn't
+/// know which key it was, so prefer `required`.
+impl Default for MetaInfoError {
+	fn default() -> Self {
+		Self::MissingKey("")
+	}
+}
+
+/// the error a value failing to convert becomes, given the key it was under
+pub(super) trait FieldError {
+	fn at(self, key: &'static str) -> MetaInfoError;
+}
+
+impl FieldError for () {
+	fn at(self, key: &'static str) -> MetaInfoError {
+		MetaInfoError::WrongType(key)
+	}
+}
+
+impl FieldError for MetaInfoError {
+	/// nested dictionaries already know which of their keys is wrong
+	fn at(self, _: &'static str) -> MetaInfoError {
+		self
+	}
+}
+
+pub(super) fn optional<T>(
+	dict: &mut Dictionary,
+	key: &'static str,
+) -> Result<Option<T>, MetaInfoError>
+where
+	T: TryFrom<Data>,
+	T::Error: FieldError,
+{
+	dict.remove(key)
+		.map(|data| T::try_from(data).map_err(|e| e.at(key)))
+		.transpose()
+}
+
+pub(super) fn required<T>(dict: &mut Dictionary, key: &'static str) -> Result<T, MetaInfoError>
+where
+	T: TryFrom<Data>,
+	T::Error: FieldError,
+{
+	optional(dict, key)?.ok_or(MetaInfoError::MissingKey(key))
+}
+
+/// like `optional`, but tells a byte string of the wrong length apart from the wrong type
+pub(super) fn optional_array<const N: usize>(
+	dict: &mut Dictionary,
+	key: &'static str,
+) -> Result<Option<[u8; N]>, MetaInfoError> {
+	match dict.remove(key) {
+		Some(Data::Bytes(bytes)) => bytes
+			.try_into()
+			.map(Some)
+			.map_err(|_| MetaInfoError::BadLength(key)),
+		Some(_) => Err(MetaInfoError::WrongType(key)),
+		None => Ok(None),
+	}
+}
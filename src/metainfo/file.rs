@@ -1,5 +1,7 @@
 use crate::bencode::{impl_try_from_data_dict, Data, Dictionary};
 
+use super::error::{optional_array, required, MetaInfoError};
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct File {
 	pub length: u64,
@@ -18,18 +20,14 @@ impl Into<Dictionary> for File {
 }
 
 impl TryFrom<Dictionary> for File {
-	type Error = ();
+	type Error = MetaInfoError;
 
 	fn try_from(mut value: Dictionary) -> Result<Self, Self::Error> {
-		let length = value.remove_as("length")?;
+		let length = required(&mut value, "length")?;
 
-		let md5sum = value
-			.remove_as_opt("md5sum")?
-			.map(Vec::try_into)
-			.transpose()
-			.map_err(|_| ())?;
+		let md5sum = optional_array(&mut value, "md5sum")?;
 
-		let path = value.remove_as("path")?;
+		let path = required(&mut value, "path")?;
 
 		Ok(Self {
 			length,
@@ -39,12 +37,12 @@ impl TryFrom<Dictionary> for File {
 	}
 }
 
-impl_try_from_data_dict!(File);
+impl_try_from_data_dict!(File, MetaInfoError::WrongType("files"));
 
 #[cfg(test)]
 mod tests {
 	use super::File;
-	use crate::bencode::*;
+	use crate::{bencode::*, metainfo::MetaInfoError};
 
 	#[test]
 	fn test_file_into() {
@@ -93,14 +91,40 @@ mod tests {
 			}))
 		);
 
-		// md5 of length 31 (Ok(Err(_))
-		assert!(try_decode_from::<File, _>(
-			"d6:lengthi25e6:md5sum31:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa4:pathl3:usr3:bin3:env5:rustcee"
-		)
-		.unwrap()
-		.is_err());
+		// md5 of length 31
+		assert_eq!(
+			try_decode_from::<File, _>(
+				"d6:lengthi25e6:md5sum31:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa4:pathl3:usr3:bin3:env5:rustcee"
+			),
+			Ok(Err(MetaInfoError::BadLength("md5sum")))
+		);
 
 		// missing stuff
-		assert!(try_decode_from::<File, _>("de").unwrap().is_err());
+		assert_eq!(
+			try_decode_from::<File, _>("de"),
+			Ok(Err(MetaInfoError::MissingKey("length")))
+		);
+		assert_eq!(
+			try_decode_from::<File, _>("d6:lengthi1ee"),
+			Ok(Err(MetaInfoError::MissingKey("path")))
+		);
+
+		// wrong types
+		assert_eq!(
+			try_decode_from::<File, _>("d6:length1:14:pathlee"),
+			Ok(Err(MetaInfoError::WrongType("length")))
+		);
+		assert_eq!(
+			try_decode_from::<File, _>("d6:lengthi1e6:md5sumi0e4:pathlee"),
+			Ok(Err(MetaInfoError::WrongType("md5sum")))
+		);
+		assert_eq!(
+			try_decode_from::<File, _>("d6:lengthi1e4:path1:ae"),
+			Ok(Err(MetaInfoError::WrongType("path")))
+		);
+		assert_eq!(
+			try_decode_from::<File, _>("le"),
+			Ok(Err(MetaInfoError::WrongType("files")))
+		);
 	}
 }
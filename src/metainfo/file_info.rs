@@ -1,6 +1,15 @@
-use crate::bencode::{impl_try_from_data_dict, Data, Dictionary};
+use std::io::{self, Read};
 
-use super::File;
+use crate::{
+	bencode::{impl_try_from_data_dict, Data, Dictionary},
+	bytes::BytesExt,
+	digest::Md5,
+};
+
+use super::{
+	error::{optional, optional_array, required},
+	File, FileTree, MetaInfoError,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum FileInfo {
@@ -13,6 +22,59 @@ pub enum FileInfo {
 		name: Vec<u8>,
 		files: Vec<File>,
 	},
+	/// BEP 52 (v2 only) torrent.  hybrid torrents are parsed as their v1 form.
+	V2 {
+		name: Vec<u8>,
+		file_tree: FileTree,
+	},
+}
+
+impl FileInfo {
+	pub fn name(&self) -> &[u8] {
+		match self {
+			Self::Single { name, .. } | Self::Multi { name, .. } | Self::V2 { name, .. } => name,
+		}
+	}
+
+	/// checks the data in `reader` against the stored md5sum.  only single-file torrents with an
+	/// md5sum are supported.
+	pub fn verify_md5(&self, mut reader: impl Read) -> io::Result<bool> {
+		let md5sum = match self {
+			Self::Single {
+				md5sum: Some(md5sum),
+				..
+			} => md5sum,
+			Self::Single { md5sum: None, .. } => {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					"No md5sum to verify.",
+				))
+			}
+			Self::Multi { .. } => {
+				return Err(io::Error::new(
+					io::ErrorKind::Unsupported,
+					"md5 verification of multi-file torrents is unsupported.",
+				))
+			}
+			Self::V2 { .. } => {
+				return Err(io::Error::new(
+					io::ErrorKind::Unsupported,
+					"md5 verification of v2 torrents is unsupported.",
+				))
+			}
+		};
+
+		let mut md5 = Md5::new();
+		let mut buf = [0; 16384];
+		loop {
+			match reader.read(&mut buf)? {
+				0 => break,
+				len => md5.update(&buf[..len]),
+			}
+		}
+
+		Ok(md5sum.eq_ignore_ascii_case(md5.digest().to_hex_string().as_bytes()))
+	}
 }
 
 impl Into<Dictionary> for FileInfo {
@@ -32,28 +94,46 @@ impl Into<Dictionary> for FileInfo {
 				dict.insert("name", name);
 				dict.insert("files", files);
 			}
+			Self::V2 { name, file_tree } => {
+				dict.insert("name", name);
+				dict.insert("file tree", file_tree);
+			}
 		};
 		dict
 	}
 }
 
-impl TryFrom<Dictionary> for FileInfo {
-	type Error = ();
-
-	fn try_from(mut data: Dictionary) -> Result<Self, Self::Error> {
-		let name = data.remove_as("name")?;
-		let files = data.remove_as("files");
+impl FileInfo {
+	/// removes the keys it knows from `data`, leaving any others
+	pub(super) fn take(data: &mut Dictionary) -> Result<Self, MetaInfoError> {
+		let name = required(data, "name")?;
+		let files = match data.remove("files") {
+			Some(Data::List(files)) => Some(
+				files
+					.into_iter()
+					.map(File::try_from)
+					.collect::<Result<_, _>>()?,
+			),
+			Some(_) => return Err(MetaInfoError::WrongType("files")),
+			None => None,
+		};
 
-		if let Ok(files) = files {
+		if let Some(files) = files {
 			Ok(Self::Multi { name, files })
 		} else {
-			let length = data.remove_as("length")?;
+			let length = match optional(data, "length")? {
+				Some(length) => length,
+				None => {
+					let file_tree = match optional(data, "file tree")? {
+						Some(file_tree) => file_tree,
+						// neither kind of torrent, report what a single file needs
+						None => return Err(MetaInfoError::MissingKey("length")),
+					};
+					return Ok(Self::V2 { name, file_tree });
+				}
+			};
 
-			let md5sum = data
-				.remove_as_opt("md5sum")?
-				.map(Vec::try_into)
-				.transpose()
-				.map_err(|_| ())?;
+			let md5sum = optional_array(data, "md5sum")?;
 
 			Ok(Self::Single {
 				name,
@@ -64,10 +144,20 @@ impl TryFrom<Dictionary> for FileInfo {
 	}
 }
 
-impl_try_from_data_dict!(FileInfo);
+impl TryFrom<Dictionary> for FileInfo {
+	type Error = MetaInfoError;
+
+	fn try_from(mut data: Dictionary) -> Result<Self, Self::Error> {
+		Self::take(&mut data)
+	}
+}
+
+impl_try_from_data_dict!(FileInfo, MetaInfoError::WrongType("info"));
 
 #[cfg(test)]
 mod tests {
+	use std::collections::BTreeMap;
+
 	use super::*;
 	use crate::bencode::*;
 
@@ -125,6 +215,35 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn test_verify_md5() {
+		let single = |md5sum: Option<&[u8; 32]>| FileInfo::Single {
+			length: 43,
+			md5sum: md5sum.copied(),
+			name: "fox".into(),
+		};
+		let data = &b"The quick brown fox jumps over the lazy dog"[..];
+
+		assert!(single(Some(b"9e107d9d372bb6826bd81d3542a419d6"))
+			.verify_md5(data)
+			.unwrap());
+		// case insensitive
+		assert!(single(Some(b"9E107D9D372BB6826BD81D3542A419D6"))
+			.verify_md5(data)
+			.unwrap());
+		assert!(!single(Some(b"d41d8cd98f00b204e9800998ecf8427e"))
+			.verify_md5(data)
+			.unwrap());
+
+		assert!(single(None).verify_md5(data).is_err());
+		assert!(FileInfo::Multi {
+			name: "mt".into(),
+			files: vec![]
+		}
+		.verify_md5(data)
+		.is_err());
+	}
+
 	#[test]
 	fn test_fileinfo_from() {
 		// single without md5sum
@@ -182,19 +301,50 @@ mod tests {
 			}))
 		);
 
+		// v2
+		assert_eq!(
+			try_decode_from("d9:file treed1:ad0:d6:lengthi1eeee4:name1:ve"),
+			Ok(Ok(FileInfo::V2 {
+				name: "v".into(),
+				file_tree: FileTree::Dir(BTreeMap::from([(
+					"a".into(),
+					FileTree::File {
+						length: 1,
+						pieces_root: None
+					}
+				)]))
+			}))
+		);
+
+		// neither length, files nor file tree
+		assert_eq!(
+			try_decode_from::<FileInfo, _>("d4:name1:ve"),
+			Ok(Err(MetaInfoError::MissingKey("length")))
+		);
+		assert_eq!(
+			try_decode_from::<FileInfo, _>("d6:lengthi0ee"),
+			Ok(Err(MetaInfoError::MissingKey("name")))
+		);
+
 		// wrong md5 length
-		assert!(
-			try_decode_from::<FileInfo, _>("d6:lengthi0e6:md5sum0:4:name0:e")
-				.unwrap()
-				.is_err()
+		assert_eq!(
+			try_decode_from::<FileInfo, _>("d6:lengthi0e6:md5sum0:4:name0:e"),
+			Ok(Err(MetaInfoError::BadLength("md5sum")))
 		);
 
 		// bad files
-		assert!(
+		assert_eq!(
 			// length is string
-			try_decode_from::<FileInfo, _>("d5:filesld6:length0:4:pathlee4:name8:bad pathee")
-				.unwrap()
-				.is_err()
-		)
+			try_decode_from::<FileInfo, _>("d5:filesld6:length0:4:pathleee4:name8:bad pathe"),
+			Ok(Err(MetaInfoError::WrongType("length")))
+		);
+		assert_eq!(
+			try_decode_from::<FileInfo, _>("d5:files0:4:name0:e"),
+			Ok(Err(MetaInfoError::WrongType("files")))
+		);
+		assert_eq!(
+			try_decode_from::<FileInfo, _>("d9:file tree0:4:name0:e"),
+			Ok(Err(MetaInfoError::WrongType("file tree")))
+		);
 	}
 }
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use crate::bencode::{impl_try_from_data_dict, Data, Dictionary};
+
+/// BEP 52 (v2) file tree: nested directories whose leaves are files
+#[derive(Debug, PartialEq, Clone)]
+pub enum FileTree {
+	/// stored as a dictionary under the empty key
+	File {
+		length: u64,
+		/// merkle root of the file's pieces, absent for empty files
+		pieces_root: Option<[u8; 32]>,
+	},
+	Dir(BTreeMap<Vec<u8>, FileTree>),
+}
+
+impl From<FileTree> for Dictionary {
+	fn from(tree: FileTree) -> Self {
+		let mut dict = Dictionary::new();
+		match tree {
+			FileTree::File {
+				length,
+				pieces_root,
+			} => {
+				let mut file = Dictionary::new();
+				file.insert("length", length);
+				file.insert_some("pieces root", pieces_root);
+				dict.insert("", file);
+			}
+			FileTree::Dir(entries) => {
+				for (name, entry) in entries {
+					dict.insert(name, entry);
+				}
+			}
+		}
+		dict
+	}
+}
+
+impl TryFrom<Dictionary> for FileTree {
+	type Error = ();
+
+	fn try_from(mut data: Dictionary) -> Result<Self, Self::Error> {
+		if let Some(file) = data.remove("") {
+			let mut file = Dictionary::try_from(file)?;
+			let length = file.remove_as("length")?;
+			let pieces_root = file.remove_as_opt("pieces root")?;
+
+			Ok(Self::File {
+				length,
+				pieces_root,
+			})
+		} else {
+			data.into_iter()
+				.map(|(name, entry)| Ok((name, Self::try_from(entry)?)))
+				.collect::<Result<_, _>>()
+				.map(Self::Dir)
+		}
+	}
+}
+
+impl_try_from_data_dict!(FileTree);
+
+#[cfg(test)]
+mod tests {
+	use std::collections::BTreeMap;
+
+	use super::FileTree;
+	use crate::bencode::*;
+
+	#[test]
+	fn test_file_tree() {
+		let tree = FileTree::Dir(BTreeMap::from([(
+			"a.txt".into(),
+			FileTree::File {
+				length: 3,
+				pieces_root: Some([b'r'; 32]),
+			},
+		)]));
+		let encoded = "d5:a.txtd0:d6:lengthi3e11:pieces root32:rrrrrrrrrrrrrrrrrrrrrrrrrrrrrrrreee";
+
+		assert_eq!(encode(tree.clone()), encoded.as_bytes());
+		assert_eq!(try_decode_from(encoded), Ok(Ok(tree)));
+
+		// nested directory, file without pieces root
+		assert_eq!(
+			try_decode_from("d3:dird5:emptyd0:d6:lengthi0eeeee"),
+			Ok(Ok(FileTree::Dir(BTreeMap::from([(
+				"dir".into(),
+				FileTree::Dir(BTreeMap::from([(
+					"empty".into(),
+					FileTree::File {
+						length: 0,
+						pieces_root: None
+					}
+				)]))
+			)]))))
+		);
+
+		// file entry with no length
+		assert!(try_decode_from::<FileTree, _>("d1:ad0:deee")
+			.unwrap()
+			.is_err());
+	}
+}
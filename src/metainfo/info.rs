@@ -1,56 +1,146 @@
-use crate::bencode::{impl_try_from_data_dict, Data, Dictionary};
+use std::ops::Range;
 
-use super::FileInfo;
+use crate::bencode::{impl_try_from_data_dict, Conflict, Data, Dictionary};
+
+use super::{
+	error::{optional, required},
+	FileInfo, MetaInfoError,
+};
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Info {
 	pub piece_length: u64,
 	pub pieces: Vec<u8>,
 	pub private: Option<bool>,
+	/// BEP 52 `meta version`, 2 for v2 and hybrid torrents
+	pub meta_version: Option<u64>,
 	pub file_info: FileInfo,
 }
 
+impl Info {
+	/// like `try_from`, for torrents from untrusted sources: errors with `BadLength("pieces")` if
+	/// `pieces` is longer than `max_pieces_bytes`, before anything is converted
+	#[allow(dead_code)]
+	pub fn try_from_limited(
+		data: Dictionary,
+		max_pieces_bytes: usize,
+	) -> Result<Self, MetaInfoError> {
+		if let Some(Data::Bytes(pieces)) = data.get("pieces") {
+			if pieces.len() > max_pieces_bytes {
+				return Err(MetaInfoError::BadLength("pieces"));
+			}
+		}
+		Self::try_from(data)
+	}
+
+	/// whether the torrent is private (BEP 27): peers only come from its trackers, never the DHT
+	/// or peer exchange.  a missing `private` key means public.
+	pub fn is_private(&self) -> bool {
+		self.private == Some(true)
+	}
+
+	/// the SHA-1 hash of each piece, in order.  a trailing partial hash is ignored.
+	pub fn piece_hashes(&self) -> impl Iterator<Item = [u8; 20]> + '_ {
+		self.pieces
+			.chunks_exact(20)
+			.map(|hash| hash.try_into().expect("chunk is 20 bytes"))
+	}
+
+	/// indices of the pieces holding any of the file at `path` (within the torrent's folder) of a
+	/// multi-file torrent.  pieces are shared with the files either side, and an empty file has an
+	/// empty range.  `None` if there's no such file, or the files before it overflow a `u64`.
+	pub fn pieces_for_path(&self, path: &[&[u8]]) -> Option<Range<usize>> {
+		let FileInfo::Multi { files, .. } = &self.file_info else {
+			return None;
+		};
+		if self.piece_length == 0 {
+			return None;
+		}
+		let piece_length = self.piece_length;
+
+		let mut offset = 0u64;
+		for file in files {
+			let end = offset.checked_add(file.length)?;
+			if file.path.iter().map(Vec::as_slice).eq(path.iter().copied()) {
+				let start = (offset / piece_length) as usize;
+				let end = if file.length == 0 {
+					start
+				} else {
+					end.div_ceil(piece_length) as usize
+				};
+				return Some(start..end);
+			}
+			offset = end;
+		}
+		None
+	}
+}
+
 impl Into<Dictionary> for Info {
 	fn into(self) -> Dictionary {
 		let mut dict = Dictionary::new();
 		dict.insert("piece length", self.piece_length);
-		dict.insert("pieces", self.pieces);
-		dict.insert_some("private", self.private.map(|s| s as u64));
-		dict.append(&mut self.file_info.into());
+		// pure v2 torrents hash their pieces in the file tree instead
+		if !(self.meta_version == Some(2) && self.pieces.is_empty()) {
+			dict.insert("pieces", self.pieces);
+		}
+		dict.insert_some("private", self.private);
+		dict.insert_some("meta version", self.meta_version);
+		dict.merge(self.file_info.into(), Conflict::Error)
+			.expect("File info has a key of the info dictionary.");
 		dict
 	}
 }
 
-impl TryFrom<Dictionary> for Info {
-	type Error = ();
+impl Info {
+	/// removes the keys it knows from `data`, leaving any others
+	pub(super) fn take(data: &mut Dictionary) -> Result<Self, MetaInfoError> {
+		let piece_length = required(data, "piece length")?;
+		// no data fits in pieces of nothing
+		if piece_length == 0 {
+			return Err(MetaInfoError::BadValue("piece length"));
+		}
+		let meta_version = optional(data, "meta version")?;
 
-	fn try_from(mut data: Dictionary) -> Result<Self, Self::Error> {
-		let piece_length = data.remove_as("piece length")?;
-		let pieces = data.remove_as("pieces")?;
+		// only v1 and hybrid torrents have them
+		let pieces = match meta_version {
+			Some(2) => optional(data, "pieces")?.unwrap_or_default(),
+			_ => required(data, "pieces")?,
+		};
 
 		let private = match data.remove("private") {
 			Some(Data::UInt(u)) => Some(u != 0),
 			Some(Data::Int(i)) => Some(i != 0),
-			Some(_) => return Err(()),
+			Some(_) => return Err(MetaInfoError::WrongType("private")),
 			None => None,
 		};
 
-		let file_info = data.try_into()?;
+		let file_info = FileInfo::take(data)?;
 
 		Ok(Self {
 			piece_length,
 			pieces,
 			private,
+			meta_version,
 			file_info,
 		})
 	}
 }
 
-impl_try_from_data_dict!(Info);
+impl TryFrom<Dictionary> for Info {
+	type Error = MetaInfoError;
+
+	fn try_from(mut data: Dictionary) -> Result<Self, Self::Error> {
+		Self::take(&mut data)
+	}
+}
+
+impl_try_from_data_dict!(Info, MetaInfoError::WrongType("info"));
 
 #[cfg(test)]
 mod tests {
 	use crate::bencode::*;
+	use crate::bytes::assert_bytes_eq;
 	use crate::metainfo::*;
 
 	#[test]
@@ -61,6 +151,7 @@ mod tests {
 				piece_length: 20,
 				pieces: "12345678901234567890".into(),
 				private: Some(true),
+				meta_version: None,
 				file_info: FileInfo::Single {
 					length: 0,
 					name: "".into(),
@@ -75,6 +166,7 @@ mod tests {
 				piece_length: 1,
 				pieces: "12345678901234567890".into(),
 				private: None,
+				meta_version: None,
 				file_info: FileInfo::Multi {
 					name: "zamn".into(),
 					files: vec![
@@ -93,11 +185,12 @@ mod tests {
 	#[test]
 	fn test_info_from() {
 		assert_eq!(
-			try_decode_from("d6:lengthi0e4:name0:12:piece lengthi0e6:pieces0:e"),
+			try_decode_from("d6:lengthi0e4:name0:12:piece lengthi1e6:pieces0:e"),
 			Ok(Ok(Info {
-				piece_length: 0,
+				piece_length: 1,
 				pieces: "".into(),
 				private: None,
+				meta_version: None,
 				file_info: FileInfo::Single {
 					length: 0,
 					name: "".into(),
@@ -114,6 +207,7 @@ mod tests {
 				piece_length: 20,
 				pieces: "12345678901234567890".into(),
 				private: Some(true),
+				meta_version: None,
 				file_info: FileInfo::Multi {
 					name: "zamn".into(),
 					files: vec![
@@ -127,4 +221,183 @@ mod tests {
 			}))
 		);
 	}
+
+	#[test]
+	fn test_info_from_err() {
+		assert_eq!(
+			try_decode_from::<Info, _>("d6:lengthi0e4:name0:6:pieces0:e"),
+			Ok(Err(MetaInfoError::MissingKey("piece length")))
+		);
+		assert_eq!(
+			try_decode_from::<Info, _>("d6:lengthi0e4:name0:12:piece lengthi1e6:piecesi0ee"),
+			Ok(Err(MetaInfoError::WrongType("pieces")))
+		);
+		assert_eq!(
+			try_decode_from::<Info, _>(
+				"d6:lengthi0e4:name0:12:piece lengthi1e6:pieces0:7:private2:noe"
+			),
+			Ok(Err(MetaInfoError::WrongType("private")))
+		);
+		assert_eq!(
+			try_decode_from::<Info, _>("d6:lengthi0e4:name0:12:piece lengthi0e6:pieces0:e"),
+			Ok(Err(MetaInfoError::BadValue("piece length")))
+		);
+		// errors from the file info come through
+		assert_eq!(
+			try_decode_from::<Info, _>("d4:name0:12:piece lengthi1e6:pieces0:e"),
+			Ok(Err(MetaInfoError::MissingKey("length")))
+		);
+	}
+
+	#[test]
+	fn test_info_v2() {
+		let info = Info {
+			piece_length: 16384,
+			pieces: "".into(),
+			private: None,
+			meta_version: Some(2),
+			file_info: FileInfo::V2 {
+				name: "v2".into(),
+				file_tree: FileTree::Dir(
+					[(
+						"file.txt".into(),
+						FileTree::File {
+							length: 4,
+							pieces_root: Some([b'x'; 32]),
+						},
+					)]
+					.into(),
+				),
+			},
+		};
+		let encoded = "d9:file treed8:file.txtd0:d6:lengthi4e11:pieces root32:xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxeee12:meta versioni2e4:name2:v212:piece lengthi16384ee";
+
+		assert_bytes_eq(encode(info.clone()), encoded);
+		assert_eq!(try_decode_from(encoded), Ok(Ok(info)));
+
+		// v1 torrents still need pieces
+		assert_eq!(
+			try_decode_from::<Info, _>("d6:lengthi0e4:name0:12:piece lengthi20ee"),
+			Ok(Err(MetaInfoError::MissingKey("pieces")))
+		);
+	}
+
+	#[test]
+	fn test_piece_hashes() {
+		let info = Info {
+			piece_length: 16384,
+			pieces: [[1; 20], [2; 20]].concat(),
+			private: None,
+			meta_version: None,
+			file_info: FileInfo::Single {
+				length: 20000,
+				md5sum: None,
+				name: "file".into(),
+			},
+		};
+		assert_eq!(
+			info.piece_hashes().collect::<Vec<_>>(),
+			vec![[1; 20], [2; 20]]
+		);
+	}
+
+	#[test]
+	fn test_try_from_limited() {
+		let info = |pieces: usize| {
+			Dictionary::from(vec![
+				("piece length", Data::from(16384u64)),
+				("pieces", Data::Bytes(vec![b'a'; pieces])),
+				("name", Data::Bytes("file".into())),
+				("length", Data::from(1u64)),
+			])
+		};
+
+		assert_eq!(
+			Info::try_from_limited(info(40), 40).map(|info| info.pieces.len()),
+			Ok(40)
+		);
+		assert_eq!(
+			Info::try_from_limited(info(60), 40),
+			Err(MetaInfoError::BadLength("pieces"))
+		);
+		// other errors are still the ones `try_from` gives
+		let mut missing = info(20);
+		missing.remove("pieces");
+		assert_eq!(
+			Info::try_from_limited(missing, 40),
+			Err(MetaInfoError::MissingKey("pieces"))
+		);
+	}
+
+	#[test]
+	fn test_is_private() {
+		let info = |private| Info {
+			piece_length: 1,
+			pieces: vec![],
+			private,
+			meta_version: None,
+			file_info: FileInfo::Single {
+				length: 0,
+				md5sum: None,
+				name: "".into(),
+			},
+		};
+		assert!(!info(None).is_private());
+		assert!(!info(Some(false)).is_private());
+		assert!(info(Some(true)).is_private());
+	}
+
+	#[test]
+	fn test_pieces_for_path() {
+		let file = |length, path: &[&str]| File {
+			length,
+			md5sum: None,
+			path: path.iter().map(|part| part.as_bytes().to_vec()).collect(),
+		};
+		// pieces of 10: [0, 10) [10, 20) [20, 30) [30, 40)
+		let info = Info {
+			piece_length: 10,
+			pieces: vec![0; 4 * 20],
+			private: None,
+			meta_version: None,
+			file_info: FileInfo::Multi {
+				name: "folder".into(),
+				files: vec![
+					// [0, 12)
+					file(12, &["a"]),
+					// [12, 15)
+					file(3, &["sub", "b"]),
+					file(0, &["empty"]),
+					// [15, 35)
+					file(20, &["c"]),
+				],
+			},
+		};
+
+		assert_eq!(info.pieces_for_path(&[b"a"]), Some(0..2));
+		// wholly inside piece 1
+		assert_eq!(info.pieces_for_path(&[b"sub", b"b"]), Some(1..2));
+		assert_eq!(info.pieces_for_path(&[b"empty"]), Some(1..1));
+		assert_eq!(info.pieces_for_path(&[b"c"]), Some(1..4));
+
+		assert_eq!(info.pieces_for_path(&[b"b"]), None);
+		assert_eq!(info.pieces_for_path(&[b"sub"]), None);
+		assert_eq!(info.pieces_for_path(&[]), None);
+
+		let mut overflowing = info.clone();
+		if let FileInfo::Multi { files, .. } = &mut overflowing.file_info {
+			files.insert(0, file(u64::MAX, &["huge"]));
+		}
+		assert_eq!(overflowing.pieces_for_path(&[b"a"]), None);
+
+		let single = Info {
+			file_info: FileInfo::Single {
+				length: 40,
+				md5sum: None,
+				name: "a".into(),
+			},
+			..info
+		};
+		assert_eq!(single.pieces_for_path(&[b"a"]), None);
+	}
 }
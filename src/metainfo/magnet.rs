@@ -0,0 +1,242 @@
+use std::net::SocketAddr;
+
+use super::MetaInfo;
+use crate::{
+	bytes::{from_base32, BytesExt},
+	tracker::{decode, url_encode},
+};
+
+/// What a magnet link (BEP 9) says about a v1 torrent.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Magnet {
+	pub info_hash: [u8; 20],
+	pub display_name: Option<String>,
+	pub trackers: Vec<String>,
+	/// peers to try before any tracker answers (`x.pe`)
+	pub peers: Vec<SocketAddr>,
+}
+
+fn from_hex(hex: &[u8]) -> Option<[u8; 20]> {
+	let digit = |d: u8| (d as char).to_digit(16).map(|d| d as u8);
+	let mut bytes = [0; 20];
+	if hex.len() != 40 {
+		return None;
+	}
+	for (byte, pair) in bytes.iter_mut().zip(hex.chunks_exact(2)) {
+		*byte = digit(pair[0])? << 4 | digit(pair[1])?;
+	}
+	Some(bytes)
+}
+
+impl Magnet {
+	/// parses a `magnet:?` uri.  `xt` must be a `urn:btih:` info hash, in hex or base32.
+	#[allow(dead_code)]
+	pub fn parse(uri: &str) -> Result<Self, &'static str> {
+		let query = uri.strip_prefix("magnet:?").ok_or("Not a magnet link.")?;
+		let mut query = decode(query).map_err(|_| "Invalid magnet query string.")?;
+
+		let info_hash = query
+			.remove_all("xt")
+			.into_iter()
+			.find_map(|xt| {
+				let hash = xt.strip_prefix(b"urn:btih:")?;
+				from_hex(hash).or_else(|| from_base32(hash))
+			})
+			.ok_or("Missing or invalid info hash (xt).")?;
+
+		let to_string = |value: Vec<u8>| String::from_utf8(value).map_err(|_| ());
+		Ok(Self {
+			info_hash,
+			display_name: query
+				.remove("dn")
+				.map(to_string)
+				.transpose()
+				.map_err(|_| "Invalid display name (dn).")?,
+			trackers: query
+				.remove_all("tr")
+				.into_iter()
+				.map(to_string)
+				.collect::<Result<_, _>>()
+				.map_err(|_| "Invalid tracker (tr).")?,
+			// a bad hint isn't worth failing over, there are still the trackers
+			peers: query
+				.remove_all("x.pe")
+				.into_iter()
+				.filter_map(|peer| String::from_utf8(peer).ok()?.parse().ok())
+				.collect(),
+		})
+	}
+
+	/// the magnet link, with the info hash in hex
+	pub fn to_uri(&self) -> String {
+		let mut uri = format!("magnet:?xt=urn:btih:{}", self.info_hash.to_hex_string());
+		if let Some(name) = &self.display_name {
+			uri += "&dn=";
+			uri += &url_encode(name.clone().into_bytes()).to_string();
+		}
+		for tracker in &self.trackers {
+			uri += "&tr=";
+			uri += &url_encode(tracker.clone().into_bytes()).to_string();
+		}
+		for peer in &self.peers {
+			uri += "&x.pe=";
+			uri += &url_encode(peer.to_string().into_bytes()).to_string();
+		}
+		uri
+	}
+}
+
+impl From<&MetaInfo> for Magnet {
+	fn from(metainfo: &MetaInfo) -> Self {
+		// the announce url, then the announce list, without repeats
+		let mut trackers: Vec<String> = vec![metainfo.announce.to_string()];
+		for tracker in metainfo.announce_list.iter().flatten().flatten() {
+			let tracker = tracker.to_string();
+			if !trackers.contains(&tracker) {
+				trackers.push(tracker);
+			}
+		}
+		trackers.retain(|tracker| !tracker.is_empty());
+
+		Self {
+			info_hash: metainfo.info_hash(),
+			display_name: Some(metainfo.info.file_info.name().to_string()),
+			trackers,
+			peers: vec![],
+		}
+	}
+}
+
+impl MetaInfo {
+	pub fn to_magnet(&self) -> String {
+		Magnet::from(self).to_uri()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::SocketAddr;
+
+	use super::Magnet;
+	use crate::metainfo::{FileInfo, Info, MetaInfo};
+
+	const INFO_HASH: [u8; 20] = [
+		0x93, 0x62, 0x07, 0xe0, 0x49, 0xdc, 0x6f, 0x3d, 0x8b, 0xa6, 0x13, 0x38, 0xb6, 0x7c, 0xb2,
+		0x10, 0x07, 0x4c, 0xae, 0x60,
+	];
+
+	#[test]
+	fn test_parse_hex() {
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:btih:936207E049DC6F3D8BA61338B67CB210074CAE60&dn=my+file%21&tr=http%3A%2F%2F127.0.0.1%3A3000%2Fannounce"),
+			Ok(Magnet {
+				info_hash: INFO_HASH,
+				display_name: Some("my file!".into()),
+				trackers: vec!["http://127.0.0.1:3000/announce".into()],
+				peers: vec![],
+			})
+		);
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:btih:936207e049dc6f3d8ba61338b67cb210074cae60"),
+			Ok(Magnet {
+				info_hash: INFO_HASH,
+				display_name: None,
+				trackers: vec![],
+				peers: vec![],
+			})
+		);
+	}
+
+	#[test]
+	fn test_parse_base32() {
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:btih:SNRAPYCJ3RXT3C5GCM4LM7FSCADUZLTA&dn=file")
+				.map(|magnet| magnet.info_hash),
+			Ok(INFO_HASH)
+		);
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:btih:snrapycj3rxt3c5gcm4lm7fscaduzlta")
+				.map(|magnet| magnet.info_hash),
+			Ok(INFO_HASH)
+		);
+	}
+
+	#[test]
+	fn test_parse_err() {
+		let err = Err("Missing or invalid info hash (xt).");
+		assert_eq!(Magnet::parse("magnet:?dn=file&tr=http%3A%2F%2Fa"), err);
+		// wrong length, not a bittorrent hash, not base32
+		assert_eq!(Magnet::parse("magnet:?xt=urn:btih:936207"), err);
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:sha1:936207e049dc6f3d8ba61338b67cb210074cae60"),
+			err
+		);
+		assert_eq!(
+			Magnet::parse("magnet:?xt=urn:btih:SNRAPYCJ3RXT3C5GCM4LM7FSCADUZLT1"),
+			err
+		);
+		assert_eq!(
+			Magnet::parse(
+				"http://example.com/?xt=urn:btih:936207e049dc6f3d8ba61338b67cb210074cae60"
+			),
+			Err("Not a magnet link.")
+		);
+	}
+
+	#[test]
+	fn test_trackers_and_peers() {
+		let magnet = Magnet::parse(concat!(
+			"magnet:?xt=urn:btih:936207e049dc6f3d8ba61338b67cb210074cae60",
+			"&tr=http%3A%2F%2Fa.example%2Fannounce&tr=http%3A%2F%2Fb.example%2Fannounce",
+			"&x.pe=10.0.0.1%3A6881&x.pe=not-an-address&x.pe=%5B2001%3Adb8%3A%3A1%5D%3A6882",
+			"&tr=udp%3A%2F%2Fc.example%3A80"
+		))
+		.unwrap();
+		assert_eq!(
+			magnet.trackers,
+			vec![
+				"http://a.example/announce",
+				"http://b.example/announce",
+				"udp://c.example:80"
+			]
+		);
+		// the malformed one is skipped
+		assert_eq!(
+			magnet.peers,
+			vec![
+				"10.0.0.1:6881".parse::<SocketAddr>().unwrap(),
+				"[2001:db8::1]:6882".parse().unwrap()
+			]
+		);
+		assert_eq!(Magnet::parse(&magnet.to_uri()), Ok(magnet));
+	}
+
+	#[test]
+	fn test_to_magnet() {
+		let mut metainfo = MetaInfo::new(
+			Info {
+				piece_length: 16384,
+				pieces: vec![b'a'; 20],
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Single {
+					length: 2,
+					md5sum: None,
+					name: "file".into(),
+				},
+			},
+			"http://127.0.0.1:3000/announce".into(),
+		);
+		metainfo.announce_list = Some(vec![
+			vec!["http://127.0.0.1:3000/announce".into()],
+			vec!["http://backup.example/announce".into()],
+		]);
+
+		let uri = metainfo.to_magnet();
+		assert_eq!(
+			uri,
+			"magnet:?xt=urn:btih:936207e049dc6f3d8ba61338b67cb210074cae60&dn=file&tr=http%3A%2F%2F127.0.0.1%3A3000%2Fannounce&tr=http%3A%2F%2Fbackup.example%2Fannounce"
+		);
+		assert_eq!(Magnet::parse(&uri), Ok(Magnet::from(&metainfo)));
+	}
+}
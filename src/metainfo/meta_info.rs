@@ -1,22 +1,125 @@
-use super::Info;
-use crate::bencode::{impl_try_from_data_dict, Data, Dictionary};
+use std::io;
+
+use sha1_smol::Sha1;
+
+use super::{
+	error::{optional, required},
+	Info, MetaInfoError,
+};
+use crate::{
+	bencode::{self, impl_try_from_data_dict, Conflict, Data, Dictionary},
+	bytes::BytesExt,
+	digest::Sha256,
+	gzip,
+};
+
+/// longest a gzipped torrent may decompress to
+const MAX_DECOMPRESSED_LEN: usize = 64 * 1024 * 1024;
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct MetaInfo {
 	pub info: Info,
 	pub announce: Vec<u8>,
-	pub announce_list: Option<Vec<Vec<u8>>>,
+	/// tiers of announce urls (BEP 12)
+	pub announce_list: Option<Vec<Vec<Vec<u8>>>>,
 	pub creation_date: Option<u64>,
 	pub comment: Option<Vec<u8>>,
 	pub created_by: Option<Vec<u8>>,
 	pub encoding: Option<Vec<u8>>,
+	/// keys of the decoded info dictionary `Info` doesn't know, hashed and encoded along with it
+	unknown_info_keys: Dictionary,
+}
+
+/// the info dictionary of `info`, with the keys it doesn't know
+fn info_dict(info: Info, unknown_keys: Dictionary) -> Dictionary {
+	let mut dict: Dictionary = info.into();
+	dict.merge(unknown_keys, Conflict::Keep)
+		.expect("Keeping conflicts doesn't fail.");
+	dict
+}
+
+impl MetaInfo {
+	/// a metainfo with only the required keys
+	pub fn new(info: Info, announce: Vec<u8>) -> Self {
+		Self {
+			info,
+			announce,
+			announce_list: None,
+			creation_date: None,
+			comment: None,
+			created_by: None,
+			encoding: None,
+			unknown_info_keys: Dictionary::new(),
+		}
+	}
+
+	/// the keys of the info dictionary `Info` doesn't know, as they were decoded.  empty if there
+	/// are none or this wasn't decoded.
+	#[allow(dead_code)]
+	pub fn raw_info(&self) -> &Dictionary {
+		&self.unknown_info_keys
+	}
+
+	/// decodes a .torrent file, decompressing it first if it's gzipped (e.g. when it was downloaded
+	/// without its `Content-Encoding`)
+	pub fn from_bytes_auto(bytes: &[u8]) -> io::Result<Self> {
+		let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+		let decompressed;
+		let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+			decompressed = gzip::decompress(bytes, MAX_DECOMPRESSED_LEN)?;
+			&decompressed[..]
+		} else {
+			bytes
+		};
+		bencode::try_decode_from_slice(bytes)
+			.map_err(|_| invalid("Torrent is not bencoded."))?
+			.map_err(|_| invalid("Torrent is not valid metainfo."))
+	}
+
+	/// SHA-1 hash of the bencoded info dictionary, used to identify v1 torrents
+	pub fn info_hash(&self) -> [u8; 20] {
+		Sha1::from(bencode::encode(info_dict(
+			self.info.clone(),
+			self.unknown_info_keys.clone(),
+		)))
+		.digest()
+		.bytes()
+	}
+
+	/// SHA-256 hash of the bencoded info dictionary, used to identify v2 (BEP 52) torrents.
+	/// only the hash is supported, not the v2 piece layout.
+	#[allow(dead_code)]
+	pub fn info_hash_v2(&self) -> [u8; 32] {
+		let mut sha = Sha256::new();
+		sha.update(&bencode::encode(info_dict(
+			self.info.clone(),
+			self.unknown_info_keys.clone(),
+		)));
+		sha.digest()
+	}
+}
+
+impl MetaInfo {
+	/// renders the metainfo as JSON, with its info hash added as `info hash`.  `pieces` and other
+	/// binary strings are hex, text strings are kept as they are.
+	#[allow(dead_code)]
+	pub fn to_json(&self) -> String {
+		let mut info: Dictionary = self.info.clone().into();
+		info.insert("pieces", self.info.pieces.to_hex_string());
+
+		let mut dict: Dictionary = self.clone().into();
+		dict.insert("info", info);
+		dict.insert("info hash", self.info_hash().to_hex_string());
+		Data::Dict(dict).to_json()
+	}
 }
 
 impl Into<Dictionary> for MetaInfo {
 	fn into(self) -> Dictionary {
 		let mut dict = Dictionary::new();
 
-		dict.insert("info", self.info);
+		dict.insert("info", info_dict(self.info, self.unknown_info_keys));
 		dict.insert("announce", self.announce);
 		dict.insert_some("announce-list", self.announce_list);
 		dict.insert_some("creation date", self.creation_date);
@@ -29,22 +132,23 @@ impl Into<Dictionary> for MetaInfo {
 }
 
 impl TryFrom<Dictionary> for MetaInfo {
-	type Error = ();
+	type Error = MetaInfoError;
 
 	fn try_from(mut value: Dictionary) -> Result<Self, Self::Error> {
-		let info = value.remove_as("info")?;
+		let mut unknown_info_keys: Dictionary = required(&mut value, "info")?;
+		let info = Info::take(&mut unknown_info_keys)?;
 
-		let announce = value.remove_as("announce")?;
+		let announce = required(&mut value, "announce")?;
 
-		let announce_list = value.remove_as_opt("announce-list")?;
+		let announce_list = optional(&mut value, "announce-list")?;
 
-		let comment = value.remove_as_opt("comment")?;
+		let comment = optional(&mut value, "comment")?;
 
-		let created_by = value.remove_as_opt("created by")?;
+		let created_by = optional(&mut value, "created by")?;
 
-		let creation_date = value.remove_as_opt("creation date")?;
+		let creation_date = optional(&mut value, "creation date")?;
 
-		let encoding = value.remove_as_opt("encoding")?;
+		let encoding = optional(&mut value, "encoding")?;
 
 		Ok(Self {
 			info,
@@ -54,26 +158,65 @@ impl TryFrom<Dictionary> for MetaInfo {
 			created_by,
 			creation_date,
 			encoding,
+			unknown_info_keys,
 		})
 	}
 }
 
-impl_try_from_data_dict!(MetaInfo);
+impl_try_from_data_dict!(MetaInfo, MetaInfoError::WrongType(""));
 
 #[cfg(test)]
 mod tests {
+	use std::io;
+
 	use crate::bencode::*;
+	use crate::bytes::BytesExt;
 	use crate::metainfo::*;
 
+	#[test]
+	fn test_info_hash() {
+		// info is d6:lengthi2e4:name4:file12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae
+		let meta_info = MetaInfo {
+			info: Info {
+				piece_length: 16384,
+				pieces: vec![b'a'; 20],
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Single {
+					length: 2,
+					md5sum: None,
+					name: "file".into(),
+				},
+			},
+			announce: "".into(),
+			announce_list: None,
+			comment: None,
+			created_by: None,
+			creation_date: None,
+			encoding: None,
+			unknown_info_keys: Dictionary::new(),
+		};
+
+		assert_eq!(
+			meta_info.info_hash().to_hex_string(),
+			"936207e049dc6f3d8ba61338b67cb210074cae60"
+		);
+		assert_eq!(
+			meta_info.info_hash_v2().to_hex_string(),
+			"86fcc1a77126b732a575dab3d6806d63ec2043188aa2f0b29f8d090322223c3a"
+		);
+	}
+
 	#[test]
 	fn test_metainfo_into() {
 		// minimal
 		assert_eq!(
 			encode(MetaInfo {
 				info: Info {
-					piece_length: 0,
+					piece_length: 1,
 					pieces: "".into(),
 					private: None,
+					meta_version: None,
 					file_info: FileInfo::Single {
 						length: 2,
 						md5sum: None,
@@ -86,8 +229,9 @@ mod tests {
 				created_by: None,
 				creation_date: None,
 				encoding: None,
+				unknown_info_keys: Dictionary::new(),
 			}),
-			b"d8:announce0:4:infod6:lengthi2e4:name4:file12:piece lengthi0e6:pieces0:ee"
+			b"d8:announce0:4:infod6:lengthi2e4:name4:file12:piece lengthi1e6:pieces0:ee"
 		);
 
 		// all options
@@ -96,19 +240,21 @@ mod tests {
 				piece_length: 5,
 				pieces: "123456".into(),
 				private: Some(false),
+				meta_version: None,
 				file_info: FileInfo::Multi {
 					files: vec![],
 					name: "folder".into(),
 				}
 			},
 			announce: "no".into(),
-			announce_list: Some(vec!["12345".into()]),
+			announce_list: Some(vec![vec!["12345".into()]]),
 			comment: Some("no comment".into()),
 			created_by: Some("me".into()),
 			creation_date: Some(0),
 			encoding: Some("utf-8".into()),
+			unknown_info_keys: Dictionary::new(),
 		}),
-		b"d8:announce2:no13:announce-listl5:12345e7:comment10:no comment10:created by2:me13:creation datei0e8:encoding5:utf-84:infod5:filesle4:name6:folder12:piece lengthi5e6:pieces6:1234567:privatei0eee"
+		b"d8:announce2:no13:announce-listll5:12345ee7:comment10:no comment10:created by2:me13:creation datei0e8:encoding5:utf-84:infod5:filesle4:name6:folder12:piece lengthi5e6:pieces6:1234567:privatei0eee"
 	);
 	}
 
@@ -117,13 +263,14 @@ mod tests {
 		// minimal
 		assert_eq!(
 			try_decode_from(
-				"d8:announce0:4:infod6:lengthi2e4:name4:file12:piece lengthi0e6:pieces0:ee"
+				"d8:announce0:4:infod6:lengthi2e4:name4:file12:piece lengthi1e6:pieces0:ee"
 			),
 			Ok(Ok(MetaInfo {
 				info: Info {
-					piece_length: 0,
+					piece_length: 1,
 					pieces: "".into(),
 					private: None,
+					meta_version: None,
 					file_info: FileInfo::Single {
 						length: 2,
 						md5sum: None,
@@ -136,28 +283,192 @@ mod tests {
 				created_by: None,
 				creation_date: None,
 				encoding: None,
+				unknown_info_keys: Dictionary::new(),
 			}))
 		);
 
 		assert_eq!(try_decode_from(
-			"d8:announce2:no13:announce-listl5:12345e7:comment10:no comment10:created by2:me13:creation datei0e8:encoding5:utf-84:infod5:filesle4:name6:folder12:piece lengthi5e6:pieces6:1234567:privatei0eee"
+			"d8:announce2:no13:announce-listll5:12345ee7:comment10:no comment10:created by2:me13:creation datei0e8:encoding5:utf-84:infod5:filesle4:name6:folder12:piece lengthi5e6:pieces6:1234567:privatei0eee"
 			),
 			Ok(Ok(MetaInfo {
 				info: Info {
 					piece_length: 5,
 					pieces: "123456".into(),
 					private: Some(false),
+					meta_version: None,
 					file_info: FileInfo::Multi {
 						files: vec![],
 						name: "folder".into(),
 					}
 				},
 				announce: "no".into(),
-				announce_list: Some(vec!["12345".into()]),
+				announce_list: Some(vec![vec!["12345".into()]]),
 				comment: Some("no comment".into()),
 				created_by: Some("me".into()),
 				creation_date: Some(0),
 				encoding: Some("utf-8".into()),
+				unknown_info_keys: Dictionary::new(),
 			})));
 	}
+
+	#[test]
+	fn test_metainfo_from_err() {
+		assert_eq!(
+			try_decode_from::<MetaInfo, _>("d8:announce0:e"),
+			Ok(Err(MetaInfoError::MissingKey("info")))
+		);
+		assert_eq!(
+			try_decode_from::<MetaInfo, _>("d8:announce0:4:info0:e"),
+			Ok(Err(MetaInfoError::WrongType("info")))
+		);
+		assert_eq!(
+			try_decode_from::<MetaInfo, _>(
+				"d8:announce0:13:creation date3:now4:infod6:lengthi2e4:name4:file12:piece lengthi1e6:pieces0:ee"
+			),
+			Ok(Err(MetaInfoError::WrongType("creation date")))
+		);
+		// nested errors keep their key
+		assert_eq!(
+			try_decode_from::<MetaInfo, _>(
+				"d8:announce0:4:infod6:lengthi2e6:md5sum1:a4:name4:file12:piece lengthi1e6:pieces0:ee"
+			),
+			Ok(Err(MetaInfoError::BadLength("md5sum")))
+		);
+		assert_eq!(
+			try_decode_from::<MetaInfo, _>("le"),
+			Ok(Err(MetaInfoError::WrongType("")))
+		);
+	}
+
+	#[test]
+	fn test_to_json() {
+		let single = MetaInfo {
+			info: Info {
+				piece_length: 16384,
+				pieces: vec![b'a'; 20],
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Single {
+					length: 2,
+					md5sum: None,
+					name: "file".into(),
+				},
+			},
+			announce: "http://127.0.0.1:3000/announce".into(),
+			announce_list: None,
+			comment: Some("a \"quoted\" comment".into()),
+			created_by: None,
+			creation_date: Some(1700000000),
+			encoding: None,
+			unknown_info_keys: Dictionary::new(),
+		};
+		assert_eq!(
+			single.to_json(),
+			concat!(
+				r#"{"announce":"http://127.0.0.1:3000/announce","comment":"a \"quoted\" comment","#,
+				r#""creation date":1700000000,"#,
+				r#""info":{"length":2,"name":"file","piece length":16384,"#,
+				r#""pieces":"6161616161616161616161616161616161616161"},"#,
+				r#""info hash":"936207e049dc6f3d8ba61338b67cb210074cae60"}"#
+			)
+		);
+
+		let multi = MetaInfo {
+			info: Info {
+				piece_length: 16384,
+				pieces: vec![0xff; 20],
+				private: Some(true),
+				meta_version: None,
+				file_info: FileInfo::Multi {
+					name: "folder".into(),
+					files: vec![
+						File {
+							length: 1,
+							md5sum: None,
+							path: vec!["a".into()],
+						},
+						File {
+							length: 2,
+							md5sum: None,
+							path: vec!["b".into(), "c".into()],
+						},
+					],
+				},
+			},
+			announce: "".into(),
+			announce_list: Some(vec![vec!["x".into()], vec!["y".into()]]),
+			comment: None,
+			created_by: None,
+			creation_date: None,
+			encoding: None,
+			unknown_info_keys: Dictionary::new(),
+		};
+		let json = multi.to_json();
+		assert!(json.starts_with(concat!(
+			r#"{"announce":"","announce-list":[["x"],["y"]],"#,
+			r#""info":{"files":[{"length":1,"path":["a"]},{"length":2,"path":["b","c"]}],"#,
+			r#""name":"folder","piece length":16384,"#,
+			r#""pieces":"ffffffffffffffffffffffffffffffffffffffff","private":1},"#,
+			r#""info hash":""#
+		)));
+		assert_eq!(
+			json,
+			format!(
+				"{}{}\"}}",
+				&json[..json.len() - 42],
+				multi.info_hash().to_hex_string()
+			)
+		);
+	}
+
+	#[test]
+	fn test_raw_info() {
+		let info = "d6:lengthi2e4:name4:file12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaa8:x-custom5:helloe";
+		let torrent = format!("d8:announce0:4:info{}e", info);
+		let meta_info: MetaInfo = try_decode_from(torrent.clone()).unwrap().unwrap();
+
+		assert_eq!(
+			meta_info.raw_info(),
+			&Dictionary::from(vec![("x-custom", "hello")])
+		);
+		// the unknown key is still hashed and encoded
+		assert_eq!(
+			meta_info.info_hash(),
+			sha1_smol::Sha1::from(info).digest().bytes()
+		);
+		assert_eq!(encode(meta_info.clone()), torrent.as_bytes());
+
+		// `info` is what's encoded, with the unknown key alongside
+		let mut changed = meta_info;
+		changed.info.private = Some(true);
+		assert_eq!(
+			encode(changed.clone()),
+			torrent
+				.replace("8:x-custom", "7:privatei1e8:x-custom")
+				.as_bytes()
+		);
+
+		let built = MetaInfo::new(changed.info, "".into());
+		assert!(built.raw_info().is_empty());
+	}
+
+	#[test]
+	fn test_from_bytes_auto() {
+		let plain =
+			b"d8:announce0:4:infod6:lengthi2e4:name4:file12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+		let gzipped = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x6d\x89\x51\x0a\x80\x20\x10\x05\xaf\xd2\x11\xd4\x44\xe4\xdd\x66\xd1\x67\x2d\xd8\x1a\x54\xf7\x2f\xa8\xcf\xe6\x63\x3e\x66\x6a\x86\x98\x8d\xcb\x0a\x1d\x22\xd4\xda\xa8\x09\x9d\xb6\x9c\xab\x06\x46\x98\x6c\x8f\x9b\x76\xfa\x80\x5d\x59\x38\x7d\xd7\xa7\x39\x47\xa6\x37\x1e\xc1\x41\x7e\x20\x6f\x1d\x29\x3a\xa3\x62\x00\x00\x00";
+
+		let from_plain = MetaInfo::from_bytes_auto(plain).unwrap();
+		assert_eq!(
+			from_plain.info_hash().to_hex_string(),
+			"936207e049dc6f3d8ba61338b67cb210074cae60"
+		);
+		assert_eq!(MetaInfo::from_bytes_auto(gzipped).unwrap(), from_plain);
+
+		let kind = |bytes: &[u8]| MetaInfo::from_bytes_auto(bytes).unwrap_err().kind();
+		assert_eq!(kind(b"d8:announce0:e"), io::ErrorKind::InvalidData);
+		assert_eq!(kind(b"not bencode"), io::ErrorKind::InvalidData);
+		// truncated
+		assert_eq!(kind(&gzipped[..40]), io::ErrorKind::InvalidData);
+	}
 }
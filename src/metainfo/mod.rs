@@ -1,9 +1,19 @@
+mod diff;
+mod error;
 mod file;
 mod file_info;
+mod file_tree;
 mod info;
+mod magnet;
 mod meta_info;
 
+#[allow(unused_imports)]
+pub use diff::FieldDiff;
+pub use error::MetaInfoError;
 pub use file::File;
 pub use file_info::FileInfo;
+pub use file_tree::FileTree;
 pub use info::Info;
+#[allow(unused_imports)]
+pub use magnet::Magnet;
 pub use meta_info::MetaInfo;
@@ -0,0 +1,137 @@
+/// Which pieces a peer has, as sent in the `bitfield` message: the high bit of the first byte is
+/// piece 0.  spare bits at the end of the last byte are always zero.
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Bitfield {
+	bytes: Vec<u8>,
+	num_pieces: usize,
+}
+
+#[allow(dead_code)]
+impl Bitfield {
+	/// a bitfield with none of `num_pieces` set
+	pub fn new(num_pieces: usize) -> Self {
+		Self {
+			bytes: vec![0; num_pieces.div_ceil(8)],
+			num_pieces,
+		}
+	}
+
+	/// whether piece `index` is set.  pieces past the end never are.
+	pub fn get(&self, index: usize) -> bool {
+		index < self.num_pieces && self.bytes[index / 8] & (0x80 >> (index % 8)) != 0
+	}
+
+	/// sets piece `index`, panicking if it's past the end (which would set a spare bit)
+	pub fn set(&mut self, index: usize) {
+		assert!(
+			index < self.num_pieces,
+			"Piece {} out of range of {} pieces.",
+			index,
+			self.num_pieces
+		);
+		self.bytes[index / 8] |= 0x80 >> (index % 8);
+	}
+
+	pub fn count_set(&self) -> usize {
+		self.bytes
+			.iter()
+			.map(|byte| byte.count_ones() as usize)
+			.sum()
+	}
+
+	pub fn is_complete(&self) -> bool {
+		self.count_set() == self.num_pieces
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+/// a received bitfield, given the number of pieces in the torrent
+impl TryFrom<(&[u8], usize)> for Bitfield {
+	type Error = &'static str;
+
+	fn try_from((bytes, num_pieces): (&[u8], usize)) -> Result<Self, Self::Error> {
+		if bytes.len() != num_pieces.div_ceil(8) {
+			return Err("Bitfield is the wrong length for the number of pieces.");
+		}
+		let spare = bytes.len() * 8 - num_pieces;
+		// spare < 8, so this is the low `spare` bits of the last byte
+		if bytes
+			.last()
+			.is_some_and(|last| last & ((1 << spare) - 1) != 0)
+		{
+			return Err("Bitfield has spare bits set.");
+		}
+		Ok(Self {
+			bytes: bytes.to_vec(),
+			num_pieces,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Bitfield;
+
+	#[test]
+	fn test_get_set() {
+		let mut bitfield = Bitfield::new(10);
+		assert_eq!(bitfield.as_bytes(), [0, 0]);
+		assert!(!bitfield.get(0));
+
+		bitfield.set(0);
+		bitfield.set(9);
+		bitfield.set(9);
+		assert_eq!(bitfield.as_bytes(), [0b1000_0000, 0b0100_0000]);
+		assert!(bitfield.get(0));
+		assert!(!bitfield.get(1));
+		assert!(bitfield.get(9));
+		assert!(!bitfield.get(10));
+		assert_eq!(bitfield.count_set(), 2);
+	}
+
+	#[test]
+	#[should_panic]
+	fn test_set_out_of_range() {
+		Bitfield::new(10).set(10);
+	}
+
+	#[test]
+	fn test_is_complete() {
+		let mut bitfield = Bitfield::new(9);
+		for i in 0..8 {
+			bitfield.set(i);
+		}
+		assert!(!bitfield.is_complete());
+		bitfield.set(8);
+		assert!(bitfield.is_complete());
+
+		assert!(Bitfield::new(0).is_complete());
+	}
+
+	#[test]
+	fn test_try_from() {
+		let bitfield = Bitfield::try_from((&[0xff, 0b1100_0000][..], 10)).unwrap();
+		assert!(bitfield.is_complete());
+		assert_eq!(
+			Bitfield::try_from((&[0xff][..], 8)).map(|b| b.count_set()),
+			Ok(8)
+		);
+
+		assert_eq!(
+			Bitfield::try_from((&[0xff, 0b1110_0000][..], 10)),
+			Err("Bitfield has spare bits set.")
+		);
+		assert_eq!(
+			Bitfield::try_from((&[0xff][..], 10)),
+			Err("Bitfield is the wrong length for the number of pieces.")
+		);
+		assert_eq!(
+			Bitfield::try_from((&[0, 0, 0][..], 10)),
+			Err("Bitfield is the wrong length for the number of pieces.")
+		);
+	}
+}
@@ -1,17 +1,33 @@
 use std::process;
 
+#[derive(Debug, PartialEq, Clone)]
 pub enum Protocol {
 	BITTORRENT,
+	/// any other protocol string, at most 255 bytes
+	Other(String),
 }
 
-impl Into<&'static str> for Protocol {
-	fn into(self) -> &'static str {
+impl Protocol {
+	pub fn as_str(&self) -> &str {
 		match self {
 			Self::BITTORRENT => "BitTorrent protocol",
+			Self::Other(pstr) => pstr,
 		}
 	}
 }
 
+impl From<&str> for Protocol {
+	fn from(pstr: &str) -> Self {
+		match pstr {
+			"BitTorrent protocol" => Self::BITTORRENT,
+			pstr => Self::Other(pstr.into()),
+		}
+	}
+}
+
+/// bit of the last reserved byte for DHT support (BEP 5)
+pub const DHT: u8 = 0x01;
+
 pub struct Handshake {
 	pub protocol: Protocol,
 	pub reserved: [u8; 8],
@@ -22,8 +38,7 @@ pub struct Handshake {
 impl Into<Vec<u8>> for Handshake {
 	fn into(self) -> Vec<u8> {
 		let mut vec = Vec::new();
-		let pstr: &str = self.protocol.into();
-		let mut pstr: Vec<u8> = pstr.into();
+		let mut pstr: Vec<u8> = self.protocol.as_str().into();
 
 		vec.push(
 			pstr.len()
@@ -51,3 +66,32 @@ pub fn peer_id() -> [u8; 20] {
 		.try_into()
 		.expect("Error generating version string: ")
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{Handshake, Protocol};
+
+	#[test]
+	fn test_protocol_round_trip() {
+		for protocol in [
+			Protocol::BITTORRENT,
+			Protocol::Other("parrot protocol".into()),
+		] {
+			assert_eq!(Protocol::from(protocol.as_str()), protocol);
+		}
+		assert_eq!(Protocol::from("BitTorrent protocol"), Protocol::BITTORRENT);
+	}
+
+	#[test]
+	fn test_handshake_into() {
+		let handshake: Vec<u8> = Handshake {
+			protocol: Protocol::Other("custom".into()),
+			reserved: [0; 8],
+			info_hash: [1; 20],
+			peer_id: [2; 20],
+		}
+		.into();
+		assert_eq!(&handshake[..7], b"\x06custom");
+		assert_eq!(handshake.len(), 1 + 6 + 8 + 20 + 20);
+	}
+}
@@ -1,6 +1,9 @@
+mod bitfield;
 mod handshake;
 mod peer;
 
+#[allow(unused_imports)]
+pub use bitfield::Bitfield;
 pub use handshake::*;
 pub use peer::Peer;
 
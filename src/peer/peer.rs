@@ -1,18 +1,21 @@
 use std::{
 	io::{self, Read, Write},
 	net::{SocketAddr, TcpListener},
-	sync::mpsc::Sender,
+	sync::{mpsc::Sender, Arc},
 };
 
-use crate::{config::Config, Handler};
+use crate::{
+	bytes::BytesExt, config::Config, rate_limit::RateLimiter, tracker::Swarms, Handler,
+	CONNECTION_TIMEOUT,
+};
 
-use super::{Handshake, Protocol};
+use super::{Handshake, Protocol, DHT};
 
 macro_rules! read_exact {
 	($stream: expr, $buf: expr) => {
 		if let Err(e) = $stream.read_exact(&mut $buf) {
 			if e.kind() != io::ErrorKind::UnexpectedEof {
-				eprintln!("Error reading from peer: {:?}", e);
+				error!("Error reading from peer: {:?}", e);
 			}
 			return Ok(());
 		};
@@ -23,24 +26,39 @@ pub struct Peer {
 	pub config: Config,
 	pub peer_id: [u8; 20],
 	pub sender: Sender<SocketAddr>,
+	/// shared with the tracker listener
+	pub limiter: Arc<RateLimiter>,
+	/// the tracker's torrents, the only ones handshakes are answered for
+	pub swarms: Swarms,
 }
 
 impl Peer {
-	pub fn listen(&self) -> std::io::Result<()> {
-		let listener = TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], self.config.peer_port)))?;
+	/// binds `peer_port`.  with port 0 the OS picks one, see `local_addr` of the listener.
+	pub fn bind(&self) -> std::io::Result<TcpListener> {
+		TcpListener::bind(SocketAddr::from(([0, 0, 0, 0], self.config.peer_port)))
+	}
 
+	/// handles connections to a listener from `bind`
+	pub fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
 		for stream in listener.incoming() {
 			let stream = match stream {
 				Ok(s) => s,
 				Err(e) => {
-					eprintln!("Error getting peer stream: {:?}", e);
+					error!("Error getting peer stream: {:?}", e);
 					continue;
 				}
 			};
-			if let Err(e) =
-				self.handle_connection(stream.local_addr()?, stream.peer_addr()?, stream)
-			{
-				eprintln!("Error handling peer connection: {:?}", e);
+			if !self.limiter.allow(stream.peer_addr()?.ip()) {
+				continue;
+			}
+			stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+			if let Err(e) = self.clone().handle_connection_timed(
+				stream.local_addr()?,
+				stream.peer_addr()?,
+				stream,
+				CONNECTION_TIMEOUT,
+			) {
+				error!("Error handling peer connection: {:?}", e);
 			};
 		}
 		Ok(())
@@ -68,18 +86,37 @@ impl Handler for Peer {
 		let mut info_hash = [0; 20];
 		read_exact!(stream, info_hash);
 
-		if info_hash != self.config.info_hash {
-			println!(
-				"Dropped peer with unwanted info hash ({:#?}): {:?}",
-				info_hash, remote
-			);
-			return Ok(());
-		}
+		let private = match self
+			.swarms
+			.lock()
+			.expect("Swarms lock poisoned.")
+			.get(&info_hash)
+		{
+			Some(swarm) => swarm.private,
+			None => {
+				info!(
+					"Dropped peer with unwanted info hash ({:#?}): {:?}",
+					info_hash, remote
+				);
+				return Ok(());
+			}
+		};
 
 		let mut peer_id = [0; 20];
 		read_exact!(stream, peer_id);
 
-		println!("Peer: {:?}", remote);
+		if let Some(allowed) = &self.config.allowed_peer_ids {
+			if !allowed.contains(&peer_id) {
+				info!(
+					"Dropped peer with unknown peer id ({}): {:?}",
+					peer_id.to_hex_string(),
+					remote
+				);
+				return Ok(());
+			}
+		}
+
+		info!("Peer: {:?}", remote);
 
 		self.sender
 			.send(remote)
@@ -87,7 +124,8 @@ impl Handler for Peer {
 
 		let handshake: Vec<u8> = Handshake {
 			protocol: Protocol::BITTORRENT,
-			reserved: [0; 8],
+			// private torrents' peers can't be found through the DHT
+			reserved: [0, 0, 0, 0, 0, 0, 0, if private { 0 } else { DHT }],
 			info_hash,
 			peer_id: self.peer_id,
 		}
@@ -98,10 +136,28 @@ impl Handler for Peer {
 
 #[cfg(test)]
 mod tests {
-	use std::sync::mpsc;
+	use std::{
+		collections::{HashMap, HashSet},
+		sync::{mpsc, Arc, Mutex},
+	};
 
 	use super::Peer;
-	use crate::{config::Config, test::assert_stream_eq};
+	use crate::{
+		config::Config,
+		rate_limit::RateLimiter,
+		test::{assert_stream_eq, MockStream},
+		tracker::{Swarm, Swarms},
+		Handler,
+	};
+
+	fn serving(info_hashes: &[[u8; 20]]) -> Swarms {
+		Arc::new(Mutex::new(
+			info_hashes
+				.iter()
+				.map(|&info_hash| (info_hash, Swarm::default()))
+				.collect::<HashMap<_, _>>(),
+		))
+	}
 
 	#[test]
 	fn test_handle_connection() {
@@ -114,6 +170,8 @@ mod tests {
 				peer_id: [3; 20],
 				config,
 				sender: sx.clone(),
+				limiter: Arc::new(RateLimiter::per_minute(0)),
+				swarms: serving(&[[1; 20]]),
 			},
 			"\x13BitTorrent protocol\x00\x00\x00\x00\x00\x00\x00\x00\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02",
 			"127.0.0.1:16384",
@@ -122,4 +180,127 @@ mod tests {
 		);
 		assert_eq!(rx.try_recv(), Ok("192.168.4.47:2000".parse().unwrap()));
 	}
+
+	#[test]
+	fn test_allowed_peer_ids() {
+		let (sx, rx) = mpsc::channel();
+		let peer = Peer {
+			peer_id: [3; 20],
+			config: Config {
+				info_hash: [1; 20],
+				allowed_peer_ids: Some(HashSet::from([[2; 20]])),
+				..Config::default()
+			},
+			sender: sx,
+			limiter: Arc::new(RateLimiter::per_minute(0)),
+			swarms: serving(&[[1; 20]]),
+		};
+		let handshake = |peer_id: u8| {
+			format!(
+				"\x13BitTorrent protocol{}{}{}",
+				"\x00".repeat(8),
+				"\x01".repeat(20),
+				(peer_id as char).to_string().repeat(20)
+			)
+		};
+
+		let mut allowed = MockStream::create(handshake(2).into());
+		peer.handle_connection(
+			"127.0.0.1:16384".parse().unwrap(),
+			"192.168.4.47:2000".parse().unwrap(),
+			&mut allowed,
+		)
+		.unwrap();
+		assert_eq!(allowed.write.len(), 68);
+		assert_eq!(rx.try_recv(), Ok("192.168.4.47:2000".parse().unwrap()));
+
+		let mut disallowed = MockStream::create(handshake(4).into());
+		peer.handle_connection(
+			"127.0.0.1:16384".parse().unwrap(),
+			"192.168.4.48:2000".parse().unwrap(),
+			&mut disallowed,
+		)
+		.unwrap();
+		assert!(disallowed.write.is_empty());
+		rx.try_recv().expect_err("Disallowed peer was sent.");
+	}
+
+	#[test]
+	fn test_served_torrents() {
+		let (sx, rx) = mpsc::channel();
+		let swarms = serving(&[[1; 20]]);
+		let peer = Peer {
+			peer_id: [3; 20],
+			config: Config {
+				info_hash: [1; 20],
+				..Config::default()
+			},
+			sender: sx,
+			limiter: Arc::new(RateLimiter::per_minute(0)),
+			swarms: swarms.clone(),
+		};
+		let handshake = |info_hash: u8| {
+			let mut stream = MockStream::create(
+				format!(
+					"\x13BitTorrent protocol{}{}{}",
+					"\x00".repeat(8),
+					(info_hash as char).to_string().repeat(20),
+					"\x02".repeat(20)
+				)
+				.into(),
+			);
+			peer.handle_connection(
+				"127.0.0.1:16384".parse().unwrap(),
+				"192.168.4.47:2000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap();
+			stream.write.len()
+		};
+
+		assert_eq!(handshake(5), 0);
+		rx.try_recv()
+			.expect_err("Peer for another torrent was sent.");
+
+		// a torrent added to the tracker later
+		swarms.lock().unwrap().insert([5; 20], Swarm::default());
+		assert_eq!(handshake(5), 68);
+		assert_eq!(rx.try_recv(), Ok("192.168.4.47:2000".parse().unwrap()));
+		assert_eq!(handshake(1), 68);
+	}
+
+	#[test]
+	fn test_dht_bit() {
+		let (sx, _rx) = mpsc::channel();
+		let swarms = serving(&[[1; 20]]);
+		let peer = Peer {
+			peer_id: [3; 20],
+			config: Config::default(),
+			sender: sx,
+			limiter: Arc::new(RateLimiter::per_minute(0)),
+			swarms: swarms.clone(),
+		};
+		let reserved = || {
+			let mut stream = MockStream::create(
+				format!(
+					"\x13BitTorrent protocol{}{}{}",
+					"\x00".repeat(8),
+					"\x01".repeat(20),
+					"\x02".repeat(20)
+				)
+				.into(),
+			);
+			peer.handle_connection(
+				"127.0.0.1:16384".parse().unwrap(),
+				"192.168.4.47:2000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap();
+			stream.write[20..28].to_vec()
+		};
+
+		assert_eq!(reserved(), [0; 8]);
+		swarms.lock().unwrap().get_mut(&[1; 20]).unwrap().private = false;
+		assert_eq!(reserved(), [0, 0, 0, 0, 0, 0, 0, 1]);
+	}
 }
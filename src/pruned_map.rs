@@ -0,0 +1,90 @@
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	ops::{Deref, DerefMut},
+	time::{Duration, Instant},
+};
+
+/// A `HashMap` whose stale entries are only dropped every so often, so a busy map isn't gone
+/// through on every access.  Stale entries can still be there in between, so lookups have to check
+/// for themselves.
+#[derive(Debug)]
+pub struct PrunedMap<K, V> {
+	map: HashMap<K, V>,
+	pruned: Instant,
+}
+
+impl<K, V> PrunedMap<K, V> {
+	pub fn new() -> Self {
+		Self {
+			map: HashMap::new(),
+			pruned: Instant::now(),
+		}
+	}
+}
+
+impl<K, V> Default for PrunedMap<K, V> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<K: Eq + Hash, V> PrunedMap<K, V> {
+	/// keeps only the entries `keep` is true for, if it's been `every` since they were last pruned
+	pub fn prune_at(
+		&mut self,
+		now: Instant,
+		every: Duration,
+		keep: impl FnMut(&K, &mut V) -> bool,
+	) {
+		if now.duration_since(self.pruned) >= every {
+			self.map.retain(keep);
+			self.pruned = now;
+		}
+	}
+}
+
+impl<K, V> Deref for PrunedMap<K, V> {
+	type Target = HashMap<K, V>;
+
+	fn deref(&self) -> &Self::Target {
+		&self.map
+	}
+}
+
+impl<K, V> DerefMut for PrunedMap<K, V> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		&mut self.map
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::PrunedMap;
+
+	#[test]
+	fn test_prune_at() {
+		let mut map = PrunedMap::new();
+		let start = Instant::now();
+		let every = Duration::from_secs(60);
+		map.insert(1, 10);
+		map.insert(2, 20);
+
+		// not yet
+		map.prune_at(start + Duration::from_secs(30), every, |_, v| *v > 10);
+		assert_eq!(map.len(), 2);
+
+		map.prune_at(start + Duration::from_secs(60), every, |_, v| *v > 10);
+		assert_eq!(map.get(&1), None);
+		assert_eq!(map.get(&2), Some(&20));
+
+		// counted from the last prune
+		map.insert(3, 0);
+		map.prune_at(start + Duration::from_secs(90), every, |_, v| *v > 10);
+		assert_eq!(map.len(), 2);
+		map.prune_at(start + Duration::from_secs(120), every, |_, v| *v > 10);
+		assert_eq!(map.len(), 1);
+	}
+}
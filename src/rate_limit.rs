@@ -0,0 +1,108 @@
+use std::{
+	net::IpAddr,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use crate::pruned_map::PrunedMap;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// Token bucket per source IP: each IP can burst up to `per_minute` connections, and earns tokens
+/// back continuously at `per_minute` over a minute.
+#[derive(Debug)]
+pub struct RateLimiter {
+	per_minute: u32,
+	buckets: Mutex<PrunedMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+	/// a limit of 0 allows every connection
+	pub fn per_minute(per_minute: u32) -> Self {
+		Self {
+			per_minute,
+			buckets: Mutex::new(PrunedMap::new()),
+		}
+	}
+
+	/// takes a token for a connection from `ip`, returning whether it should be accepted
+	pub fn allow(&self, ip: IpAddr) -> bool {
+		self.allow_at(ip, Instant::now())
+	}
+
+	fn allow_at(&self, ip: IpAddr, now: Instant) -> bool {
+		if self.per_minute == 0 {
+			return true;
+		}
+		let capacity = self.per_minute as f64;
+
+		let mut buckets = self.buckets.lock().expect("Rate limiter lock poisoned.");
+		// full buckets are the same as no bucket
+		buckets.prune_at(now, WINDOW, |_, b| now.duration_since(b.updated) < WINDOW);
+
+		let bucket = buckets.entry(ip).or_insert(Bucket {
+			tokens: capacity,
+			updated: now,
+		});
+
+		let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * capacity / WINDOW.as_secs_f64()).min(capacity);
+		bucket.updated = now;
+
+		if bucket.tokens >= 1.0 {
+			bucket.tokens -= 1.0;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::RateLimiter;
+
+	#[test]
+	fn test_token_bucket() {
+		let limiter = RateLimiter::per_minute(3);
+		let ip = "192.168.7.160".parse().unwrap();
+		let start = Instant::now();
+
+		// burst up to the limit
+		assert!(limiter.allow_at(ip, start));
+		assert!(limiter.allow_at(ip, start));
+		assert!(limiter.allow_at(ip, start));
+		assert!(!limiter.allow_at(ip, start));
+
+		// other ips have their own bucket
+		assert!(limiter.allow_at("10.0.0.1".parse().unwrap(), start));
+
+		// one token refills every 20 seconds
+		assert!(!limiter.allow_at(ip, start + Duration::from_secs(10)));
+		assert!(limiter.allow_at(ip, start + Duration::from_secs(20)));
+		assert!(!limiter.allow_at(ip, start + Duration::from_secs(21)));
+
+		// refills never exceed the burst
+		let later = start + Duration::from_secs(600);
+		for _ in 0..3 {
+			assert!(limiter.allow_at(ip, later));
+		}
+		assert!(!limiter.allow_at(ip, later));
+	}
+
+	#[test]
+	fn test_unlimited() {
+		let limiter = RateLimiter::per_minute(0);
+		let ip = "::1".parse().unwrap();
+		let now = Instant::now();
+		assert!((0..1000).all(|_| limiter.allow_at(ip, now)));
+	}
+}
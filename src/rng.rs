@@ -0,0 +1,95 @@
+use std::{
+	collections::hash_map::RandomState,
+	hash::{BuildHasher, Hasher},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A small non-cryptographic PRNG (splitmix64), for shuffling peers.  seeded, it always gives the
+/// same numbers, which tests rely on.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+	pub fn seeded(seed: u64) -> Self {
+		Self(seed)
+	}
+
+	/// seeded from the time and the random keys std gives each `RandomState`
+	pub fn from_entropy() -> Self {
+		let mut hasher = RandomState::new().build_hasher();
+		hasher.write_u128(
+			SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |d| d.as_nanos()),
+		);
+		Self(hasher.finish())
+	}
+
+	/// `seed` if given, otherwise entropy
+	pub fn new(seed: Option<u64>) -> Self {
+		seed.map_or_else(Self::from_entropy, Self::seeded)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+		z ^ (z >> 31)
+	}
+
+	/// a number from 0 to `n` (exclusive), with a bias too small to matter for shuffling
+	pub fn below(&mut self, n: usize) -> usize {
+		((self.next_u64() as u128 * n as u128) >> 64) as usize
+	}
+
+	/// Fisher-Yates
+	pub fn shuffle<T>(&mut self, items: &mut [T]) {
+		for i in (1..items.len()).rev() {
+			items.swap(i, self.below(i + 1));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Rng;
+
+	#[test]
+	fn test_seeded() {
+		let mut a = Rng::seeded(1234);
+		let mut b = Rng::new(Some(1234));
+		for _ in 0..100 {
+			assert_eq!(a.next_u64(), b.next_u64());
+		}
+		// splitmix64 reference output for seed 0
+		assert_eq!(Rng::seeded(0).next_u64(), 0xe220a8397b1dcdaf);
+		assert_ne!(Rng::seeded(1).next_u64(), Rng::seeded(2).next_u64());
+	}
+
+	#[test]
+	fn test_shuffle() {
+		let mut rng = Rng::seeded(42);
+		for _ in 0..1000 {
+			assert!(rng.below(7) < 7);
+		}
+
+		let mut items: Vec<u32> = (0..50).collect();
+		rng.shuffle(&mut items);
+		assert_ne!(items, (0..50).collect::<Vec<_>>());
+		let mut sorted = items.clone();
+		sorted.sort();
+		assert_eq!(sorted, (0..50).collect::<Vec<_>>());
+
+		let mut again: Vec<u32> = (0..50).collect();
+		let mut rng = Rng::seeded(42);
+		for _ in 0..1000 {
+			rng.below(7);
+		}
+		rng.shuffle(&mut again);
+		assert_eq!(again, items);
+
+		rng.shuffle(&mut Vec::<u32>::new());
+		rng.shuffle(&mut [1]);
+	}
+}
@@ -0,0 +1,146 @@
+use std::{
+	io,
+	net::{IpAddr, SocketAddr, TcpStream},
+};
+
+pub use sys::connect_from;
+
+/// Connects to `remote` from the local address `local` (with any port), for hosts with more than
+/// one interface.  std can't bind a socket before connecting it, so this goes through libc.  The
+/// constants and `sockaddr` layout are those of linux on these architectures, others (e.g. mips
+/// and sparc) number them differently.
+#[cfg(all(
+	target_os = "linux",
+	any(
+		target_arch = "x86",
+		target_arch = "x86_64",
+		target_arch = "arm",
+		target_arch = "aarch64",
+		target_arch = "riscv64"
+	)
+))]
+mod sys {
+	use std::{
+		ffi::c_int,
+		os::fd::{AsRawFd, FromRawFd, OwnedFd},
+	};
+
+	use super::*;
+
+	const SOCK_STREAM: c_int = 1;
+	const SOCK_CLOEXEC: c_int = 0o2000000;
+
+	extern "C" {
+		fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+		fn bind(fd: c_int, addr: *const SockAddr, len: u32) -> c_int;
+		fn connect(fd: c_int, addr: *const SockAddr, len: u32) -> c_int;
+	}
+
+	pub fn connect_from(local: IpAddr, remote: SocketAddr) -> io::Result<TcpStream> {
+		if local.is_ipv4() != remote.is_ipv4() {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"Bind address and remote address are different IP versions.",
+			));
+		}
+
+		let local = SockAddr::new(SocketAddr::new(local, 0));
+		let remote = SockAddr::new(remote);
+
+		// SAFETY: plain syscalls on a socket this function owns; the addresses outlive the calls
+		// and their lengths match the structs they describe.
+		unsafe {
+			let fd = socket(local.family() as c_int, SOCK_STREAM | SOCK_CLOEXEC, 0);
+			if fd < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			// closes the socket if anything below fails
+			let fd = OwnedFd::from_raw_fd(fd);
+
+			if bind(fd.as_raw_fd(), &local, local.len) < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			if connect(fd.as_raw_fd(), &remote, remote.len) < 0 {
+				return Err(io::Error::last_os_error());
+			}
+			Ok(TcpStream::from(fd))
+		}
+	}
+
+	/// `struct sockaddr_in` or `struct sockaddr_in6`, laid out as linux expects
+	#[repr(C, align(4))]
+	struct SockAddr {
+		bytes: [u8; 28],
+		len: u32,
+	}
+
+	impl SockAddr {
+		const AF_INET: u16 = 2;
+		const AF_INET6: u16 = 10;
+
+		fn new(addr: SocketAddr) -> Self {
+			let mut bytes = [0; 28];
+			bytes[2..4].copy_from_slice(&addr.port().to_be_bytes());
+			let len = match addr {
+				SocketAddr::V4(v4) => {
+					bytes[..2].copy_from_slice(&Self::AF_INET.to_ne_bytes());
+					bytes[4..8].copy_from_slice(&v4.ip().octets());
+					16
+				}
+				SocketAddr::V6(v6) => {
+					bytes[..2].copy_from_slice(&Self::AF_INET6.to_ne_bytes());
+					bytes[4..8].copy_from_slice(&v6.flowinfo().to_be_bytes());
+					bytes[8..24].copy_from_slice(&v6.ip().octets());
+					bytes[24..].copy_from_slice(&v6.scope_id().to_ne_bytes());
+					28
+				}
+			};
+			Self { bytes, len }
+		}
+
+		fn family(&self) -> u16 {
+			u16::from_ne_bytes([self.bytes[0], self.bytes[1]])
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use super::*;
+
+		#[test]
+		fn test_sockaddr() {
+			let v4 = SockAddr::new("127.0.0.1:6881".parse().unwrap());
+			assert_eq!(v4.len, 16);
+			assert_eq!(v4.family(), SockAddr::AF_INET);
+			assert_eq!(&v4.bytes[2..8], &[0x1a, 0xe1, 127, 0, 0, 1]);
+			assert_eq!(&v4.bytes[8..], &[0; 20]);
+
+			let v6 = SockAddr::new("[::1]:80".parse().unwrap());
+			assert_eq!(v6.len, 28);
+			assert_eq!(v6.family(), SockAddr::AF_INET6);
+			assert_eq!(&v6.bytes[2..4], &[0, 80]);
+			assert_eq!(v6.bytes[23], 1);
+		}
+	}
+}
+
+#[cfg(not(all(
+	target_os = "linux",
+	any(
+		target_arch = "x86",
+		target_arch = "x86_64",
+		target_arch = "arm",
+		target_arch = "aarch64",
+		target_arch = "riscv64"
+	)
+)))]
+mod sys {
+	use super::*;
+
+	pub fn connect_from(_: IpAddr, _: SocketAddr) -> io::Result<TcpStream> {
+		Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			"Binding the source address isn't supported on this platform.",
+		))
+	}
+}
@@ -5,23 +5,29 @@ mod test {
 	pub struct MockStream {
 		pub read: Vec<u8>,
 		pub write: Vec<u8>,
+		/// most bytes returned by one `read`, like a socket receiving the data in pieces
+		pub chunk_size: usize,
 	}
 
 	impl MockStream {
 		pub fn create(read: Vec<u8>) -> Self {
+			Self::with_chunk_size(read, usize::MAX)
+		}
+
+		pub fn with_chunk_size(read: Vec<u8>, chunk_size: usize) -> Self {
 			Self {
 				read: Vec::from(read),
 				write: Vec::new(),
+				chunk_size,
 			}
 		}
 	}
 
 	impl Read for MockStream {
 		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-			let size = buf.len().min(self.read.len());
-			let mut values: Vec<u8> = self.read.drain(..size).collect();
-			values.resize(buf.len(), 0);
-			buf.copy_from_slice(values.as_slice());
+			let size = buf.len().min(self.read.len()).min(self.chunk_size);
+			buf[..size].copy_from_slice(&self.read[..size]);
+			self.read.drain(..size);
 			Ok(size)
 		}
 	}
@@ -44,17 +50,55 @@ mod test {
 		local: &'static str,
 		remote: &'static str,
 		result: impl Into<Vec<u8>>,
+	) {
+		assert_stream_eq_chunked(handler, read, local, remote, result, usize::MAX)
+	}
+
+	/// like `assert_stream_eq`, reading at most `chunk_size` bytes at a time
+	#[cfg(test)]
+	pub fn assert_stream_eq_chunked(
+		handler: impl Handler,
+		read: impl Into<Vec<u8>>,
+		local: &'static str,
+		remote: &'static str,
+		result: impl Into<Vec<u8>>,
+		chunk_size: usize,
 	) {
 		use crate::bytes::assert_bytes_eq;
 
-		let mut stream = MockStream::create(read.into());
+		let mut stream = MockStream::with_chunk_size(read.into(), chunk_size);
 		handler
 			.handle_connection(local.parse().unwrap(), remote.parse().unwrap(), &mut stream)
 			.expect("Error handling connection: ");
 
 		assert_bytes_eq(stream.write, result);
 	}
+
+	#[test]
+	fn test_chunk_size() {
+		let mut stream = MockStream::with_chunk_size(b"abc".to_vec(), 1);
+		let mut buf = [0; 8];
+		assert_eq!(stream.read(&mut buf).unwrap(), 1);
+		assert_eq!(buf[0], b'a');
+
+		let mut rest = Vec::new();
+		stream.read_to_end(&mut rest).unwrap();
+		assert_eq!(rest, b"bc");
+		assert_eq!(stream.read(&mut buf).unwrap(), 0);
+	}
+
+	#[test]
+	fn test_read_leaves_rest_of_buffer() {
+		let mut stream = MockStream::create(b"abc".to_vec());
+		let mut buf = [0xff; 8];
+		assert_eq!(stream.read(&mut buf).unwrap(), 3);
+		assert_eq!(buf, [b'a', b'b', b'c', 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+		// nothing left, so nothing is written
+		assert_eq!(stream.read(&mut buf).unwrap(), 0);
+		assert_eq!(buf, [b'a', b'b', b'c', 0xff, 0xff, 0xff, 0xff, 0xff]);
+	}
 }
 
 #[cfg(test)]
-pub use test::{assert_stream_eq, MockStream};
+pub use test::{assert_stream_eq, assert_stream_eq_chunked, MockStream};
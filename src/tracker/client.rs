@@ -0,0 +1,634 @@
+use std::{
+	io::{self, Read, Write},
+	net::{IpAddr, TcpStream, ToSocketAddrs},
+	thread,
+	time::Duration,
+};
+
+use super::{TrackerEvent, TrackerRequest, TrackerResponse};
+use crate::{bencode, gzip, metainfo::MetaInfo, peer, socket};
+
+/// what the client reports about itself in an announce
+#[derive(Debug, Clone, Default)]
+pub struct AnnounceStats {
+	/// port the client is listening on
+	pub port: u16,
+	pub uploaded: u64,
+	pub downloaded: u64,
+	pub left: u64,
+	/// `tracker id` from a previous response, echoed back as `trackerid`
+	pub tracker_id: Option<String>,
+}
+
+/// longest a gzipped tracker response may decompress to
+const MAX_RESPONSE_LEN: usize = 1024 * 1024;
+
+/// the parts of an `http://` announce url needed to make a request
+#[derive(Debug, PartialEq)]
+struct AnnounceUrl {
+	host: String,
+	port: u16,
+	/// path including any query string already in the url
+	path: String,
+}
+
+impl AnnounceUrl {
+	fn parse(url: &[u8]) -> io::Result<Self> {
+		let invalid = |msg| io::Error::new(io::ErrorKind::InvalidInput, msg);
+
+		let url = std::str::from_utf8(url).map_err(|_| invalid("Announce url is not UTF-8."))?;
+		let rest = url.strip_prefix("http://").ok_or(io::Error::new(
+			io::ErrorKind::Unsupported,
+			"Only http announce urls are supported.",
+		))?;
+
+		let (authority, path) = match rest.find(['/', '?']) {
+			Some(i) => (&rest[..i], &rest[i..]),
+			None => (rest, "/"),
+		};
+
+		let (host, port) = match authority.rsplit_once(':') {
+			// don't split inside an ipv6 literal like [::1]
+			Some((host, port)) if !port.contains(']') => (
+				host,
+				port.parse()
+					.map_err(|_| invalid("Invalid port in announce url."))?,
+			),
+			_ => (authority, 80),
+		};
+
+		if host.is_empty() {
+			return Err(invalid("Missing host in announce url."));
+		}
+
+		Ok(Self {
+			host: host.trim_start_matches('[').trim_end_matches(']').into(),
+			port,
+			path: path.into(),
+		})
+	}
+
+	/// the `Host` header's value, with an ipv6 literal in brackets again
+	fn host_header(&self) -> String {
+		if self.host.contains(':') {
+			format!("[{}]:{}", self.host, self.port)
+		} else {
+			format!("{}:{}", self.host, self.port)
+		}
+	}
+}
+
+/// extracts the body of an http response, failing on anything but a 200.  gzip bodies are
+/// decompressed.
+fn parse_http_response(response: &[u8]) -> io::Result<Vec<u8>> {
+	let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+	let split = response
+		.windows(4)
+		.position(|w| w == b"\r\n\r\n")
+		.ok_or(invalid("Incomplete HTTP response."))?;
+	let head = std::str::from_utf8(&response[..split])
+		.map_err(|_| invalid("HTTP response headers are not UTF-8."))?;
+	let mut body = &response[split + 4..];
+
+	let mut lines = head.split("\r\n");
+	let status = lines.next().unwrap_or_default();
+	match status.split(' ').nth(1) {
+		Some("200") => (),
+		Some(_) => {
+			return Err(io::Error::other(format!(
+				"Tracker responded with {:?}",
+				status
+			)))
+		}
+		None => return Err(invalid("Invalid HTTP status line.")),
+	}
+
+	let headers: Vec<_> = lines
+		.filter_map(|line| line.split_once(':'))
+		.map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+		.collect();
+
+	if let Some((_, len)) = headers.iter().find(|(k, _)| k == "content-length") {
+		let len = len
+			.parse()
+			.map_err(|_| invalid("Invalid Content-Length."))?;
+		body = body.get(..len).ok_or(invalid("Truncated HTTP body."))?;
+	}
+
+	match headers.iter().find(|(k, _)| k == "content-encoding") {
+		Some((_, encoding)) if encoding.eq_ignore_ascii_case("gzip") => {
+			gzip::decompress(body, MAX_RESPONSE_LEN)
+		}
+		Some((_, encoding)) if !encoding.eq_ignore_ascii_case("identity") => {
+			Err(invalid("Unsupported Content-Encoding in tracker response."))
+		}
+		_ => Ok(body.to_vec()),
+	}
+}
+
+fn announce_over(
+	mut stream: impl Read + Write,
+	url: &AnnounceUrl,
+	request: TrackerRequest,
+) -> io::Result<TrackerResponse> {
+	let query =
+		String::from_utf8(super::encode(request.into())).expect("query string is url encoded");
+	let separator = if url.path.contains('?') { '&' } else { '?' };
+
+	// HTTP/1.0 so the tracker can't answer with a chunked body
+	write!(
+		stream,
+		"GET {}{}{} HTTP/1.0\r\nHost: {}\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+		url.path,
+		separator,
+		query,
+		url.host_header()
+	)?;
+	stream.flush()?;
+
+	let mut response = Vec::new();
+	stream.read_to_end(&mut response)?;
+
+	let body = parse_http_response(&response)?;
+
+	match bencode::try_decode_from(body) {
+		Ok(Ok(response)) => Ok(response),
+		Ok(Err(_)) => Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			"Invalid tracker response.",
+		)),
+		Err(e) => Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("Invalid bencode in tracker response: {:?}", e),
+		)),
+	}
+}
+
+fn build_request(metainfo: &MetaInfo, event: TrackerEvent, stats: AnnounceStats) -> TrackerRequest {
+	TrackerRequest::builder(metainfo.info_hash(), peer::peer_id(), stats.port)
+		.uploaded(stats.uploaded)
+		.downloaded(stats.downloaded)
+		.left(stats.left)
+		.event(event)
+		.trackerid(stats.tracker_id)
+		.build()
+}
+
+fn connect(url: &AnnounceUrl, bind: Option<IpAddr>) -> io::Result<TcpStream> {
+	let bind = match bind {
+		Some(ip) => ip,
+		None => return TcpStream::connect((url.host.as_str(), url.port)),
+	};
+
+	let remote = (url.host.as_str(), url.port)
+		.to_socket_addrs()?
+		.find(|addr| addr.is_ipv4() == bind.is_ipv4())
+		.ok_or(io::Error::new(
+			io::ErrorKind::NotFound,
+			"Tracker has no address of the same IP version as the bind address.",
+		))?;
+	socket::connect_from(bind, remote)
+}
+
+/// announces to the torrent's tracker as a client, returning the tracker's parsed response.  With
+/// `bind`, the connection is made from that local address.
+#[allow(dead_code)]
+pub fn announce(
+	metainfo: &MetaInfo,
+	event: TrackerEvent,
+	stats: AnnounceStats,
+	bind: Option<IpAddr>,
+) -> io::Result<TrackerResponse> {
+	let url = AnnounceUrl::parse(&metainfo.announce)?;
+	let stream = connect(&url, bind)?;
+	announce_over(stream, &url, build_request(metainfo, event, stats))
+}
+
+/// delay before the first retry when the tracker hasn't given a `min interval`, doubled each time
+/// after
+const RETRY_DELAY: Duration = Duration::from_secs(15);
+
+/// whether the error could go away by trying again, as opposed to a bad url or response
+fn is_transient(e: &io::Error) -> bool {
+	!matches!(
+		e.kind(),
+		io::ErrorKind::InvalidInput | io::ErrorKind::InvalidData | io::ErrorKind::Unsupported
+	)
+}
+
+fn retry<T>(
+	max_retries: u32,
+	mut delay: Duration,
+	mut attempt: impl FnMut() -> io::Result<T>,
+	mut sleep: impl FnMut(Duration),
+) -> io::Result<T> {
+	let mut retries = 0;
+	loop {
+		match attempt() {
+			Err(e) if retries < max_retries && is_transient(&e) => {
+				warn!("Announce failed ({}), retrying in {:?}", e, delay);
+				sleep(delay);
+				delay *= 2;
+				retries += 1;
+			}
+			result => return result,
+		}
+	}
+}
+
+/// `announce`, retried up to `max_retries` times with exponential backoff if the tracker can't be
+/// reached.  The first retry waits the `min interval` of the tracker's last response, if there was
+/// one.  A `failure reason` from the tracker is returned as is.
+#[allow(dead_code)]
+pub fn announce_with_retry(
+	metainfo: &MetaInfo,
+	event: TrackerEvent,
+	stats: AnnounceStats,
+	bind: Option<IpAddr>,
+	max_retries: u32,
+	min_interval: Option<u64>,
+) -> io::Result<TrackerResponse> {
+	retry(
+		max_retries,
+		min_interval.map_or(RETRY_DELAY, Duration::from_secs),
+		|| announce(metainfo, event.clone(), stats.clone(), bind),
+		thread::sleep,
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		metainfo::{FileInfo, Info},
+		test::MockStream,
+		tracker::{self, Peers},
+	};
+
+	fn metainfo() -> MetaInfo {
+		MetaInfo::new(
+			Info {
+				piece_length: 16384,
+				pieces: vec![b'a'; 20],
+				private: None,
+				meta_version: None,
+				file_info: FileInfo::Single {
+					length: 2,
+					md5sum: None,
+					name: "file".into(),
+				},
+			},
+			"http://tracker.example:6969/announce".into(),
+		)
+	}
+
+	#[test]
+	fn test_announce_url() {
+		assert_eq!(
+			AnnounceUrl::parse(b"http://tracker.example:6969/announce").unwrap(),
+			AnnounceUrl {
+				host: "tracker.example".into(),
+				port: 6969,
+				path: "/announce".into()
+			}
+		);
+		assert_eq!(
+			AnnounceUrl::parse(b"http://[::1]/a?passkey=x").unwrap(),
+			AnnounceUrl {
+				host: "::1".into(),
+				port: 80,
+				path: "/a?passkey=x".into()
+			}
+		);
+		assert_eq!(AnnounceUrl::parse(b"http://example.com").unwrap().path, "/");
+
+		let host = |url: &[u8]| AnnounceUrl::parse(url).unwrap().host_header();
+		assert_eq!(host(b"http://example.com/announce"), "example.com:80");
+		assert_eq!(host(b"http://[::1]:6969/announce"), "[::1]:6969");
+
+		assert!(AnnounceUrl::parse(b"udp://tracker.example:80").is_err());
+		assert!(AnnounceUrl::parse(b"http://:80/announce").is_err());
+		assert!(AnnounceUrl::parse(b"http://host:port/announce").is_err());
+	}
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn test_connect_bind() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = AnnounceUrl {
+			host: "127.0.0.1".into(),
+			port: listener.local_addr().unwrap().port(),
+			path: "/announce".into(),
+		};
+		let bind = |ip: &str| connect(&url, Some(ip.parse().unwrap())).map_err(|e| e.kind());
+
+		assert_eq!(bind("::1").unwrap_err(), io::ErrorKind::NotFound);
+
+		// all of 127.0.0.0/8 is loopback on linux, but not every architecture can bind
+		let stream = match bind("127.0.0.2") {
+			Err(io::ErrorKind::Unsupported) => return,
+			stream => stream.unwrap(),
+		};
+		assert_eq!(stream.local_addr().unwrap().ip().to_string(), "127.0.0.2");
+		assert_eq!(listener.accept().unwrap().1.ip().to_string(), "127.0.0.2");
+	}
+
+	#[test]
+	fn test_announce_over() {
+		let body =
+			b"d8:completei2e10:incompletei3e8:intervali900e5:peers6:\x7f\x00\x00\x01\x1f\x90e";
+		let mut response =
+			format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len()).into_bytes();
+		response.extend_from_slice(body);
+		let mut stream = MockStream::create(response);
+
+		let url = AnnounceUrl::parse(&metainfo().announce).unwrap();
+		let response = announce_over(
+			&mut stream,
+			&url,
+			build_request(
+				&metainfo(),
+				TrackerEvent::STARTED,
+				AnnounceStats {
+					port: 16384,
+					uploaded: 1,
+					downloaded: 2,
+					left: 3,
+					tracker_id: None,
+				},
+			),
+		)
+		.unwrap();
+
+		assert_eq!(
+			response,
+			TrackerResponse::Ok {
+				interval: 900,
+				min_interval: None,
+				tracker_id: None,
+				complete: 2,
+				incomplete: 3,
+				peers: Peers::Compact(vec![127, 0, 0, 1, 0x1f, 0x90]),
+				peers6: None,
+				warning_message: None,
+			}
+		);
+
+		// the request sent is a parseable announce
+		let sent = String::from_utf8(stream.write.clone()).unwrap();
+		let (request_line, headers) = sent.split_once("\r\n").unwrap();
+		assert!(headers.starts_with("Host: tracker.example:6969\r\n"));
+		let query = request_line
+			.strip_prefix("GET /announce?")
+			.unwrap()
+			.strip_suffix(" HTTP/1.0")
+			.unwrap();
+		let mut query = tracker::decode(query).unwrap();
+		assert_eq!(query.get("compact"), Some(&b"1"[..]));
+		assert_eq!(query.remove("event"), Some("started".into()));
+		let request = TrackerRequest::try_from(query).unwrap();
+		assert_eq!(request.info_hash, metainfo().info_hash());
+		assert_eq!(request.peer_id, peer::peer_id());
+		assert_eq!(
+			(
+				request.port,
+				request.uploaded,
+				request.downloaded,
+				request.left
+			),
+			(16384, 1, 2, 3)
+		);
+	}
+
+	#[test]
+	fn test_announce_failure() {
+		let mut stream =
+			MockStream::create("HTTP/1.1 200 OK\r\n\r\nd14:failure reason9:not todaye".into());
+		let url = AnnounceUrl::parse(&metainfo().announce).unwrap();
+		let request = build_request(&metainfo(), TrackerEvent::REGULAR, AnnounceStats::default());
+
+		assert_eq!(
+			announce_over(&mut stream, &url, request).unwrap(),
+			TrackerResponse::Err("not today".into())
+		);
+		// regular announces don't send an event
+		assert!(!String::from_utf8(stream.write).unwrap().contains("event="));
+
+		// http errors
+		let mut stream = MockStream::create("HTTP/1.1 404 Not Found\r\n\r\n".into());
+		let request = build_request(&metainfo(), TrackerEvent::REGULAR, AnnounceStats::default());
+		assert!(announce_over(&mut stream, &url, request).is_err());
+	}
+
+	#[test]
+	fn test_announce_tracker_id() {
+		let body = b"d8:completei5e10:incompletei6e8:intervali900e12:min intervali60e5:peers0:10:tracker id3:abc15:warning message4:slowe";
+		let mut response = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+		response.extend_from_slice(body);
+		let mut stream = MockStream::create(response);
+		let url = AnnounceUrl::parse(&metainfo().announce).unwrap();
+		let request = build_request(&metainfo(), TrackerEvent::STARTED, AnnounceStats::default());
+
+		let response = announce_over(&mut stream, &url, request).unwrap();
+		assert_eq!(
+			response,
+			TrackerResponse::Ok {
+				interval: 900,
+				min_interval: Some(60),
+				tracker_id: Some("abc".into()),
+				complete: 5,
+				incomplete: 6,
+				peers: Peers::Compact(vec![]),
+				peers6: None,
+				warning_message: Some("slow".into()),
+			}
+		);
+		assert!(!String::from_utf8(stream.write)
+			.unwrap()
+			.contains("trackerid="));
+
+		let tracker_id = match response {
+			TrackerResponse::Ok { tracker_id, .. } => tracker_id,
+			TrackerResponse::Err(_) => unreachable!(),
+		};
+		let mut stream =
+			MockStream::create("HTTP/1.1 200 OK\r\n\r\nd8:intervali900e5:peers0:e".into());
+		let request = build_request(
+			&metainfo(),
+			TrackerEvent::REGULAR,
+			AnnounceStats {
+				tracker_id,
+				..AnnounceStats::default()
+			},
+		);
+
+		// optional fields absent
+		assert_eq!(
+			announce_over(&mut stream, &url, request).unwrap(),
+			TrackerResponse::Ok {
+				interval: 900,
+				min_interval: None,
+				tracker_id: None,
+				complete: 0,
+				incomplete: 0,
+				peers: Peers::Compact(vec![]),
+				peers6: None,
+				warning_message: None,
+			}
+		);
+		assert!(String::from_utf8(stream.write)
+			.unwrap()
+			.contains("trackerid=abc"));
+	}
+
+	#[test]
+	fn test_gzip_response() {
+		let gzipped = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x4b\xb1\xb0\xca\xcc\x2b\x49\x2d\x2a\x4b\xcc\xc9\xb4\x34\x30\x48\x35\xb5\x2a\x48\x4d\x2d\x2a\x36\xb3\xaa\x67\x60\x60\x94\x9f\x90\x0a\x00\x15\xfe\xd5\x66\x20\x00\x00\x00";
+		let mut response = format!(
+			"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+			gzipped.len()
+		)
+		.into_bytes();
+		response.extend_from_slice(gzipped);
+
+		assert_eq!(
+			parse_http_response(&response).unwrap(),
+			b"d8:intervali900e5:peers6:\x7f\x00\x00\x01\x1f\x90e"
+		);
+
+		let mut stream = MockStream::create(response);
+		let url = AnnounceUrl::parse(&metainfo().announce).unwrap();
+		let request = build_request(&metainfo(), TrackerEvent::STARTED, AnnounceStats::default());
+		assert_eq!(
+			announce_over(&mut stream, &url, request).unwrap(),
+			TrackerResponse::Ok {
+				interval: 900,
+				min_interval: None,
+				tracker_id: None,
+				complete: 0,
+				incomplete: 0,
+				peers: Peers::Compact(vec![127, 0, 0, 1, 0x1f, 0x90]),
+				peers6: None,
+				warning_message: None,
+			}
+		);
+		assert!(String::from_utf8(stream.write)
+			.unwrap()
+			.contains("\r\nAccept-Encoding: gzip\r\n"));
+
+		// bodies without the header are passed through untouched
+		let mut plain = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+		plain.extend_from_slice(gzipped);
+		assert_eq!(parse_http_response(&plain).unwrap(), gzipped);
+	}
+
+	#[test]
+	fn test_retry() {
+		let mut attempts = 0;
+		let mut delays = vec![];
+		let result = retry(
+			5,
+			RETRY_DELAY,
+			|| {
+				attempts += 1;
+				if attempts <= 2 {
+					Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+				} else {
+					Ok(TrackerResponse::Err("not today".into()))
+				}
+			},
+			|d| delays.push(d),
+		);
+
+		assert_eq!(result.unwrap(), TrackerResponse::Err("not today".into()));
+		assert_eq!(attempts, 3);
+		assert_eq!(delays, vec![RETRY_DELAY, RETRY_DELAY * 2]);
+
+		// from the tracker's min interval
+		let mut delays = vec![];
+		let result: io::Result<()> = retry(
+			2,
+			Duration::from_secs(60),
+			|| Err(io::Error::from(io::ErrorKind::ConnectionReset)),
+			|d| delays.push(d),
+		);
+		assert!(result.is_err());
+		assert_eq!(
+			delays,
+			vec![Duration::from_secs(60), Duration::from_secs(120)]
+		);
+
+		// gives up after max_retries
+		let mut attempts = 0;
+		let result: io::Result<()> = retry(
+			2,
+			RETRY_DELAY,
+			|| {
+				attempts += 1;
+				Err(io::Error::from(io::ErrorKind::TimedOut))
+			},
+			|_| (),
+		);
+		assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+		assert_eq!(attempts, 3);
+
+		// a bad response won't get better
+		let mut attempts = 0;
+		let result: io::Result<()> = retry(
+			2,
+			RETRY_DELAY,
+			|| {
+				attempts += 1;
+				Err(io::Error::from(io::ErrorKind::InvalidData))
+			},
+			|_| (),
+		);
+		assert!(result.is_err());
+		assert_eq!(attempts, 1);
+	}
+
+	#[test]
+	fn test_announce_with_retry() {
+		let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+		let mut metainfo = metainfo();
+		metainfo.announce = format!("http://{}/announce", listener.local_addr().unwrap()).into();
+
+		let tracker = thread::spawn(move || {
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut request = vec![];
+			let mut buf = [0; 1024];
+			while !request.ends_with(b"\r\n\r\n") {
+				let n = stream.read(&mut buf).unwrap();
+				assert_ne!(n, 0);
+				request.extend_from_slice(&buf[..n]);
+			}
+			stream
+				.write_all(b"HTTP/1.1 200 OK\r\n\r\nd14:failure reason9:not todaye")
+				.unwrap();
+		});
+
+		let announce = |metainfo: &MetaInfo| {
+			announce_with_retry(
+				metainfo,
+				TrackerEvent::STARTED,
+				AnnounceStats::default(),
+				None,
+				2,
+				None,
+			)
+		};
+		assert_eq!(
+			announce(&metainfo).unwrap(),
+			TrackerResponse::Err("not today".into())
+		);
+		tracker.join().unwrap();
+
+		// unsupported urls fail without waiting to retry
+		metainfo.announce = "udp://tracker.example:80".into();
+		assert_eq!(
+			announce(&metainfo).unwrap_err().kind(),
+			io::ErrorKind::Unsupported
+		);
+	}
+}
@@ -0,0 +1,184 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+
+use super::Server;
+use crate::config::Config;
+use crate::http::Response;
+use crate::peer::{self, Peer};
+use crate::{Handler, CONNECTION_TIMEOUT};
+
+/// Stream with one byte already read from it, which is given back by the first read.
+struct Peeked<S> {
+	first: Option<u8>,
+	stream: S,
+}
+
+impl<S: Read> Read for Peeked<S> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		match (self.first, buf) {
+			(_, []) => Ok(0),
+			(Some(byte), [head, ..]) => {
+				*head = byte;
+				self.first = None;
+				Ok(1)
+			}
+			(None, buf) => self.stream.read(buf),
+		}
+	}
+}
+
+impl<S: Write> Write for Peeked<S> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.stream.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.stream.flush()
+	}
+}
+
+/// Serves both the tracker and the peer protocol on `server_port`, for when only one port can be
+/// forwarded.
+pub struct Combined {
+	pub server: Server,
+	pub peer: Peer,
+}
+
+impl Combined {
+	pub fn new(config: Config, sender: Sender<SocketAddr>) -> Self {
+		let server = Server::new(config.clone(), sender.clone());
+		let peer = Peer {
+			config,
+			peer_id: peer::peer_id(),
+			sender,
+			limiter: server.limiter.clone(),
+			swarms: server.swarms.clone(),
+		};
+		Self { server, peer }
+	}
+
+	pub fn listen(self: Arc<Self>) -> io::Result<()> {
+		let listener = TcpListener::bind(("0.0.0.0", self.server.config.server_port))?;
+		// peers are served here too
+		self.server
+			.peer_port
+			.store(listener.local_addr()?.port(), Ordering::Relaxed);
+
+		for stream in listener.incoming() {
+			let mut stream = stream?;
+			if !self.server.limiter.allow(stream.peer_addr()?.ip()) {
+				continue;
+			}
+			stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+			match self.clone().handle_connection_timed(
+				stream.local_addr()?,
+				stream.peer_addr()?,
+				stream.try_clone()?,
+				CONNECTION_TIMEOUT,
+			) {
+				Ok(true) => (),
+				Ok(false) => Response::new(400).write_to(&mut stream)?,
+				Err(e) => {
+					error!("Error handling combined connection: {:?}", e);
+					Response::new(500).write_to(&mut stream)?;
+				}
+			};
+		}
+		Ok(())
+	}
+}
+
+impl Handler for Combined {
+	/// same as `Server`: whether the request was handled
+	type Ok = bool;
+
+	fn handle_connection(
+		&self,
+		local: SocketAddr,
+		remote: SocketAddr,
+		mut stream: impl Read + Write,
+	) -> io::Result<Self::Ok> {
+		let mut first = [0; 1];
+		if stream.read(&mut first)? == 0 {
+			return Ok(true);
+		}
+		let stream = Peeked {
+			first: Some(first[0]),
+			stream,
+		};
+
+		match first[0] {
+			// length of "BitTorrent protocol"
+			0x13 => self
+				.peer
+				.handle_connection(local, remote, stream)
+				.map(|_| true),
+			// GET or HEAD
+			b'G' | b'H' => self.server.handle_connection(local, remote, stream),
+			_ => Ok(false),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::mpsc;
+
+	use super::Combined;
+	use crate::{config::Config, test::assert_stream_eq};
+
+	fn combined() -> (Combined, mpsc::Receiver<std::net::SocketAddr>) {
+		let (sx, rx) = mpsc::channel();
+		let mut combined = Combined::new(
+			Config {
+				info_hash: [1; 20],
+				..Config::default()
+			},
+			sx,
+		);
+		combined.peer.peer_id = [3; 20];
+		(combined, rx)
+	}
+
+	#[test]
+	fn test_route_handshake() {
+		let (combined, rx) = combined();
+		assert_stream_eq(
+			combined,
+			"\x13BitTorrent protocol\x00\x00\x00\x00\x00\x00\x00\x00\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02\x02",
+			"127.0.0.1:3000",
+			"192.168.4.47:2000",
+			"\x13BitTorrent protocol\x00\x00\x00\x00\x00\x00\x00\x00\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03\x03"
+		);
+		assert_eq!(rx.try_recv(), Ok("192.168.4.47:2000".parse().unwrap()));
+	}
+
+	#[test]
+	fn test_route_announce() {
+		let (combined, rx) = combined();
+		assert_stream_eq(
+			combined,
+			"GET /announce?info_hash=22222222222222222222&peer_id=magicnumber123456789&port=25565&uploaded=4&downloaded=5&left=6 HTTP/1.1\r\n",
+			"127.0.0.1:3000",
+			"192.168.7.160:50000",
+			"HTTP/1.1 200 OK\r\nContent-Length: 40\r\nContent-Type: text/plain\r\n\r\nd14:failure reason18:Invalid info hash.e"
+		);
+		rx.try_recv().expect_err("Unexpected IP in server.");
+	}
+
+	#[test]
+	fn test_route_unknown() {
+		let (combined, _rx) = combined();
+		// neither handler is given the connection
+		assert_stream_eq(
+			combined,
+			"POST /announce HTTP/1.1\r\n",
+			"127.0.0.1:3000",
+			"192.168.7.160:50000",
+			"",
+		);
+	}
+}
@@ -1,9 +1,19 @@
+mod client;
+mod combined;
 mod query_string;
+mod resolver;
 mod server;
+mod swarm;
 mod tracker_request;
 mod tracker_response;
 
+#[allow(unused_imports)]
+pub use client::{announce, announce_with_retry, AnnounceStats};
+pub use combined::Combined;
 pub use query_string::*;
 pub use server::Server;
+pub use swarm::{Swarm, Swarms};
+#[allow(unused_imports)]
+pub use tracker_request::TrackerRequestBuilder;
 pub use tracker_request::{TrackerEvent, TrackerRequest};
 pub use tracker_response::*;
@@ -1,30 +1,64 @@
-use std::collections::HashMap;
+use std::{borrow::Cow, collections::HashMap};
 
-#[derive(Debug, PartialEq)]
-pub struct QueryString(HashMap<Vec<u8>, Vec<u8>>);
+/// Decoded query string.  Keys may be repeated (e.g. multiple `info_hash` in a scrape), so every
+/// value is kept in the order it appeared.
+#[derive(Debug, PartialEq, Clone)]
+pub struct QueryString(HashMap<Vec<u8>, Vec<Vec<u8>>>);
 
 impl From<HashMap<Vec<u8>, Vec<u8>>> for QueryString {
 	fn from(value: HashMap<Vec<u8>, Vec<u8>>) -> Self {
-		Self(value)
+		Self(value.into_iter().map(|(k, v)| (k, vec![v])).collect())
 	}
 }
 
 impl QueryString {
+	/// removes the key, returning its first value
 	pub fn remove(&mut self, key: &str) -> Option<Vec<u8>> {
-		self.0.remove(key.as_bytes())
+		self.0
+			.remove(key.as_bytes())
+			.and_then(|values| values.into_iter().next())
+	}
+
+	/// removes the key, returning every value it had (empty if missing)
+	pub fn remove_all(&mut self, key: &str) -> Vec<Vec<u8>> {
+		self.0.remove(key.as_bytes()).unwrap_or_default()
+	}
+
+	/// first value of the key
+	pub fn get(&self, key: &str) -> Option<&[u8]> {
+		self.0
+			.get(key.as_bytes())
+			.and_then(|values| values.first())
+			.map(Vec::as_slice)
+	}
+
+	/// first value of the key as text, with invalid UTF-8 replaced.  values like `info_hash` are
+	/// binary, so use `get` for those.
+	#[allow(dead_code)]
+	pub fn get_str(&self, key: &str) -> Option<Cow<'_, str>> {
+		self.get(key).map(String::from_utf8_lossy)
+	}
+
+	#[allow(dead_code)]
+	pub fn contains_key(&self, key: &str) -> bool {
+		self.0.contains_key(key.as_bytes())
+	}
+
+	pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+		self.0.keys().map(Vec::as_slice)
 	}
 }
 
 fn to_hex_digit(b: Option<u8>) -> Option<u8> {
 	match b {
 		Some(b @ b'0'..=b'9') => Some(b - b'0'),
-		Some(b @ b'a'..=b'z') => Some(b - b'a' + 10),
-		Some(b @ b'A'..=b'Z') => Some(b - b'A' + 10),
+		Some(b @ b'a'..=b'f') => Some(b - b'a' + 10),
+		Some(b @ b'A'..=b'F') => Some(b - b'A' + 10),
 		_ => None,
 	}
 }
 
-fn url_decode(s: &str) -> Result<Vec<u8>, ()> {
+pub fn url_decode(s: &str) -> Result<Vec<u8>, ()> {
 	let mut bytes = s.bytes();
 	let mut vec = Vec::with_capacity(s.len());
 	while let Some(byte) = bytes.next() {
@@ -33,19 +67,21 @@ fn url_decode(s: &str) -> Result<Vec<u8>, ()> {
 				(Some(a), Some(b)) => vec.push(a * 16 + b),
 				_ => return Err(()),
 			},
+			// application/x-www-form-urlencoded spaces.  a literal + is sent as %2B
+			b'+' => vec.push(b' '),
 			_ => vec.push(byte),
 		};
 	}
 	Ok(vec)
 }
 
-fn url_encode(s: Vec<u8>) -> Vec<u8> {
+pub fn url_encode(s: Vec<u8>) -> Vec<u8> {
 	let mut bytes = s.into_iter();
 	let mut vec = Vec::new();
 	while let Some(byte) = bytes.next() {
 		match byte {
 			b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'-' | b'.' | b'~' => vec.push(byte),
-			_ => vec.append(&mut format!("%{:X}", byte).into_bytes()),
+			_ => vec.append(&mut format!("%{:02X}", byte).into_bytes()),
 		}
 	}
 	vec
@@ -53,24 +89,31 @@ fn url_encode(s: Vec<u8>) -> Vec<u8> {
 
 // TODO use better error types
 pub fn decode(data: &str) -> Result<QueryString, ()> {
-	let mut map = HashMap::new();
+	let mut map: HashMap<_, Vec<_>> = HashMap::new();
+	if data.is_empty() {
+		return Ok(QueryString(map));
+	}
 	for item in data.split('&') {
 		let (key, value) = match item.split_once('=') {
 			Some(tup) => tup,
 			None => return Err(()),
 		};
-		map.insert(url_decode(key)?, url_decode(value)?);
+		map.entry(url_decode(key)?)
+			.or_default()
+			.push(url_decode(value)?);
 	}
 	Ok(QueryString(map))
 }
 
 pub fn encode(data: QueryString) -> Vec<u8> {
 	let mut pairs = Vec::new();
-	for (k, v) in data.0.into_iter() {
-		pairs.append(&mut url_encode(k));
-		pairs.push(b'=');
-		pairs.append(&mut url_encode(v));
-		pairs.push(b'&');
+	for (k, values) in data.0.into_iter() {
+		for v in values {
+			pairs.append(&mut url_encode(k.clone()));
+			pairs.push(b'=');
+			pairs.append(&mut url_encode(v));
+			pairs.push(b'&');
+		}
 	}
 	// remove superfluous &
 	pairs.pop();
@@ -98,12 +141,29 @@ mod tests {
 
 		// unterminated byte sequence
 		assert!(url_decode("20%").is_err());
+		// not hex
+		assert!(url_decode("%zz").is_err());
+
+		// + is a space, %2B a plus
+		assert_eq!(url_decode("hello+world").unwrap(), b"hello world");
+		assert_eq!(url_decode("1%2B1+%2b+1").unwrap(), b"1+1 + 1");
+		assert_eq!(
+			decode("event=+started&peer_id=a%2Bb"),
+			Ok(qs!(("event", " started"), ("peer_id", "a+b")))
+		);
 	}
 
 	#[test]
 	fn test_url_encode() {
 		assert_eq!(url_encode(Vec::from("hello world")), b"hello%20world");
 		assert_eq!(url_encode(Vec::from("h○llow")), b"h%E2%97%8Bllow");
+
+		// low bytes are padded to two hex digits
+		assert_eq!(url_encode(vec![0x0a, b'a', 0x05]), b"%0Aa%05");
+		assert_eq!(
+			url_decode(&String::from_utf8(url_encode(vec![0x0a, 0x05])).unwrap()).unwrap(),
+			vec![0x0a, 0x05]
+		);
 	}
 
 	#[test]
@@ -117,6 +177,49 @@ mod tests {
 		assert!(decode("a").is_err());
 		// no second half
 		assert!(decode("a=b&").is_err());
+		// empty
+		assert_eq!(decode(""), Ok(qs!()));
+	}
+
+	#[test]
+	fn test_accessors() {
+		let qs = qs!(("info_hash", "abc"), ("port", "8080"));
+
+		assert_eq!(qs.get("port"), Some(&b"8080"[..]));
+		assert_eq!(qs.get("left"), None);
+
+		assert!(qs.contains_key("info_hash"));
+		assert!(!qs.contains_key("peer_id"));
+
+		let mut keys: Vec<_> = qs.keys().collect();
+		keys.sort();
+		assert_eq!(keys, vec![&b"info_hash"[..], &b"port"[..]]);
+
+		assert_eq!(qs.get_str("port").as_deref(), Some("8080"));
+		assert_eq!(qs.get_str("left"), None);
+
+		// accessors don't consume anything
+		assert_eq!(qs, qs!(("info_hash", "abc"), ("port", "8080")));
+	}
+
+	#[test]
+	fn test_repeated_keys() {
+		let mut qs = decode("info_hash=aaa&port=1&info_hash=bbb").unwrap();
+
+		assert_eq!(qs.get("info_hash"), Some(&b"aaa"[..]));
+		assert_eq!(
+			encode(qs.clone()).len(),
+			"info_hash=aaa&port=1&info_hash=bbb".len()
+		);
+		assert_eq!(
+			qs.remove_all("info_hash"),
+			vec![b"aaa".to_vec(), b"bbb".to_vec()]
+		);
+		assert_eq!(qs.remove_all("info_hash"), Vec::<Vec<u8>>::new());
+
+		let mut qs = decode("a=1&a=2").unwrap();
+		assert_eq!(qs.remove("a"), Some(b"1".to_vec()));
+		assert_eq!(qs.remove("a"), None);
 	}
 
 	#[test]
@@ -127,4 +230,21 @@ mod tests {
 		assert!(enc == b"a=b&c=d" || enc == b"c=d&a=b");
 		assert_eq!(&b"w%20w=%20%20"[..], encode(qs!(("w w", "  "))));
 	}
+
+	#[test]
+	fn test_binary_values() {
+		let info_hash = [0x00, 0xff, b'&', b'=', 0x80, b'%'];
+		let mut qs =
+			QueryString::from(HashMap::from([(b"info_hash".to_vec(), info_hash.to_vec())]));
+
+		let encoded = String::from_utf8(encode(qs.clone())).unwrap();
+		assert_eq!(encoded, "info_hash=%00%FF%26%3D%80%25");
+		assert_eq!(decode(&encoded), Ok(qs.clone()));
+
+		assert_eq!(
+			qs.get_str("info_hash").as_deref(),
+			Some("\0\u{fffd}&=\u{fffd}%")
+		);
+		assert_eq!(qs.remove("info_hash"), Some(info_hash.to_vec()));
+	}
 }
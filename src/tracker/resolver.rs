@@ -0,0 +1,136 @@
+use std::{
+	io,
+	net::{IpAddr, ToSocketAddrs},
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+/// how long a lookup is reused before resolving the host again
+const TTL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug)]
+struct Resolved {
+	host: String,
+	addrs: Vec<IpAddr>,
+	at: Instant,
+}
+
+/// Resolves the advertised host with the system resolver, caching the addresses for `TTL`.
+#[derive(Debug, Default)]
+pub struct Resolver {
+	cache: Mutex<Option<Resolved>>,
+}
+
+fn lookup(host: &str) -> io::Result<Vec<IpAddr>> {
+	Ok((host, 0).to_socket_addrs()?.map(|addr| addr.ip()).collect())
+}
+
+impl Resolver {
+	/// addresses of `host`, looked up again once the cached ones are older than `TTL`
+	pub fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+		self.resolve_with(host, Instant::now(), lookup)
+	}
+
+	fn resolve_with(
+		&self,
+		host: &str,
+		now: Instant,
+		lookup: impl FnOnce(&str) -> io::Result<Vec<IpAddr>>,
+	) -> io::Result<Vec<IpAddr>> {
+		let mut cache = self.cache.lock().expect("Resolver lock poisoned.");
+		if let Some(resolved) = cache.as_ref() {
+			if resolved.host == host && now.duration_since(resolved.at) < TTL {
+				return Ok(resolved.addrs.clone());
+			}
+		}
+
+		let addrs = lookup(host)?;
+		*cache = Some(Resolved {
+			host: host.into(),
+			addrs: addrs.clone(),
+			at: now,
+		});
+		Ok(addrs)
+	}
+}
+
+/// the address to advertise: the first ipv4 one if `prefer_v4` (for compact peer lists), otherwise
+/// the first one
+pub fn choose(addrs: &[IpAddr], prefer_v4: bool) -> Option<IpAddr> {
+	addrs
+		.iter()
+		.find(|addr| prefer_v4 && addr.is_ipv4())
+		.or(addrs.first())
+		.copied()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		cell::Cell,
+		io,
+		net::IpAddr,
+		time::{Duration, Instant},
+	};
+
+	use super::{choose, Resolver};
+
+	#[test]
+	fn test_cache() {
+		let resolver = Resolver::default();
+		let lookups = Cell::new(0);
+		let lookup = |addr: &'static str| {
+			let lookups = &lookups;
+			move |_: &str| {
+				lookups.set(lookups.get() + 1);
+				Ok(vec![addr.parse().unwrap()])
+			}
+		};
+		let start = Instant::now();
+
+		let first = resolver.resolve_with("tracker.example", start, lookup("10.0.0.1"));
+		assert_eq!(first.unwrap(), vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+
+		// cached
+		let cached = resolver.resolve_with(
+			"tracker.example",
+			start + Duration::from_secs(60),
+			lookup("10.0.0.2"),
+		);
+		assert_eq!(cached.unwrap(), vec!["10.0.0.1".parse::<IpAddr>().unwrap()]);
+		assert_eq!(lookups.get(), 1);
+
+		// expired
+		let expired = resolver.resolve_with(
+			"tracker.example",
+			start + Duration::from_secs(300),
+			lookup("10.0.0.2"),
+		);
+		assert_eq!(
+			expired.unwrap(),
+			vec!["10.0.0.2".parse::<IpAddr>().unwrap()]
+		);
+		assert_eq!(lookups.get(), 2);
+
+		// failures aren't cached
+		let failed = resolver.resolve_with("other.example", start, |_| {
+			Err(io::Error::new(io::ErrorKind::NotFound, "no such host"))
+		});
+		assert!(failed.is_err());
+
+		assert_eq!(
+			resolver.resolve("127.0.0.1").unwrap(),
+			vec!["127.0.0.1".parse::<IpAddr>().unwrap()]
+		);
+	}
+
+	#[test]
+	fn test_choose() {
+		let addrs: Vec<IpAddr> = vec!["::1".parse().unwrap(), "10.0.0.1".parse().unwrap()];
+
+		assert_eq!(choose(&addrs, true), Some("10.0.0.1".parse().unwrap()));
+		assert_eq!(choose(&addrs, false), Some("::1".parse().unwrap()));
+		assert_eq!(choose(&addrs[..1], true), Some("::1".parse().unwrap()));
+		assert_eq!(choose(&[], true), None);
+	}
+}
@@ -1,39 +1,492 @@
-use std::io::{Read, Write};
-use std::net::{IpAddr, SocketAddr, SocketAddrV4, TcpListener};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicU16, Ordering};
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
-use super::{Peers, TrackerRequest, TrackerResponse};
+use super::resolver::{self, Resolver};
+use super::{
+	compact6, Peers, QueryString, Swarm, Swarms, TrackerEvent, TrackerRequest, TrackerResponse,
+};
+use crate::bencode::Dictionary;
 use crate::config::{Config, PeerHost};
+use crate::http::Response;
 use crate::peer::{self, Peer};
+use crate::pruned_map::PrunedMap;
+use crate::rate_limit::RateLimiter;
+use crate::rng::Rng;
 use crate::tracker::IP;
-use crate::{bencode, Handler};
+use crate::{bencode, bytes::BytesExt, Handler, CONNECTION_TIMEOUT};
+
+/// longest request (line and headers) read before giving up on it.  announces are well under this.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Method {
+	Get,
+	Head,
+}
+
+/// the method and target (without the leading `/`) of a request's first line
+fn parse_request_line(data: &str) -> Option<(Method, &str)> {
+	let (method, rest) = data.split_once(' ')?;
+	let method = match method {
+		"GET" => Method::Get,
+		"HEAD" => Method::Head,
+		_ => return None,
+	};
+	let (target, _) = rest.strip_prefix('/')?.split_once(' ')?;
+	Some((method, target))
+}
+
+/// a torrent's swarm, before any peers announce
+fn new_swarm(config: &Config) -> Swarm {
+	Swarm::with_max_peers(config.max_peers_per_torrent).with_interval(config.announce_interval)
+}
+
+/// a failure response with `reason`, e.g. for an announce we won't answer with peers
+fn failure(reason: &str) -> Response {
+	Response::new(200).body(bencode::encode(TrackerResponse::Err(reason.into())))
+}
+
+/// the addresses our own peer is advertised at: `advertise_ipv4` and `advertise_ipv6` if either is
+/// set, otherwise the one `peer_host` gives.  neither for a `peer_host` of `host` or `resolve`,
+/// which are sent by name or looked up instead.
+fn select_advertised_ips(
+	config: &Config,
+	local: SocketAddr,
+) -> (Option<Ipv4Addr>, Option<Ipv6Addr>) {
+	if config.advertise_ipv4.is_some() || config.advertise_ipv6.is_some() {
+		return (config.advertise_ipv4, config.advertise_ipv6);
+	}
+	let ip = match config.peer_host {
+		PeerHost::IP(ip) => ip,
+		PeerHost::INFER => local.ip(),
+		PeerHost::HOST | PeerHost::RESOLVE => return (None, None),
+	};
+	match ip {
+		IpAddr::V4(v4) => (Some(v4), None),
+		IpAddr::V6(v6) => (None, Some(v6)),
+	}
+}
+
+/// (info hash, peer id)
+type AnnounceKey = ([u8; 20], [u8; 20]);
 
 pub struct Server {
 	pub config: Config,
 	pub sender: Sender<SocketAddr>,
+	/// last accepted announce time of each peer
+	last_announce: Mutex<PrunedMap<AnnounceKey, Instant>>,
+	/// passkey each peer announced with, when `passkeys` is set
+	users: Mutex<PrunedMap<AnnounceKey, String>>,
+	/// peers of each torrent served, by info hash
+	pub(super) swarms: Swarms,
+	pub(super) limiter: Arc<RateLimiter>,
+	/// port peers are sent to, which is `peer_port` until the peer listener is bound
+	pub(super) peer_port: AtomicU16,
+	resolver: Resolver,
+	started: Instant,
+	/// shuffles the peers sent, so clients don't all connect to the same ones first
+	rng: Mutex<Rng>,
 }
 
 impl Server {
-	pub fn listen(&self) -> std::io::Result<()> {
-		let listener = TcpListener::bind(("0.0.0.0", self.config.server_port))?;
+	pub fn new(config: Config, sender: Sender<SocketAddr>) -> Self {
+		Self {
+			limiter: Arc::new(RateLimiter::per_minute(config.max_conns_per_min)),
+			swarms: Arc::new(Mutex::new(HashMap::from([(
+				config.info_hash,
+				new_swarm(&config),
+			)]))),
+			peer_port: AtomicU16::new(config.peer_port),
+			resolver: Resolver::default(),
+			started: Instant::now(),
+			rng: Mutex::new(Rng::new(config.rng_seed)),
+			config,
+			sender,
+			last_announce: Mutex::new(PrunedMap::new()),
+			users: Mutex::new(PrunedMap::new()),
+		}
+	}
+
+	/// serves another torrent, alongside `config.info_hash`.  `interval` overrides
+	/// `config.announce_interval` for its peers, and `private` is from its info dictionary.
+	pub fn add_torrent(&self, info_hash: [u8; 20], interval: Option<u64>, private: bool) {
+		let mut swarms = self.swarms.lock().expect("Swarms lock poisoned.");
+		let swarm = swarms
+			.entry(info_hash)
+			.or_insert_with(|| new_swarm(&self.config));
+		if let Some(interval) = interval {
+			swarm.interval = interval;
+		}
+		swarm.private = private;
+	}
+
+	fn serves(&self, info_hash: &[u8; 20]) -> bool {
+		self.swarms
+			.lock()
+			.expect("Swarms lock poisoned.")
+			.contains_key(info_hash)
+	}
+
+	/// whether the peer is in the swarm already, so this isn't its first announce
+	fn known_peer(&self, request: &TrackerRequest) -> bool {
+		self.swarms
+			.lock()
+			.expect("Swarms lock poisoned.")
+			.get(&request.info_hash)
+			.is_some_and(|swarm| swarm.contains(&request.peer_id))
+	}
+
+	/// records the announce (unless `dry_run`), returning whether the peer announced too recently
+	/// to be accepted
+	fn rate_limited(&self, request: &TrackerRequest, dry_run: bool) -> bool {
+		// however soon they come, a stopped peer has to leave the swarm and a download be counted
+		if self.config.min_announce_interval == 0
+			|| matches!(
+				request.event,
+				Some(TrackerEvent::STOPPED | TrackerEvent::COMPLETED)
+			) {
+			return false;
+		}
+		let min_interval = Duration::from_secs(self.config.min_announce_interval);
+		let now = Instant::now();
+
+		let mut last_announce = self
+			.last_announce
+			.lock()
+			.expect("Announce times lock poisoned.");
+		// forget peers that can announce again so the map doesn't grow forever
+		last_announce.prune_at(now, min_interval, |_, last| {
+			now.duration_since(*last) < min_interval
+		});
+
+		let key = (request.info_hash, request.peer_id);
+		if last_announce
+			.get(&key)
+			.is_some_and(|last| now.duration_since(*last) < min_interval)
+		{
+			return true;
+		}
+		if !dry_run {
+			last_announce.insert(key, now);
+		}
+		false
+	}
+
+	/// a failure if passkeys are required and `passkey` isn't one of them
+	fn check_passkey(&self, passkey: Option<&str>) -> Option<Response> {
+		let passkeys = self.config.passkeys.as_ref()?;
+		match passkey {
+			None => Some(failure("missing passkey")),
+			Some(passkey) if !passkeys.contains(passkey) => Some(failure("invalid passkey")),
+			Some(_) => None,
+		}
+	}
 
+	/// answers an announce, or `None` if it can't be parsed.  `path_passkey` is from
+	/// `<announce path>/<passkey>`, which takes precedence over a `passkey` in the query.  with
+	/// `dry_run` (for a HEAD) the response is only built: the announce isn't recorded and the peer
+	/// isn't reported.
+	fn announce(
+		&self,
+		local: SocketAddr,
+		remote: SocketAddr,
+		mut query_string: QueryString,
+		path_passkey: Option<&str>,
+		dry_run: bool,
+	) -> Option<Response> {
+		let passkey = match path_passkey {
+			Some(passkey) => Some(passkey.to_string()),
+			None => query_string
+				.remove("passkey")
+				.map(|passkey| passkey.to_string()),
+		};
+		if let Some(response) = self.check_passkey(passkey.as_deref()) {
+			return Some(response);
+		}
+
+		// worth a specific failure, since the client might be able to fix it
+		if query_string
+			.get("info_hash")
+			.is_some_and(|info_hash| info_hash.len() != 20)
+		{
+			return Some(failure("invalid info_hash length"));
+		}
+
+		let tracker_request = match TrackerRequest::try_from(query_string.clone()) {
+			Ok(t_r) => t_r,
+			Err(_) => {
+				warn!(
+					"Unparseable announce from {:?} with keys: {:?}",
+					remote,
+					query_string
+						.keys()
+						.map(BytesExt::to_string)
+						.collect::<Vec<_>>()
+				);
+				return None;
+			}
+		};
+		if !self.serves(&tracker_request.info_hash) {
+			return Some(failure("Invalid info hash."));
+		}
+		// anyone who knows the info hash is reported, even if the announce is refused below
+		if !dry_run {
+			self.sender
+				.send(remote)
+				.expect("Error sending message from server thread.");
+		}
+
+		Some(
+			if self.config.require_compact && tracker_request.compact != Some(true) {
+				failure("compact required")
+			} else if self.config.require_started_first
+				&& tracker_request.event != Some(TrackerEvent::STARTED)
+				&& !self.known_peer(&tracker_request)
+			{
+				failure("started event required")
+			} else if self.rate_limited(&tracker_request, dry_run) {
+				// when the peer can announce again, at the latest
+				failure("rate limited")
+					.header("Retry-After", self.config.min_announce_interval.to_string())
+			} else {
+				info!("Server: {:?}", remote);
+				if let Some(corrupt) = tracker_request.corrupt.filter(|&corrupt| corrupt > 0) {
+					info!("{:?} reported {} corrupt bytes", remote, corrupt);
+				}
+
+				let port = self.peer_port.load(Ordering::Relaxed);
+				let own = match select_advertised_ips(&self.config, local) {
+					(None, None) => vec![self.host_ip(tracker_request.compact == Some(true))],
+					(v4, v6) => v4
+						.map(IpAddr::V4)
+						.into_iter()
+						.chain(v6.map(IpAddr::V6))
+						.map(IP::IP)
+						.collect(),
+				};
+
+				debug!("Sending peer with IP {:?}", own);
+
+				let seeders_first = self.config.prefer_seeders && tracker_request.left > 0;
+				let (interval, complete, incomplete, others) = {
+					let mut swarms = self.swarms.lock().expect("Swarms lock poisoned.");
+					if !dry_run {
+						swarms
+							.entry(tracker_request.info_hash)
+							.or_insert_with(|| new_swarm(&self.config))
+							.announce(
+								&tracker_request,
+								SocketAddr::new(remote.ip(), tracker_request.port),
+							);
+
+						// before the ratio is checked, since the peer is in the swarm either way
+						let mut users = self.users.lock().expect("Users lock poisoned.");
+						let key = (tracker_request.info_hash, tracker_request.peer_id);
+						if tracker_request.event == Some(TrackerEvent::STOPPED) {
+							users.remove(&key);
+						} else if let (Some(_), Some(passkey)) = (&self.config.passkeys, passkey) {
+							users.insert(key, passkey);
+						}
+						// forget peers that timed out or were dropped for others with their swarm
+						users.prune_at(
+							Instant::now(),
+							Duration::from_secs(self.config.announce_interval),
+							|(info_hash, peer_id), _| {
+								swarms
+									.get(info_hash)
+									.is_some_and(|swarm| swarm.contains(peer_id))
+							},
+						);
+					}
+
+					// served, and swarms are never removed
+					let swarm = &swarms[&tracker_request.info_hash];
+					if let Some(min_ratio) = self.config.min_ratio {
+						if swarm
+							.stats(&tracker_request.peer_id)
+							.is_some_and(|stats| stats.below_ratio(min_ratio))
+						{
+							return Some(failure("insufficient ratio"));
+						}
+					}
+					// the freshest are sent, in random order (but still seeders first)
+					let mut others = swarm.select_peers(
+						// our own peer counts towards numwant
+						(tracker_request.numwant.unwrap_or(50) as usize).saturating_sub(own.len()),
+						&tracker_request.peer_id,
+						seeders_first,
+					);
+					self.rng
+						.lock()
+						.expect("Rng lock poisoned.")
+						.shuffle(&mut others);
+					if seeders_first {
+						others.sort_by_key(|peer| !peer.is_seeder());
+					}
+					let others: Vec<_> = others
+						.into_iter()
+						.map(|peer| (peer.peer_id, peer.addr))
+						.collect();
+					(swarm.interval, swarm.complete(), swarm.incomplete(), others)
+				};
+
+				// ipv4 peers go in peers and ipv6 ones in peers6.  compact lists can't hold hostnames,
+				// so if we're only advertised by name the full list is sent, unless compact is required.
+				let compact = tracker_request.compact == Some(true)
+					&& (self.config.require_compact
+						|| own.iter().any(|ip| matches!(ip, IP::IP(_))));
+
+				// our own peer goes first, then the rest of the swarm
+				let (peers, peers6) = if compact {
+					let own = own.iter().filter_map(|ip| match ip {
+						IP::IP(ip) => Some(SocketAddr::new(*ip, port)),
+						IP::STRING(_) => None,
+					});
+					let mut v4s = Vec::new();
+					let mut v6s = Vec::new();
+					for addr in own.chain(others.into_iter().map(|(_, addr)| addr)) {
+						match addr {
+							SocketAddr::V4(addr) => v4s.push(addr),
+							SocketAddr::V6(addr) => v6s.push(compact6(addr)),
+						}
+					}
+					(Peers::create_compact(v4s), (!v6s.is_empty()).then_some(v6s))
+				} else {
+					let with_id =
+						|peer_id| (tracker_request.no_peer_id != Some(true)).then_some(peer_id);
+					let mut peers: Vec<_> = own
+						.into_iter()
+						.map(|ip| super::Peer {
+							peer_id: with_id(peer::peer_id()),
+							ip,
+							port,
+						})
+						.collect();
+					peers.extend(others.into_iter().map(|(peer_id, addr)| super::Peer {
+						peer_id: with_id(peer_id),
+						ip: IP::IP(addr.ip()),
+						port: addr.port(),
+					}));
+					(Peers::Full(peers), None)
+				};
+
+				let response = TrackerResponse::Ok {
+					interval,
+					min_interval: (self.config.min_announce_interval > 0)
+						.then_some(self.config.min_announce_interval),
+					tracker_id: None, // TODO
+					complete,
+					incomplete,
+					peers,
+					peers6,
+					warning_message: Some(format!("Your IP is {}", remote.ip())),
+				};
+				Response::new(200).body(if self.config.legacy_key_order {
+					response.encode_legacy()
+				} else {
+					bencode::encode(response)
+				})
+			},
+		)
+	}
+
+	/// `host` to advertise when there's no address to, looked up if `peer_host` is `resolve`
+	fn host_ip(&self, prefer_v4: bool) -> IP {
+		if self.config.peer_host == PeerHost::RESOLVE {
+			match self.resolver.resolve(&self.config.host) {
+				Ok(addrs) => {
+					if let Some(ip) = resolver::choose(&addrs, prefer_v4) {
+						return IP::IP(ip);
+					}
+				}
+				Err(e) => warn!("Error resolving {}: {}", self.config.host, e),
+			}
+		}
+		IP::STRING(self.config.host.clone())
+	}
+
+	/// answers a scrape (BEP 48) for the requested info hashes, or every torrent if none are given.
+	/// unknown info hashes are left out.
+	fn scrape(&self, mut query_string: QueryString) -> Vec<u8> {
+		let info_hashes = query_string.remove_all("info_hash");
+		let swarms = self.swarms.lock().expect("Swarms lock poisoned.");
+
+		let mut files = Dictionary::new();
+		for (info_hash, swarm) in swarms.iter() {
+			if info_hashes.is_empty() || info_hashes.iter().any(|hash| hash == info_hash) {
+				files.insert(info_hash.to_vec(), swarm.scrape());
+			}
+		}
+
+		let mut dict = Dictionary::new();
+		dict.insert("files", files);
+		bencode::encode(dict)
+	}
+
+	/// seconds since the server started, and how many torrents and peers it's tracking
+	fn healthz(&self) -> Vec<u8> {
+		let swarms = self.swarms.lock().expect("Swarms lock poisoned.");
+
+		let mut dict = Dictionary::new();
+		dict.insert(
+			"peers",
+			swarms
+				.values()
+				.map(|swarm| swarm.complete() + swarm.incomplete())
+				.sum::<u64>(),
+		);
+		dict.insert("torrents", swarms.len() as u64);
+		dict.insert("uptime", self.started.elapsed().as_secs());
+		bencode::encode(dict)
+	}
+
+	/// binds the peer listener, advertising the port it's bound to, and serves it on another thread
+	fn spawn_peer(&self) -> std::io::Result<()> {
 		let peer = Peer {
 			config: self.config.clone(),
 			peer_id: peer::peer_id(),
 			sender: self.sender.clone(),
+			limiter: self.limiter.clone(),
+			swarms: self.swarms.clone(),
 		};
 
-		thread::spawn(move || peer.listen().unwrap());
+		let listener = peer.bind()?;
+		self.peer_port
+			.store(listener.local_addr()?.port(), Ordering::Relaxed);
+		thread::spawn(move || Arc::new(peer).serve(listener).unwrap());
+		Ok(())
+	}
+
+	pub fn listen(self: Arc<Self>) -> std::io::Result<()> {
+		let listener = TcpListener::bind(("0.0.0.0", self.config.server_port))?;
+
+		self.spawn_peer()?;
 
 		for stream in listener.incoming() {
 			let mut stream = stream?;
-			match self.handle_connection(stream.local_addr()?, stream.peer_addr()?, &mut stream) {
+			// dropping the stream closes it
+			if !self.limiter.allow(stream.peer_addr()?.ip()) {
+				continue;
+			}
+			// so a client that stops sending doesn't keep the handler's thread around
+			stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+			match self.clone().handle_connection_timed(
+				stream.local_addr()?,
+				stream.peer_addr()?,
+				stream.try_clone()?,
+				CONNECTION_TIMEOUT,
+			) {
 				Ok(true) => (),
-				Ok(false) => write!(&mut stream, "HTTP/1.1 400 BAD REQUEST\r\n\r\n")?,
+				Ok(false) => Response::new(400).write_to(&mut stream)?,
 				Err(e) => {
-					eprintln!("Error handling server connection: {:?}", e);
-					write!(&mut stream, "HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n")?;
+					error!("Error handling server connection: {:?}", e);
+					Response::new(500).write_to(&mut stream)?;
 				}
 			};
 		}
@@ -52,141 +505,1053 @@ impl Handler for Server {
 	) -> std::io::Result<Self::Ok> {
 		let mut data = Vec::new();
 		{
-			const BUF_SIZE: usize = 1024;
-			let mut buf = [0; BUF_SIZE];
-
+			// stop at the end of the headers: a GET has no body, and a keep-alive client won't
+			// close the connection
+			let mut reader = BufReader::new(&mut stream);
 			loop {
-				match stream.read(&mut buf) {
-					Ok(BUF_SIZE) => data.extend_from_slice(&buf[..]),
-					Ok(i) => {
-						data.extend_from_slice(&buf[..i]);
-						break;
-					}
-					Err(_) => break,
+				// one byte over, to tell a request of exactly the limit from a longer one
+				let limit = MAX_REQUEST_SIZE + 1 - data.len() as u64;
+				match reader.by_ref().take(limit).read_until(b'\n', &mut data) {
+					Ok(0) | Err(_) => break,
+					Ok(_) if data.ends_with(b"\r\n\r\n") || data.ends_with(b"\n\n") => break,
+					Ok(_) => (),
 				}
 			}
 		}
 
+		if data.len() as u64 > MAX_REQUEST_SIZE {
+			Response::new(413).write_to(&mut stream)?;
+			return Ok(true);
+		}
+
 		let data = match String::from_utf8(data) {
 			Ok(d) => d,
 			Err(_) => return Ok(false),
 		};
 
-		let path = match data.strip_prefix("GET /announce?") {
-			Some(p) => p,
+		let (method, target) = match parse_request_line(&data) {
+			Some(parsed) => parsed,
 			None => return Ok(false),
 		};
+		let (path, query_string) = target.split_once('?').unwrap_or((target, ""));
 
-		let query_string = match path.split_once(" ") {
-			Some((x, _)) => x,
-			_ => return Ok(false),
+		let query_string = match super::decode(query_string) {
+			Ok(qs) => qs,
+			Err(_) => return Ok(false),
 		};
 
-		let tracker_request =
-			match super::decode(query_string).map(|qs| TrackerRequest::try_from(qs)) {
-				Ok(Ok(t_r)) => t_r,
+		let announce_path = self.config.announce_path.trim_start_matches('/');
+		let scrape_path = self.config.scrape_path.trim_start_matches('/');
+		// `<announce path>/<passkey>`, only with passkeys, and never the scrape path, which could
+		// be under the announce path too
+		let path_passkey = match path
+			.strip_prefix(announce_path)
+			.and_then(|rest| rest.strip_prefix('/'))
+			.filter(|_| self.config.passkeys.is_some() && path != scrape_path)
+		{
+			Some(passkey) => match super::url_decode(passkey) {
+				Ok(passkey) if !passkey.is_empty() => Some(passkey.to_string()),
 				_ => return Ok(false),
-			};
-		let mut body = if self.config.info_hash == tracker_request.info_hash {
-			println!("Server: {:?}", remote);
-
-			self.sender
-				.send(remote)
-				.expect("Error sending message from server thread.");
-
-			let ip = match self.config.peer_host {
-				PeerHost::HOST => IP::STRING(self.config.host.clone()),
-				PeerHost::IP(ip) => IP::IP(ip),
-				PeerHost::INFER => IP::IP(local.ip()),
-			};
-
-			println!("Sending peer with IP {:?}", ip);
+			},
+			None => None,
+		};
 
-			let peers = match (&tracker_request.compact, ip) {
-				(&Some(true), IP::IP(IpAddr::V4(v4))) => {
-					Peers::create_compact(vec![SocketAddrV4::new(v4, self.config.peer_port)])
+		let response = match path {
+			"healthz" => Response::new(200).body(self.healthz()),
+			path if path == scrape_path => Response::new(200).body(self.scrape(query_string)),
+			// lets monitoring check we're up
+			path if method == Method::Head
+				&& (path == announce_path || path_passkey.is_some())
+				&& query_string.keys().next().is_none() =>
+			{
+				self.check_passkey(path_passkey.as_deref())
+					.unwrap_or_else(|| Response::new(200))
+			}
+			path if path == announce_path => {
+				match self.announce(local, remote, query_string, None, method == Method::Head) {
+					Some(response) => response,
+					None => return Ok(false),
 				}
-				(_, ip) => Peers::Full(vec![super::Peer {
-					peer_id: peer::peer_id(),
-					ip,
-					port: self.config.peer_port,
-				}]),
-			};
-
-			bencode::encode(TrackerResponse::Ok {
-				interval: 300,
-				min_interval: None,
-				tracker_id: None, // TODO
-				complete: 1,
-				incomplete: 0,
-				peers,
-				warning_message: Some(format!("Your IP is {}", remote.ip())),
-			})
-		} else {
-			bencode::encode(TrackerResponse::Err("Invalid info hash."))
+			}
+			_ if path_passkey.is_some() => {
+				match self.announce(
+					local,
+					remote,
+					query_string,
+					path_passkey.as_deref(),
+					method == Method::Head,
+				) {
+					Some(response) => response,
+					None => return Ok(false),
+				}
+			}
+			// lets monitoring check we're up
+			"" if method == Method::Head => Response::new(200),
+			_ => return Ok(false),
 		};
 
-		let mut bytes = format!(
-			"HTTP/1.1 200 OK\r\nContent-Length: {}\r\nContent-Type: text/plain\r\n\r\n",
-			body.len()
-		)
-		.into_bytes();
-
-		bytes.append(&mut body);
-		bytes.push(b'\r');
-		bytes.push(b'\n');
-
-		stream.write_all(&bytes).map(|_| true)
+		let response = response.header("Content-Type", &self.config.response_content_type);
+		// a HEAD gets the headers a GET would
+		match method {
+			Method::Get => response.write_to(&mut stream)?,
+			Method::Head => response.write_head_to(&mut stream)?,
+		}
+		Ok(true)
 	}
 }
 
 #[cfg(test)]
 mod tests {
-	use std::sync::mpsc;
+	use std::{
+		collections::HashSet,
+		io::{self, Read, Write},
+		net::SocketAddr,
+		sync::mpsc,
+		time::{Duration, Instant},
+	};
 
-	use super::Server;
-	use crate::{config::Config, peer, test::assert_stream_eq};
+	use super::{parse_request_line, select_advertised_ips, Method, Server, MAX_REQUEST_SIZE};
+	use crate::{
+		bencode::{decode, try_decode_from, Data},
+		config::{Config, PeerHost},
+		peer,
+		test::{assert_stream_eq, assert_stream_eq_chunked, MockStream},
+		tracker::TrackerResponse,
+		Handler,
+	};
 
 	#[test]
 	fn test_handle_req() {
-		let (sx, rx) = mpsc::channel();
-		assert_stream_eq(
-			Server {
-				config: Config::default(),
-				sender: sx.clone(),
-			},
-			"GET / HTTP/1.1\r\n\r\n",
-			"127.0.0.1:3000",
-			"192.168.7.160:51551",
-			"HTTP/1.1 400 BAD REQUEST\r\n\r\n",
-		);
-
+		let (unknown, rx) = server(Config::default());
+		// `listen` answers these with a 400
+		assert_eq!(request(&unknown, "GET", "/"), (false, Vec::new()));
 		rx.try_recv().expect_err("Unexpected IP in server.");
 
-		let mut config = Config::default();
-		config.info_hash = [b'1'; 20];
-
+		let (announce, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
 		assert_stream_eq(
-			Server { sender: sx.clone(),
-			config
-			},
+			announce,
 			"GET /announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=4&downloaded=5&left=6 HTTP/1.1\r\n",
 			"127.0.0.1:3000",
 			"192.168.7.160:50000",
-			format!("HTTP/1.1 200 OK\r\nContent-Length: 162\r\nContent-Type: text/plain\r\n\r\nd8:completei1e10:incompletei0e8:intervali300e5:peersld2:ip9:127.0.0.17:peer id20:{}4:porti16384eee15:warning message24:Your IP is 192.168.7.160e\r\n", String::from_utf8(peer::peer_id().to_vec()).unwrap())
+			format!("HTTP/1.1 200 OK\r\nContent-Length: 162\r\nContent-Type: text/plain\r\n\r\nd8:completei0e10:incompletei1e8:intervali300e5:peersld2:ip9:127.0.0.17:peer id20:{}4:porti16384eee15:warning message24:Your IP is 192.168.7.160e", String::from_utf8(peer::peer_id().to_vec()).unwrap())
 		);
 		assert_eq!(rx.try_recv(), Ok("192.168.7.160:50000".parse().unwrap()));
 
-		config = Config::default();
-		config.info_hash = [b'2'; 20];
 		assert_stream_eq(
-			Server { sender: sx.clone(),
-			config
-			},
+			server(Config {
+				info_hash: [b'2'; 20],
+				..Config::default()
+			})
+			.0,
 			"GET /announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=4&downloaded=5&left=6 HTTP/1.1\r\n",
 			"127.0.0.1:3000",
 			"192.168.7.160:50000",
-			"HTTP/1.1 200 OK\r\nContent-Length: 40\r\nContent-Type: text/plain\r\n\r\nd14:failure reason18:Invalid info hash.e\r\n"
+			"HTTP/1.1 200 OK\r\nContent-Length: 40\r\nContent-Type: text/plain\r\n\r\nd14:failure reason18:Invalid info hash.e"
+		);
+	}
+
+	#[test]
+	fn test_min_announce_interval() {
+		let (server, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			min_announce_interval: 60,
+			..Config::default()
+		});
+
+		let announce_event = |event: &str| {
+			let (_, response) = request(
+				&server,
+				"GET",
+				&format!("/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=4&downloaded=5&left=6{}", event),
+			);
+			// checks the length
+			body(&response);
+			String::from_utf8(response).unwrap()
+		};
+		let announce = || announce_event("");
+
+		let accepted = announce();
+		assert!(accepted.contains("5:peers"));
+		assert!(accepted.contains("12:min intervali60e"));
+		assert!(!accepted.contains("Retry-After"));
+		assert!(rx.try_recv().is_ok());
+
+		let limited = announce();
+		assert!(limited.contains("\r\nRetry-After: 60\r\n"));
+		assert!(limited.ends_with("d14:failure reason12:rate limitede"));
+		assert!(rx.try_recv().is_ok());
+
+		// pretend the peer announced a minute ago
+		for last in server.last_announce.lock().unwrap().values_mut() {
+			*last = Instant::now() - Duration::from_secs(60);
+		}
+		assert!(announce().contains("5:peers"));
+		assert!(rx.try_recv().is_ok());
+
+		// stopping right away still leaves the swarm
+		assert!(announce_event("&event=stopped").contains("5:peers"));
+		assert!(!server.swarms.lock().unwrap()[&[b'1'; 20]].contains(b"magicnumber123456789"));
+	}
+
+	/// the body of a response, checking it's exactly `Content-Length` long
+	fn body(response: &[u8]) -> &[u8] {
+		let end = response
+			.windows(4)
+			.position(|window| window == b"\r\n\r\n")
+			.unwrap();
+		let length: usize = std::str::from_utf8(&response[..end])
+			.unwrap()
+			.split("\r\n")
+			.find_map(|header| header.strip_prefix("Content-Length: "))
+			.expect("Response has no Content-Length.")
+			.parse()
+			.unwrap();
+		let body = &response[end + 4..];
+		assert_eq!(body.len(), length);
+		body
+	}
+
+	/// sends `target` to the server, returning the response body
+	fn get(server: &Server, target: &str) -> String {
+		String::from_utf8(get_bytes(server, target)).unwrap()
+	}
+
+	/// like `get`, for bodies that aren't UTF-8 (e.g. with compact peers)
+	fn get_bytes(server: &Server, target: &str) -> Vec<u8> {
+		let (handled, response) = request(server, "GET", target);
+		assert!(handled);
+		body(&response).to_vec()
+	}
+
+	/// sends `method target` to the server, returning whether it was handled and the whole response
+	fn request(server: &Server, method: &str, target: &str) -> (bool, Vec<u8>) {
+		let mut stream =
+			MockStream::create(format!("{} {} HTTP/1.1\r\n\r\n", method, target).into());
+		let handled = server
+			.handle_connection(
+				"127.0.0.1:3000".parse().unwrap(),
+				"192.168.7.160:50000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap();
+		(handled, stream.write)
+	}
+
+	/// a server for `config`, with the receiver for the peers it reports
+	fn server(config: Config) -> (Server, mpsc::Receiver<SocketAddr>) {
+		let (sx, rx) = mpsc::channel();
+		(Server::new(config, sx), rx)
+	}
+
+	#[test]
+	fn test_swarm_events() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0";
+
+		assert_eq!(
+			get(&server, "/scrape"),
+			"d5:filesd20:11111111111111111111d8:completei0e10:downloadedi0e10:incompletei0eeee"
+		);
+
+		let started = get(&server, &format!("{}&left=6&event=started", announce));
+		assert!(started.starts_with("d8:completei0e10:incompletei1e"));
+
+		let completed = get(&server, &format!("{}&left=0&event=completed", announce));
+		assert!(completed.starts_with("d8:completei1e10:incompletei0e"));
+
+		assert_eq!(
+			get(&server, "/scrape?info_hash=11111111111111111111"),
+			"d5:filesd20:11111111111111111111d8:completei1e10:downloadedi1e10:incompletei0eeee"
+		);
+		// unknown torrents are left out
+		assert_eq!(
+			get(&server, "/scrape?info_hash=22222222222222222222"),
+			"d5:filesdee"
+		);
+
+		get(&server, &format!("{}&left=0&event=stopped", announce));
+		assert_eq!(
+			get(&server, "/scrape"),
+			"d5:filesd20:11111111111111111111d8:completei0e10:downloadedi1e10:incompletei0eeee"
+		);
+	}
+
+	#[test]
+	fn test_prefer_seeders() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			prefer_seeders: true,
+			..Config::default()
+		});
+		let announce_with = |peer_id: u8, left: u64, extra: &str| {
+			let body = get(
+				&server,
+				&format!(
+					"/announce?info_hash=11111111111111111111&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&compact=0{}",
+					(peer_id as char).to_string().repeat(20),
+					peer_id as u16 * 100,
+					left,
+					extra
+				),
+			);
+			match try_decode_from(&body[..]) {
+				Ok(Ok(TrackerResponse::Ok {
+					complete,
+					incomplete,
+					peers,
+					..
+				})) => (complete, incomplete, peers.addrs()),
+				other => panic!("Unexpected response {:?}", other),
+			}
+		};
+		let announce = |peer_id, left| announce_with(peer_id, left, "");
+
+		for (peer_id, left) in [(b'a', 10), (b'b', 0), (b'c', 20), (b'd', 0)] {
+			announce(peer_id, left);
+		}
+
+		let (complete, incomplete, addrs) = announce(b'e', 30);
+		assert_eq!((complete, incomplete), (2, 3));
+		let ports: Vec<_> = addrs.iter().map(SocketAddr::port).collect();
+		// our own peer, then seeders b and d
+		assert_eq!(ports.len(), 5);
+		assert_eq!(ports[0], 16384);
+		let mut seeders = ports[1..3].to_vec();
+		seeders.sort();
+		assert_eq!(seeders, vec![b'b' as u16 * 100, b'd' as u16 * 100]);
+
+		// seeders don't need seeders first, but still get everyone
+		let (_, _, addrs) = announce(b'b', 0);
+		assert_eq!(addrs.len(), 5);
+
+		// our own peer is one of the numwant
+		let (_, _, addrs) = announce_with(b'e', 30, "&numwant=3");
+		assert_eq!(addrs.len(), 3);
+	}
+
+	#[test]
+	fn test_no_peer_id() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+
+		assert!(get(&server, &format!("{}&compact=0", announce)).contains("7:peer id"));
+		assert!(
+			!get(&server, &format!("{}&compact=0&no_peer_id=1", announce)).contains("7:peer id")
+		);
+		assert!(
+			get(&server, &format!("{}&compact=0&no_peer_id=0", announce)).contains("7:peer id")
+		);
+	}
+
+	#[test]
+	fn test_info_hash_length() {
+		let (server, rx) = server(Config::default());
+
+		assert_eq!(
+			get(&server, "/announce?info_hash=1111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0"),
+			"d14:failure reason24:invalid info_hash lengthe"
+		);
+		rx.try_recv().expect_err("Unexpected IP in server.");
+	}
+
+	#[test]
+	fn test_add_torrent() {
+		let (server, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
+		let announce = "/announce?info_hash=22222222222222222222&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+
+		assert_eq!(
+			get(&server, announce),
+			"d14:failure reason18:Invalid info hash.e"
+		);
+		rx.try_recv().expect_err("Unexpected IP in server.");
+
+		server.add_torrent([b'2'; 20], None, true);
+		assert!(!get(&server, announce).contains("failure reason"));
+		assert_eq!(rx.try_recv(), Ok("192.168.7.160:50000".parse().unwrap()));
+		// still serves the first
+		assert!(!get(&server, "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0").contains("failure reason"));
+	}
+
+	#[test]
+	fn test_torrent_intervals() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			announce_interval: 900,
+			..Config::default()
+		});
+		server.add_torrent([b'2'; 20], Some(60), true);
+		server.add_torrent([b'3'; 20], Some(3600), true);
+		let interval = |info_hash: &str| {
+			match try_decode_from(get_bytes(&server, &format!("/announce?info_hash={}&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0", info_hash))) {
+				Ok(Ok(TrackerResponse::Ok { interval, .. })) => interval,
+				other => panic!("Unexpected response {:?}", other),
+			}
+		};
+
+		assert_eq!(interval("11111111111111111111"), 900);
+		assert_eq!(interval("22222222222222222222"), 60);
+		assert_eq!(interval("33333333333333333333"), 3600);
+
+		// overriding one already served
+		server.add_torrent([b'1'; 20], Some(120), true);
+		assert_eq!(interval("11111111111111111111"), 120);
+	}
+
+	#[test]
+	fn test_advertised_peer_port() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			peer_port: 0,
+			..Config::default()
+		});
+		server.spawn_peer().unwrap();
+
+		let body = get(&server, "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0&compact=0");
+		let port = match try_decode_from(&body[..]) {
+			Ok(Ok(TrackerResponse::Ok { peers, .. })) => peers.addrs()[0].port(),
+			other => panic!("Unexpected response {:?}", other),
+		};
+		assert_ne!(port, 0);
+		// the peer is listening there
+		std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+	}
+
+	/// a client that sent its request but keeps the connection open
+	struct KeepAlive(MockStream);
+
+	impl Read for KeepAlive {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			if self.0.read.is_empty() {
+				panic!("Read past the end of the request.");
+			}
+			self.0.read(buf)
+		}
+	}
+
+	impl Write for KeepAlive {
+		fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+			self.0.write(buf)
+		}
+
+		fn flush(&mut self) -> io::Result<()> {
+			self.0.flush()
+		}
+	}
+
+	#[test]
+	fn test_keep_alive() {
+		let (server, _rx) = server(Config::default());
+		// exactly 1024 bytes, which fills a 1024 byte buffer and looks like there's more to read
+		let request = format!(
+			"GET /scrape HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+			"a".repeat(1024 - 37)
+		);
+		assert_eq!(request.len(), 1024);
+		let mut stream = KeepAlive(MockStream::create(request.into()));
+
+		assert!(server
+			.handle_connection(
+				"127.0.0.1:3000".parse().unwrap(),
+				"192.168.7.160:50000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap());
+		assert!(String::from_utf8(stream.0.write)
+			.unwrap()
+			.starts_with("HTTP/1.1 200 OK\r\n"));
+	}
+
+	#[test]
+	fn test_content_type() {
+		let (server, _rx) = server(Config {
+			response_content_type: "application/octet-stream".into(),
+			..Config::default()
+		});
+		let response = String::from_utf8(request(&server, "GET", "/scrape").1).unwrap();
+		assert!(response.contains("\r\nContent-Type: application/octet-stream\r\n"));
+		assert_eq!(body(response.as_bytes()), b"d5:filesd20:\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01\x01d8:completei0e10:downloadedi0e10:incompletei0eeee");
+	}
+
+	#[test]
+	fn test_resolve_peer_host() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			host: "127.0.0.2".into(),
+			peer_host: PeerHost::RESOLVE,
+			..Config::default()
+		});
+
+		let body = get(&server, "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0&compact=1");
+		match try_decode_from(&body[..]) {
+			Ok(Ok(TrackerResponse::Ok { peers, .. })) => {
+				assert_eq!(peers.addrs()[0], "127.0.0.2:16384".parse().unwrap())
+			}
+			other => panic!("Unexpected response {:?}", other),
+		}
+	}
+
+	#[test]
+	fn test_select_advertised_ips() {
+		let local = "10.0.0.1:3000".parse().unwrap();
+		let v4 = "192.0.2.1".parse().unwrap();
+		let v6 = "2001:db8::1".parse().unwrap();
+
+		let config = Config::default();
+		assert_eq!(
+			select_advertised_ips(&config, local),
+			(Some("10.0.0.1".parse().unwrap()), None)
+		);
+
+		let v4_only = Config {
+			advertise_ipv4: Some(v4),
+			..Config::default()
+		};
+		assert_eq!(select_advertised_ips(&v4_only, local), (Some(v4), None));
+
+		let v6_only = Config {
+			advertise_ipv6: Some(v6),
+			..Config::default()
+		};
+		assert_eq!(select_advertised_ips(&v6_only, local), (None, Some(v6)));
+
+		let dual = Config {
+			advertise_ipv4: Some(v4),
+			advertise_ipv6: Some(v6),
+			peer_host: PeerHost::HOST,
+			..Config::default()
+		};
+		assert_eq!(select_advertised_ips(&dual, local), (Some(v4), Some(v6)));
+
+		let host = Config {
+			peer_host: PeerHost::HOST,
+			..Config::default()
+		};
+		assert_eq!(select_advertised_ips(&host, local), (None, None));
+	}
+
+	#[test]
+	fn test_dual_stack_peers() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			advertise_ipv4: Some("192.0.2.1".parse().unwrap()),
+			advertise_ipv6: Some("2001:db8::1".parse().unwrap()),
+			..Config::default()
+		});
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+
+		let addrs = |body: Vec<u8>| match try_decode_from(&body[..]) {
+			Ok(Ok(response @ TrackerResponse::Ok { .. })) => response.peer_addrs(),
+			other => panic!("Unexpected response {:?}", other),
+		};
+		let expected: Vec<SocketAddr> = vec![
+			"192.0.2.1:16384".parse().unwrap(),
+			"[2001:db8::1]:16384".parse().unwrap(),
+		];
+
+		let compact = get_bytes(&server, &format!("{}&compact=1", announce));
+		assert!(compact.windows(11).any(|window| window == b"6:peers618:"));
+		assert_eq!(addrs(compact), expected);
+		assert_eq!(
+			addrs(get_bytes(&server, &format!("{}&compact=0", announce))),
+			expected
+		);
+	}
+
+	#[test]
+	fn test_require_compact() {
+		let (v4, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			require_compact: true,
+			..Config::default()
+		});
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+
+		assert_eq!(
+			get(&v4, &format!("{}&compact=0", announce)),
+			"d14:failure reason16:compact requirede"
+		);
+		// the peer knows the info hash, so it's still reported
+		assert_eq!(rx.try_recv(), Ok("192.168.7.160:50000".parse().unwrap()));
+
+		let body = get_bytes(&v4, &format!("{}&compact=1", announce));
+		assert!(body.windows(8).any(|window| window == b"5:peers6"));
+		assert_eq!(rx.try_recv(), Ok("192.168.7.160:50000".parse().unwrap()));
+
+		// only reachable over ipv6, so the peer goes in peers6
+		let (v6, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			require_compact: true,
+			advertise_ipv6: Some("2001:db8::1".parse().unwrap()),
+			..Config::default()
+		});
+		let body = get_bytes(&v6, &format!("{}&compact=1", announce));
+		match try_decode_from(&body[..]) {
+			Ok(Ok(response @ TrackerResponse::Ok { .. })) => assert_eq!(
+				response.peer_addrs(),
+				vec!["[2001:db8::1]:16384".parse::<SocketAddr>().unwrap()]
+			),
+			other => panic!("Unexpected response {:?}", other),
+		}
+		assert!(body.windows(11).any(|window| window == b"6:peers618:"));
+	}
+
+	#[test]
+	fn test_require_started_first() {
+		let (server, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			require_started_first: true,
+			..Config::default()
+		});
+		let announce = |peer_id: &str, event: &str| {
+			get(
+				&server,
+				&format!("/announce?info_hash=11111111111111111111&peer_id={}&port=25565&uploaded=0&downloaded=0&left=0{}", peer_id, event),
+			)
+		};
+		let err = "d14:failure reason22:started event requirede";
+
+		// a bare first announce, still reported since the peer knows the info hash
+		assert_eq!(announce("magicnumber123456789", ""), err);
+		assert_eq!(announce("magicnumber123456789", "&event=completed"), err);
+		assert_eq!(rx.try_iter().count(), 2);
+
+		// started, then regular announces
+		assert!(!announce("magicnumber123456789", "&event=started").contains("failure reason"));
+		assert!(!announce("magicnumber123456789", "").contains("failure reason"));
+		assert!(!announce("magicnumber123456789", "&event=completed").contains("failure reason"));
+		assert_eq!(rx.try_iter().count(), 3);
+
+		// stopping means starting again
+		assert!(!announce("magicnumber123456789", "&event=stopped").contains("failure reason"));
+		assert_eq!(announce("magicnumber123456789", ""), err);
+
+		// other peers are tracked separately
+		assert_eq!(announce("othernumber123456789", ""), err);
+		// refused or not, every announce is reported
+		assert_eq!(rx.try_iter().count(), 3);
+	}
+
+	#[test]
+	fn test_rng_seed() {
+		let peer_order = |seed| {
+			let (server, _rx) = server(Config {
+				info_hash: [b'1'; 20],
+				rng_seed: Some(seed),
+				..Config::default()
+			});
+			let announce = |peer: u16, compact: u8| {
+				get_bytes(
+					&server,
+					&format!("/announce?info_hash=11111111111111111111&peer_id=peer{:016}&port={}&uploaded=0&downloaded=0&left=0&compact={}", peer, 6881 + peer, compact),
+				)
+			};
+			for peer in 0..20 {
+				announce(peer, 1);
+			}
+			match try_decode_from(&announce(100, 0)[..]) {
+				Ok(Ok(response @ TrackerResponse::Ok { .. })) => response.peer_addrs(),
+				other => panic!("Unexpected response {:?}", other),
+			}
+		};
+
+		let order = peer_order(7);
+		// ourselves, then the swarm
+		assert_eq!(order.len(), 21);
+		assert_eq!(peer_order(7), order);
+		assert_ne!(peer_order(8), order);
+
+		let mut ports: Vec<_> = order[1..].iter().map(|addr| addr.port()).collect();
+		ports.sort();
+		assert_eq!(ports, (6881..6901).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_oversized_request() {
+		let (server, _rx) = server(Config::default());
+
+		// no newline at all
+		let mut request = b"GET /announce?info_hash=".to_vec();
+		request.resize(1024 * 1024, b'a');
+		let mut stream = MockStream::create(request);
+		assert!(server
+			.handle_connection(
+				"127.0.0.1:3000".parse().unwrap(),
+				"192.168.7.160:50000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap());
+		assert_eq!(
+			String::from_utf8(stream.write).unwrap(),
+			"HTTP/1.1 413 PAYLOAD TOO LARGE\r\nContent-Length: 0\r\n\r\n"
+		);
+		// gave up long before the end
+		assert!(stream.read.len() > 1000 * 1024);
+
+		// endless headers
+		let mut request = b"GET /scrape HTTP/1.1\r\n".to_vec();
+		for _ in 0..1024 {
+			request.extend_from_slice(b"X-Padding: 0123456789\r\n");
+		}
+		let mut stream = MockStream::create(request);
+		server
+			.handle_connection(
+				"127.0.0.1:3000".parse().unwrap(),
+				"192.168.7.160:50000".parse().unwrap(),
+				&mut stream,
+			)
+			.unwrap();
+		assert!(String::from_utf8(stream.write)
+			.unwrap()
+			.starts_with("HTTP/1.1 413 "));
+
+		// the limit itself is allowed
+		let status = |len: usize| {
+			let mut request = b"GET /scrape HTTP/1.1\r\nX-Padding: ".to_vec();
+			request.resize(len - 4, b'a');
+			request.extend_from_slice(b"\r\n\r\n");
+			let mut stream = MockStream::create(request);
+			server
+				.handle_connection(
+					"127.0.0.1:3000".parse().unwrap(),
+					"192.168.7.160:50000".parse().unwrap(),
+					&mut stream,
+				)
+				.unwrap();
+			String::from_utf8(stream.write).unwrap()[9..12].to_string()
+		};
+		assert_eq!(status(MAX_REQUEST_SIZE as usize), "200");
+		assert_eq!(status(MAX_REQUEST_SIZE as usize + 1), "413");
+	}
+
+	#[test]
+	fn test_parse_request_line() {
+		assert_eq!(
+			parse_request_line("GET /announce?a=b HTTP/1.1\r\n\r\n"),
+			Some((Method::Get, "announce?a=b"))
+		);
+		assert_eq!(
+			parse_request_line("HEAD / HTTP/1.1\r\n\r\n"),
+			Some((Method::Head, ""))
+		);
+		assert_eq!(parse_request_line("POST /announce HTTP/1.1\r\n"), None);
+		assert_eq!(parse_request_line("GET announce HTTP/1.1\r\n"), None);
+		assert_eq!(parse_request_line("GET /announce"), None);
+	}
+
+	#[test]
+	fn test_head() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
+		let send = |method: &str, target: &str| {
+			let (handled, response) = request(&server, method, target);
+			(handled, String::from_utf8(response).unwrap())
+		};
+		let head = |target| send("HEAD", target);
+
+		assert_eq!(
+			head("/"),
+			(
+				true,
+				"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nContent-Type: text/plain\r\n\r\n".into()
+			)
+		);
+
+		assert_eq!(head("/announce"), head("/"));
+
+		// the headers of the GET, without its body
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+		let (handled, headers) = head(announce);
+		assert!(handled);
+		assert!(headers.ends_with("\r\n\r\n"));
+		// a HEAD doesn't announce
+		assert!(!server.swarms.lock().unwrap()[&[b'1'; 20]].contains(b"magicnumber123456789"));
+		let (_, get) = send("GET", announce);
+		assert!(get.starts_with(&headers));
+		assert_eq!(get.len(), headers.len() + body(get.as_bytes()).len());
+		assert!(!headers.contains("Content-Length: 0\r\n"));
+
+		// but GET / is still unknown
+		assert!(!send("GET", "/").0);
+		assert!(!head("/unknown").0);
+	}
+
+	#[test]
+	fn test_healthz() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			..Config::default()
+		});
+		assert_eq!(
+			get(&server, "/healthz"),
+			"d5:peersi0e8:torrentsi1e6:uptimei0ee"
+		);
+
+		get(&server, "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0");
+		let mut health = match decode(get(&server, "/healthz")) {
+			Ok(Data::Dict(dict)) => dict,
+			other => panic!("Unexpected health {:?}", other),
+		};
+		assert_eq!(health.remove_as::<u64>("peers"), Ok(1));
+		assert_eq!(health.remove_as::<u64>("torrents"), Ok(1));
+		assert!(health.remove_as::<u64>("uptime").is_ok());
+		assert!(health.is_empty());
+	}
+
+	#[test]
+	fn test_partial_reads() {
+		let (server, rx) = server(Config {
+			info_hash: [b'2'; 20],
+			..Config::default()
+		});
+		assert_stream_eq_chunked(
+			server,
+			"GET /announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=4&downloaded=5&left=6 HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+			"127.0.0.1:3000",
+			"192.168.7.160:50000",
+			"HTTP/1.1 200 OK\r\nContent-Length: 40\r\nContent-Type: text/plain\r\n\r\nd14:failure reason18:Invalid info hash.e",
+			1,
+		);
+		rx.try_recv().expect_err("Unexpected IP in server.");
+	}
+
+	#[test]
+	fn test_legacy_key_order() {
+		let announce = "/announce?info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0&no_peer_id=1";
+		let response = |legacy_key_order| {
+			let (server, _rx) = server(Config {
+				info_hash: [b'1'; 20],
+				legacy_key_order,
+				..Config::default()
+			});
+			get(&server, announce)
+		};
+
+		let sorted = response(false);
+		let legacy = response(true);
+		assert_ne!(sorted, legacy);
+		assert!(sorted.starts_with("d8:completei1e10:incompletei0e8:intervali300e5:peersl"));
+		assert!(legacy.starts_with("d8:intervali300e8:completei1e10:incompletei0e5:peersl"));
+		// the same response either way
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>(&legacy[..]),
+			try_decode_from::<TrackerResponse, _>(&sorted[..])
+		);
+	}
+
+	#[test]
+	fn test_min_ratio() {
+		let (server, rx) = server(Config {
+			info_hash: [b'1'; 20],
+			min_ratio: Some(0.5),
+			passkeys: Some(HashSet::from(["alice".into()])),
+			..Config::default()
+		});
+		let user = |peer_id: &[u8; 20]| {
+			server
+				.users
+				.lock()
+				.unwrap()
+				.get(&([b'1'; 20], *peer_id))
+				.cloned()
+		};
+		let announce = |peer_id: &str, uploaded, downloaded| {
+			let body = get(&server, &format!("/announce/alice?info_hash=11111111111111111111&peer_id={}&port=25565&uploaded={}&downloaded={}&left=100", peer_id, uploaded, downloaded));
+			match try_decode_from(&body[..]) {
+				Ok(Ok(TrackerResponse::Err(reason))) => Err(reason),
+				Ok(Ok(response)) => Ok(response),
+				other => panic!("Unexpected response {:?}", other),
+			}
+		};
+
+		// nothing downloaded yet
+		assert!(announce("newpeer1234567890123", 0, 0).is_ok());
+		// above the ratio
+		assert!(announce("seedypeer12345678901", 100, 100).is_ok());
+		// below it
+		assert_eq!(
+			announce("leechypeer1234567890", 10, 100),
+			Err("insufficient ratio".into())
+		);
+		// but still in the swarm, as a user, and reported
+		assert_eq!(user(b"leechypeer1234567890"), Some("alice".into()));
+		assert_eq!(rx.try_iter().count(), 3);
+		// and catching up over later announces
+		assert!(announce("leechypeer1234567890", 60, 110).is_ok());
+	}
+
+	#[test]
+	fn test_passkeys() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			passkeys: Some(HashSet::from(["alice".into(), "bob".into()])),
+			..Config::default()
+		});
+		let user = |peer_id: &[u8; 20]| {
+			server
+				.users
+				.lock()
+				.unwrap()
+				.get(&([b'1'; 20], *peer_id))
+				.cloned()
+		};
+		let query = "info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+		let failure = |target: &str| match try_decode_from(get(&server, target)) {
+			Ok(Ok(TrackerResponse::Err(reason))) => Some(reason),
+			Ok(Ok(TrackerResponse::Ok { .. })) => None,
+			other => panic!("Unexpected response {:?}", other),
+		};
+
+		// in the path or the query
+		assert_eq!(failure(&format!("/announce/alice?{}", query)), None);
+		assert_eq!(user(b"magicnumber123456789"), Some("alice".into()));
+		assert_eq!(failure(&format!("/announce?passkey=bob&{}", query)), None);
+		assert_eq!(user(b"magicnumber123456789"), Some("bob".into()));
+
+		assert_eq!(
+			failure(&format!("/announce/mallory?{}", query)),
+			Some("invalid passkey".into())
+		);
+		assert_eq!(
+			failure(&format!("/announce?passkey=mallory&{}", query)),
+			Some("invalid passkey".into())
+		);
+		assert_eq!(
+			failure(&format!("/announce?{}", query)),
+			Some("missing passkey".into())
+		);
+		// not a different user
+		assert_eq!(user(b"magicnumber123456789"), Some("bob".into()));
+
+		// forgotten once the peer stops
+		assert_eq!(
+			failure(&format!("/announce/bob?{}&event=stopped", query)),
+			None
+		);
+		assert_eq!(user(b"magicnumber123456789"), None);
+
+		// the path passkey is url-decoded
+		assert_eq!(failure(&format!("/announce/%61lice?{}", query)), None);
+		assert_eq!(user(b"magicnumber123456789"), Some("alice".into()));
+
+		let send = |method: &str, target: &str| {
+			let (handled, response) = request(&server, method, target);
+			(handled, String::from_utf8(response).unwrap())
+		};
+		// an empty or undecodable passkey isn't an announce
+		assert!(!send("GET", &format!("/announce/?{}", query)).0);
+		assert!(!send("GET", &format!("/announce/%zz?{}", query)).0);
+
+		// a HEAD is only answered as up with a valid passkey
+		let empty = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nContent-Type: text/plain\r\n\r\n";
+		assert_eq!(send("HEAD", "/announce/alice"), (true, empty.into()));
+		assert_ne!(send("HEAD", "/announce/mallory").1, empty);
+		assert_ne!(send("HEAD", "/announce").1, empty);
+	}
+
+	#[test]
+	fn test_custom_paths() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			announce_path: "/tracker/announce".into(),
+			scrape_path: "/tracker/scrape".into(),
+			..Config::default()
+		});
+		let query = "info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+		let handled = |target: &str| request(&server, "GET", target).0;
+
+		assert!(get(&server, &format!("/tracker/announce?{}", query)).starts_with("d8:completei1e"));
+		assert_eq!(
+			get(&server, "/tracker/scrape"),
+			"d5:filesd20:11111111111111111111d8:completei1e10:downloadedi0e10:incompletei0eeee"
+		);
+
+		// the default paths aren't served any more
+		assert!(!handled(&format!("/announce?{}", query)));
+		assert!(!handled("/scrape"));
+		assert!(!handled(&format!("/tracker/announcement?{}", query)));
+		assert!(!handled("/tracker"));
+		// without passkeys, nothing under the announce path is an announce
+		assert!(!handled(&format!("/tracker/announce/alice?{}", query)));
+	}
+
+	#[test]
+	fn test_scrape_under_announce_path() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			announce_path: "/tracker".into(),
+			scrape_path: "/tracker/scrape".into(),
+			passkeys: Some(HashSet::from(["alice".into()])),
+			..Config::default()
+		});
+		let query = "info_hash=11111111111111111111&peer_id=magicnumber123456789&port=25565&uploaded=0&downloaded=0&left=0";
+
+		// a scrape, not an announce with the passkey `scrape`
+		assert_eq!(
+			get(&server, "/tracker/scrape"),
+			"d5:filesd20:11111111111111111111d8:completei0e10:downloadedi0e10:incompletei0eeee"
+		);
+		assert!(get(&server, &format!("/tracker/alice?{}", query)).starts_with("d8:completei1e"));
+	}
+
+	#[test]
+	fn test_compact_ipv6() {
+		let (server, _rx) = server(Config {
+			info_hash: [b'1'; 20],
+			advertise_ipv6: Some("2001:db8::1".parse().unwrap()),
+			..Config::default()
+		});
+		let announce = |peer_id: &str| {
+			get_bytes(
+				&server,
+				&format!("/announce?info_hash=11111111111111111111&peer_id={}&port=6881&uploaded=0&downloaded=0&left=0&compact=1", peer_id),
+			)
+		};
+		let response = |body: &[u8]| match try_decode_from(body) {
+			Ok(Ok(response @ TrackerResponse::Ok { .. })) => response,
+			other => panic!("Unexpected response {:?}", other),
+		};
+
+		// only our ipv6 peer, still compact
+		let v6_only = announce("aaaaaaaaaaaaaaaaaaaa");
+		assert!(v6_only.windows(9).any(|window| window == b"5:peers0:"));
+		assert!(v6_only.windows(11).any(|window| window == b"6:peers618:"));
+		assert_eq!(
+			response(&v6_only).peer_addrs(),
+			vec!["[2001:db8::1]:16384".parse::<SocketAddr>().unwrap()]
+		);
+
+		// the other peer goes in peers, compact v4, and ours in peers6
+		let mixed = announce("bbbbbbbbbbbbbbbbbbbb");
+		assert!(mixed.windows(9).any(|window| window == b"5:peers6:"));
+		assert!(mixed.windows(11).any(|window| window == b"6:peers618:"));
+		assert_eq!(
+			response(&mixed).peer_addrs(),
+			vec![
+				"192.168.7.160:6881".parse::<SocketAddr>().unwrap(),
+				"[2001:db8::1]:16384".parse().unwrap()
+			]
+		);
+
+		// full dicts only when compact isn't asked for
+		let full = get(
+			&server,
+			"/announce?info_hash=11111111111111111111&peer_id=cccccccccccccccccccc&port=6881&uploaded=0&downloaded=0&left=0&compact=0",
 		);
+		assert!(full.contains("5:peersld"));
+		assert!(!full.contains("6:peers6"));
 	}
 }
@@ -0,0 +1,505 @@
+use std::{
+	cmp::Reverse,
+	collections::HashMap,
+	net::SocketAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use super::{TrackerEvent, TrackerRequest};
+use crate::{bencode::Dictionary, pruned_map::PrunedMap};
+
+/// seconds between announces, unless the swarm is given its own
+const DEFAULT_INTERVAL: u64 = 300;
+
+/// stats kept before those of peers no longer in the swarm are dropped
+const MAX_STATS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwarmPeer {
+	pub peer_id: [u8; 20],
+	pub addr: SocketAddr,
+	/// bytes left to download as of the last announce, 0 for seeders
+	pub left: u64,
+	last_seen: Instant,
+}
+
+impl SwarmPeer {
+	pub fn is_seeder(&self) -> bool {
+		self.left == 0
+	}
+}
+
+/// Amounts a peer has transferred over all its announces, which clients only report since they
+/// started.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PeerStats {
+	pub total_uploaded: u64,
+	pub total_downloaded: u64,
+	last_uploaded: u64,
+	last_downloaded: u64,
+}
+
+impl PeerStats {
+	/// adds what was transferred since the last announce.  a value lower than last time means the
+	/// client restarted and is counting from 0 again.
+	fn record(&mut self, uploaded: u64, downloaded: u64) {
+		let delta = |now: u64, last: u64| now.checked_sub(last).unwrap_or(now);
+		self.total_uploaded = self
+			.total_uploaded
+			.saturating_add(delta(uploaded, self.last_uploaded));
+		self.total_downloaded = self
+			.total_downloaded
+			.saturating_add(delta(downloaded, self.last_downloaded));
+		self.last_uploaded = uploaded;
+		self.last_downloaded = downloaded;
+	}
+
+	/// whether total uploaded / downloaded is under `min_ratio`.  peers that haven't downloaded
+	/// anything have no ratio yet, so never are.
+	pub fn below_ratio(&self, min_ratio: f64) -> bool {
+		self.total_downloaded > 0
+			&& (self.total_uploaded as f64 / self.total_downloaded as f64) < min_ratio
+	}
+}
+
+/// the swarm of each torrent served, by info hash.  shared with the peer listener, which only
+/// answers handshakes for these torrents.
+pub type Swarms = Arc<Mutex<HashMap<[u8; 20], Swarm>>>;
+
+/// Peers announcing one torrent, by peer id.
+#[derive(Debug)]
+pub struct Swarm {
+	peers: PrunedMap<[u8; 20], SwarmPeer>,
+	/// kept after peers stop or time out, so totals carry over when they come back, until there
+	/// are more than `MAX_STATS`
+	stats: HashMap<[u8; 20], PeerStats>,
+	/// number of completed downloads reported
+	pub downloaded: u64,
+	/// peers kept before the least recently seen is dropped for a new one, 0 for no limit
+	max_peers: usize,
+	/// seconds peers are told to wait between announces.  ones that miss two are dropped.
+	pub interval: u64,
+	/// whether the torrent is private (BEP 27), so our peer doesn't advertise the DHT for it.
+	/// torrents only known by info hash are assumed to be.
+	pub private: bool,
+}
+
+impl Default for Swarm {
+	fn default() -> Self {
+		Self {
+			peers: PrunedMap::new(),
+			stats: HashMap::new(),
+			downloaded: 0,
+			max_peers: 0,
+			interval: DEFAULT_INTERVAL,
+			private: true,
+		}
+	}
+}
+
+impl Swarm {
+	pub fn with_max_peers(max_peers: usize) -> Self {
+		Self {
+			max_peers,
+			..Self::default()
+		}
+	}
+
+	pub fn with_interval(mut self, interval: u64) -> Self {
+		self.interval = interval;
+		self
+	}
+
+	fn peer_timeout(&self) -> Duration {
+		Duration::from_secs(self.interval.saturating_mul(2))
+	}
+
+	/// records an announce from a peer reachable at `addr`
+	pub fn announce(&mut self, request: &TrackerRequest, addr: SocketAddr) {
+		self.announce_at(request, addr, Instant::now())
+	}
+
+	fn announce_at(&mut self, request: &TrackerRequest, addr: SocketAddr, now: Instant) {
+		let timeout = self.peer_timeout();
+		// timed out peers are skipped until they're dropped here
+		self.peers
+			.prune_at(now, Duration::from_secs(self.interval), |_, peer| {
+				now.duration_since(peer.last_seen) < timeout
+			});
+		if self.stats.len() >= MAX_STATS {
+			let peers = &self.peers;
+			self.stats
+				.retain(|peer_id, _| peers.contains_key(peer_id) || *peer_id == request.peer_id);
+		}
+		self.stats
+			.entry(request.peer_id)
+			.or_default()
+			.record(request.uploaded, request.downloaded);
+
+		match request.event {
+			Some(TrackerEvent::STOPPED) => {
+				self.peers.remove(&request.peer_id);
+				return;
+			}
+			// clients shouldn't send completed twice, but only count a download once per peer
+			Some(TrackerEvent::COMPLETED)
+				if !self
+					.peers
+					.get(&request.peer_id)
+					.is_some_and(SwarmPeer::is_seeder) =>
+			{
+				self.downloaded += 1
+			}
+			_ => (),
+		}
+
+		if self.max_peers > 0
+			&& self.peers.len() >= self.max_peers
+			&& !self.peers.contains_key(&request.peer_id)
+		{
+			let oldest = self
+				.peers
+				.values()
+				.min_by_key(|peer| peer.last_seen)
+				.map(|peer| peer.peer_id);
+			if let Some(oldest) = oldest {
+				self.peers.remove(&oldest);
+			}
+		}
+
+		self.peers.insert(
+			request.peer_id,
+			SwarmPeer {
+				peer_id: request.peer_id,
+				addr,
+				left: request.left,
+				last_seen: now,
+			},
+		);
+	}
+
+	/// whether the peer has announced since it last stopped or timed out
+	pub fn contains(&self, peer_id: &[u8; 20]) -> bool {
+		self.contains_at(peer_id, Instant::now())
+	}
+
+	fn contains_at(&self, peer_id: &[u8; 20], now: Instant) -> bool {
+		self.peers
+			.get(peer_id)
+			.is_some_and(|peer| now.duration_since(peer.last_seen) < self.peer_timeout())
+	}
+
+	/// what the peer has transferred in total, if it's ever announced
+	pub fn stats(&self, peer_id: &[u8; 20]) -> Option<&PeerStats> {
+		self.stats.get(peer_id)
+	}
+
+	/// peers that haven't timed out, even if they haven't been dropped yet
+	fn live_peers(&self) -> impl Iterator<Item = &SwarmPeer> {
+		let (now, timeout) = (Instant::now(), self.peer_timeout());
+		self.peers
+			.values()
+			.filter(move |peer| now.duration_since(peer.last_seen) < timeout)
+	}
+
+	/// number of seeders
+	pub fn complete(&self) -> u64 {
+		self.live_peers().filter(|peer| peer.is_seeder()).count() as u64
+	}
+
+	/// number of leechers
+	pub fn incomplete(&self) -> u64 {
+		self.live_peers().filter(|peer| !peer.is_seeder()).count() as u64
+	}
+
+	/// every peer (with its peer id) but `exclude`, the peer asking, and those that have timed out,
+	/// most recently seen first.  with `seeders_first`, seeders come before leechers.
+	fn peers_at(
+		&self,
+		exclude: &[u8; 20],
+		seeders_first: bool,
+		now: Instant,
+	) -> Vec<(&[u8; 20], &SwarmPeer)> {
+		let mut peers: Vec<_> = self
+			.peers
+			.iter()
+			.filter(|(peer_id, peer)| {
+				*peer_id != exclude && now.duration_since(peer.last_seen) < self.peer_timeout()
+			})
+			.collect();
+		peers.sort_by_key(|(_, peer)| Reverse(peer.last_seen));
+		if seeders_first {
+			peers.sort_by_key(|(_, peer)| !peer.is_seeder());
+		}
+		peers
+	}
+
+	/// the `want` most recently seen peers but `exclude`, with `seeders_first` the most recently
+	/// seen seeders then leechers.  peers that sent `stopped` are already gone, and those that have
+	/// timed out are skipped.
+	pub fn select_peers(
+		&self,
+		want: usize,
+		exclude: &[u8; 20],
+		seeders_first: bool,
+	) -> Vec<SwarmPeer> {
+		self.select_peers_at(want, exclude, seeders_first, Instant::now())
+	}
+
+	fn select_peers_at(
+		&self,
+		want: usize,
+		exclude: &[u8; 20],
+		seeders_first: bool,
+		now: Instant,
+	) -> Vec<SwarmPeer> {
+		self.peers_at(exclude, seeders_first, now)
+			.into_iter()
+			.take(want)
+			.map(|(_, peer)| peer.clone())
+			.collect()
+	}
+
+	/// this torrent's entry in a scrape response's `files` dictionary
+	pub fn scrape(&self) -> Dictionary {
+		let mut dict = Dictionary::new();
+		dict.insert("complete", self.complete());
+		dict.insert("downloaded", self.downloaded);
+		dict.insert("incomplete", self.incomplete());
+		dict
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::{PeerStats, Swarm, SwarmPeer, MAX_STATS};
+	use crate::{
+		bencode::encode,
+		tracker::{TrackerEvent, TrackerRequest},
+	};
+
+	fn request(peer_id: u8, left: u64, event: TrackerEvent) -> TrackerRequest {
+		TrackerRequest::builder([1; 20], [peer_id; 20], 6881)
+			.left(left)
+			.event(event)
+			.build()
+	}
+
+	#[test]
+	fn test_events() {
+		let addr = "10.0.0.1:6881".parse().unwrap();
+		let mut swarm = Swarm::default();
+
+		swarm.announce(&request(1, 100, TrackerEvent::STARTED), addr);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (0, 1));
+
+		swarm.announce(&request(1, 0, TrackerEvent::COMPLETED), addr);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (1, 0));
+		assert_eq!(swarm.downloaded, 1);
+
+		// a repeated completed isn't another download
+		swarm.announce(&request(1, 0, TrackerEvent::COMPLETED), addr);
+		assert_eq!(swarm.downloaded, 1);
+
+		swarm.announce(&request(2, 50, TrackerEvent::PAUSED), addr);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (1, 1));
+
+		assert_eq!(
+			encode(swarm.scrape()),
+			b"d8:completei1e10:downloadedi1e10:incompletei1ee"
+		);
+
+		swarm.announce(&request(1, 0, TrackerEvent::STOPPED), addr);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (0, 1));
+		// stopping doesn't undo a download
+		assert_eq!(swarm.downloaded, 1);
+	}
+
+	#[test]
+	fn test_peers() {
+		let mut swarm = Swarm::default();
+		for (peer_id, left) in [(1, 10), (2, 0), (3, 20), (4, 0), (5, 30)] {
+			let addr = format!("10.0.0.{}:6881", peer_id).parse().unwrap();
+			swarm.announce(&request(peer_id, left, TrackerEvent::STARTED), addr);
+		}
+		assert_eq!((swarm.complete(), swarm.incomplete()), (2, 3));
+
+		let peers = swarm.select_peers(10, &[5; 20], true);
+		assert_eq!(peers.len(), 4);
+		assert!(peers[..2].iter().all(SwarmPeer::is_seeder));
+		assert!(peers[2..].iter().all(|peer| !peer.is_seeder()));
+		// seeders are picked first, not only sorted first
+		let peers = swarm.select_peers(1, &[5; 20], true);
+		assert!(peers[0].is_seeder());
+
+		let mut peer_ids: Vec<_> = swarm
+			.select_peers(10, &[5; 20], false)
+			.iter()
+			.map(|peer| peer.peer_id[0])
+			.collect();
+		peer_ids.sort();
+		assert_eq!(peer_ids, vec![1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn test_timeout() {
+		let addr = "10.0.0.1:6881".parse().unwrap();
+		let mut swarm = Swarm::default();
+		let start = Instant::now();
+
+		swarm.announce_at(&request(1, 100, TrackerEvent::STARTED), addr, start);
+		swarm.announce_at(
+			&request(2, 0, TrackerEvent::STARTED),
+			addr,
+			start + Duration::from_secs(300),
+		);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (1, 1));
+
+		swarm.announce_at(
+			&request(2, 0, TrackerEvent::REGULAR),
+			addr,
+			start + Duration::from_secs(600),
+		);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (1, 0));
+		assert!(!swarm.contains_at(&[1; 20], start + Duration::from_secs(600)));
+		assert!(swarm.contains_at(&[2; 20], start + Duration::from_secs(600)));
+		assert!(!swarm.contains_at(&[2; 20], start + Duration::from_secs(1200)));
+
+		// a longer interval keeps peers longer
+		let mut swarm = Swarm::default().with_interval(3600);
+		swarm.announce_at(&request(1, 100, TrackerEvent::STARTED), addr, start);
+		assert!(swarm.contains_at(&[1; 20], start + Duration::from_secs(3600)));
+		assert!(!swarm.contains_at(&[1; 20], start + Duration::from_secs(7200)));
+
+		// too long to double
+		let swarm = Swarm::default().with_interval(u64::MAX);
+		assert_eq!(swarm.peer_timeout(), Duration::from_secs(u64::MAX));
+	}
+
+	#[test]
+	fn test_max_peers() {
+		let addr = "10.0.0.1:6881".parse().unwrap();
+		let mut swarm = Swarm::with_max_peers(3);
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+
+		for peer in 1..=3 {
+			swarm.announce_at(
+				&request(peer, 100, TrackerEvent::STARTED),
+				addr,
+				at(peer as u64),
+			);
+		}
+		// 1 announces again, so 2 is now the least recently seen
+		swarm.announce_at(&request(1, 100, TrackerEvent::REGULAR), addr, at(10));
+		// full, but known peers can still announce
+		assert_eq!(swarm.incomplete(), 3);
+
+		swarm.announce_at(&request(4, 100, TrackerEvent::STARTED), addr, at(11));
+		let mut peers: Vec<_> = swarm
+			.peers_at(&[0; 20], false, at(11))
+			.into_iter()
+			.map(|(peer_id, _)| peer_id[0])
+			.collect();
+		peers.sort();
+		assert_eq!(peers, vec![1, 3, 4]);
+
+		swarm.announce_at(&request(5, 100, TrackerEvent::STARTED), addr, at(12));
+		assert!(swarm.peers.contains_key(&[5; 20]));
+		assert!(!swarm.peers.contains_key(&[3; 20]));
+		assert_eq!(swarm.incomplete(), 3);
+	}
+
+	#[test]
+	fn test_select_peers() {
+		let mut swarm = Swarm::default();
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+		let addr = |peer_id| format!("10.0.0.{}:6881", peer_id).parse().unwrap();
+
+		// 1 goes stale, 2 stops, 3 to 5 are fresh (5 most recently)
+		swarm.announce_at(&request(1, 10, TrackerEvent::STARTED), addr(1), at(0));
+		for peer_id in 2..=5 {
+			swarm.announce_at(
+				&request(peer_id, 10, TrackerEvent::STARTED),
+				addr(peer_id),
+				at(100 * peer_id as u64),
+			);
+		}
+		swarm.announce_at(&request(2, 10, TrackerEvent::STOPPED), addr(2), at(550));
+
+		let now = at(650);
+		let peer_ids = |peers: Vec<SwarmPeer>| -> Vec<u8> {
+			peers.iter().map(|peer| peer.peer_id[0]).collect()
+		};
+		assert_eq!(
+			peer_ids(swarm.select_peers_at(10, &[0; 20], false, now)),
+			vec![5, 4, 3]
+		);
+		assert_eq!(
+			peer_ids(swarm.select_peers_at(2, &[0; 20], false, now)),
+			vec![5, 4]
+		);
+		assert_eq!(
+			peer_ids(swarm.select_peers_at(10, &[4; 20], false, now)),
+			vec![5, 3]
+		);
+		assert_eq!(swarm.select_peers_at(0, &[0; 20], false, now), vec![]);
+
+		assert_eq!(
+			swarm.select_peers_at(1, &[0; 20], false, now)[0].addr,
+			addr(5)
+		);
+	}
+
+	#[test]
+	fn test_peer_stats() {
+		let addr = "10.0.0.1:6881".parse().unwrap();
+		let mut swarm = Swarm::default();
+		let announce = |swarm: &mut Swarm, uploaded, downloaded, event| {
+			let request = TrackerRequest::builder([1; 20], [1; 20], 6881)
+				.uploaded(uploaded)
+				.downloaded(downloaded)
+				.left(10)
+				.event(event)
+				.build();
+			swarm.announce(&request, addr);
+		};
+		assert_eq!(swarm.stats(&[1; 20]), None);
+
+		announce(&mut swarm, 100, 50, TrackerEvent::STARTED);
+		announce(&mut swarm, 300, 80, TrackerEvent::REGULAR);
+		// restarted, so counting from 0 again
+		announce(&mut swarm, 20, 10, TrackerEvent::STARTED);
+
+		let stats = swarm.stats(&[1; 20]).unwrap();
+		assert_eq!((stats.total_uploaded, stats.total_downloaded), (320, 90));
+
+		// totals outlive the peer stopping
+		announce(&mut swarm, 25, 10, TrackerEvent::STOPPED);
+		assert_eq!((swarm.complete(), swarm.incomplete()), (0, 0));
+		let stats = swarm.stats(&[1; 20]).unwrap();
+		assert_eq!((stats.total_uploaded, stats.total_downloaded), (325, 90));
+		assert_eq!(swarm.stats(&[2; 20]), None);
+
+		// until there are too many
+		for i in 0..MAX_STATS as u32 {
+			let mut peer_id = [2; 20];
+			peer_id[..4].copy_from_slice(&i.to_be_bytes());
+			let request = TrackerRequest::builder([1; 20], peer_id, 6881)
+				.event(TrackerEvent::STOPPED)
+				.build();
+			swarm.announce(&request, addr);
+		}
+		assert_eq!(swarm.stats(&[1; 20]), None);
+		assert!(swarm.stats.len() <= MAX_STATS);
+
+		let mut stats = PeerStats::default();
+		stats.record(u64::MAX, 0);
+		stats.record(10, 0);
+		assert_eq!(stats.total_uploaded, u64::MAX);
+	}
+}
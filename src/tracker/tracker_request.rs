@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
 use super::QueryString;
 
-#[derive(PartialEq, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum TrackerEvent {
 	/// trackerrequest must include event key
 	STARTED,
@@ -10,6 +13,8 @@ pub enum TrackerEvent {
 	COMPLETED,
 	/// normal periodic check
 	REGULAR,
+	/// partial seed that has stopped downloading (BEP 21), otherwise treated like a regular check
+	PAUSED,
 }
 
 impl Into<&'static str> for TrackerEvent {
@@ -19,6 +24,7 @@ impl Into<&'static str> for TrackerEvent {
 			Self::STOPPED => "stopped",
 			Self::COMPLETED => "completed",
 			Self::REGULAR => "",
+			Self::PAUSED => "paused",
 		}
 	}
 }
@@ -29,6 +35,7 @@ impl From<String> for TrackerEvent {
 			"started" => Self::STARTED,
 			"stopped" => Self::STOPPED,
 			"completed" => Self::COMPLETED,
+			"paused" => Self::PAUSED,
 			_ => Self::REGULAR,
 		}
 	}
@@ -55,8 +62,9 @@ pub struct TrackerRequest {
 	pub downloaded: u64,
 	/// number of bytes left to download
 	pub left: u64,
+	/// bytes downloaded that failed the hash check, if the client reports it
+	pub corrupt: Option<u64>,
 	/// accepts peers in "Compact Mode"
-	/// TODO unimplemented
 	pub compact: Option<bool>,
 	/// requests peer list without peer ids, lesser precedence than compact
 	pub no_peer_id: Option<bool>,
@@ -70,6 +78,74 @@ pub struct TrackerRequest {
 	pub trackerid: Option<Vec<u8>>,
 }
 
+impl TrackerRequest {
+	/// starts a request with the required fields, see `TrackerRequestBuilder` for the defaults
+	#[allow(dead_code)]
+	pub fn builder(info_hash: [u8; 20], peer_id: [u8; 20], port: u16) -> TrackerRequestBuilder {
+		TrackerRequestBuilder(Self {
+			info_hash,
+			peer_id,
+			port,
+			uploaded: 0,
+			downloaded: 0,
+			left: 0,
+			corrupt: None,
+			compact: Some(true),
+			no_peer_id: None,
+			event: None,
+			ip: None,
+			numwant: None,
+			trackerid: None,
+		})
+	}
+}
+
+/// Builds a `TrackerRequest`.  Unset fields default to nothing uploaded, downloaded, or left, a
+/// compact peer list, and no event.
+pub struct TrackerRequestBuilder(TrackerRequest);
+
+#[allow(dead_code)]
+impl TrackerRequestBuilder {
+	pub fn uploaded(mut self, uploaded: u64) -> Self {
+		self.0.uploaded = uploaded;
+		self
+	}
+
+	pub fn downloaded(mut self, downloaded: u64) -> Self {
+		self.0.downloaded = downloaded;
+		self
+	}
+
+	pub fn left(mut self, left: u64) -> Self {
+		self.0.left = left;
+		self
+	}
+
+	pub fn event(mut self, event: TrackerEvent) -> Self {
+		self.0.event = Some(event);
+		self
+	}
+
+	pub fn numwant(mut self, numwant: u64) -> Self {
+		self.0.numwant = Some(numwant);
+		self
+	}
+
+	pub fn compact(mut self, compact: bool) -> Self {
+		self.0.compact = Some(compact);
+		self
+	}
+
+	pub fn trackerid(mut self, trackerid: Option<impl Into<Vec<u8>>>) -> Self {
+		self.0.trackerid = trackerid.map(Into::into);
+		self
+	}
+
+	pub fn build(self) -> TrackerRequest {
+		self.0
+	}
+}
+
 macro_rules! parse {
 	($x: expr$(, $T: ident)?) => {
 		String::from_utf8($x)
@@ -113,8 +189,12 @@ impl TryFrom<QueryString> for TrackerRequest {
 			None => return Err(()),
 		};
 
-		// let compact = value.remove("compact").map(|s| s != vec![b'0']);
-		let compact = None;
+		let corrupt = match value.remove("corrupt") {
+			Some(s) => Some(parse!(s)),
+			None => None,
+		};
+
+		let compact = value.remove("compact").map(|s| s != vec![b'0']);
 		let no_peer_id = value.remove("no_peer_id").map(|s| s != vec![b'0']);
 		let event = value
 			.remove("event")
@@ -134,6 +214,7 @@ impl TryFrom<QueryString> for TrackerRequest {
 			uploaded,
 			downloaded,
 			left,
+			corrupt,
 			compact,
 			no_peer_id,
 			event,
@@ -144,6 +225,43 @@ impl TryFrom<QueryString> for TrackerRequest {
 	}
 }
 
+impl From<TrackerRequest> for QueryString {
+	fn from(request: TrackerRequest) -> Self {
+		let flag = |b: bool| Vec::from(if b { "1" } else { "0" });
+
+		let mut map: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+		map.insert("info_hash".into(), request.info_hash.into());
+		map.insert("peer_id".into(), request.peer_id.into());
+		map.insert("port".into(), request.port.to_string().into());
+		map.insert("uploaded".into(), request.uploaded.to_string().into());
+		map.insert("downloaded".into(), request.downloaded.to_string().into());
+		map.insert("left".into(), request.left.to_string().into());
+		if let Some(corrupt) = request.corrupt {
+			map.insert("corrupt".into(), corrupt.to_string().into());
+		}
+		if let Some(compact) = request.compact {
+			map.insert("compact".into(), flag(compact));
+		}
+		if let Some(no_peer_id) = request.no_peer_id {
+			map.insert("no_peer_id".into(), flag(no_peer_id));
+		}
+		if let Some(event) = request.event.filter(|e| e != &TrackerEvent::REGULAR) {
+			let event: &str = event.into();
+			map.insert("event".into(), event.into());
+		}
+		if let Some(ip) = request.ip {
+			map.insert("ip".into(), ip);
+		}
+		if let Some(numwant) = request.numwant {
+			map.insert("numwant".into(), numwant.to_string().into());
+		}
+		if let Some(trackerid) = request.trackerid {
+			map.insert("trackerid".into(), trackerid);
+		}
+		QueryString::from(map)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use std::collections::HashMap;
@@ -170,6 +288,7 @@ mod tests {
 			)),
 			Ok(TrackerRequest {
 				compact: None,
+				corrupt: None,
 				downloaded: 3000,
 				event: None,
 				info_hash: [b'b'; 20],
@@ -184,4 +303,113 @@ mod tests {
 			})
 		);
 	}
+
+	#[test]
+	fn test_corrupt() {
+		let parse = |corrupt: Option<&str>| {
+			let mut query = HashMap::from([
+				("info_hash", "bbbbbbbbbbbbbbbbbbbb"),
+				("peer_id", "aaaaaaaaaaaaaaaaaaaa"),
+				("port", "8080"),
+				("uploaded", "0"),
+				("downloaded", "0"),
+				("left", "0"),
+			]);
+			if let Some(corrupt) = corrupt {
+				query.insert("corrupt", corrupt);
+			}
+			TrackerRequest::try_from(QueryString::from(
+				query
+					.into_iter()
+					.map(|(k, v)| (k.into(), v.into()))
+					.collect::<HashMap<_, _>>(),
+			))
+			.map(|request| request.corrupt)
+		};
+
+		assert_eq!(parse(None), Ok(None));
+		assert_eq!(parse(Some("16384")), Ok(Some(16384)));
+		assert_eq!(parse(Some("lots")), Err(()));
+	}
+
+	#[test]
+	fn test_builder() {
+		assert_eq!(
+			TrackerRequest::builder([b'b'; 20], [b'a'; 20], 8080).build(),
+			TrackerRequest {
+				info_hash: [b'b'; 20],
+				peer_id: [b'a'; 20],
+				port: 8080,
+				uploaded: 0,
+				downloaded: 0,
+				left: 0,
+				corrupt: None,
+				compact: Some(true),
+				no_peer_id: None,
+				event: None,
+				ip: None,
+				numwant: None,
+				trackerid: None,
+			}
+		);
+
+		assert_eq!(
+			TrackerRequest::builder([b'b'; 20], [b'a'; 20], 8080)
+				.uploaded(25000)
+				.downloaded(3000)
+				.left(200)
+				.event(TrackerEvent::STARTED)
+				.numwant(10)
+				.compact(false)
+				.trackerid(Some("abc"))
+				.build(),
+			TrackerRequest {
+				info_hash: [b'b'; 20],
+				peer_id: [b'a'; 20],
+				port: 8080,
+				uploaded: 25000,
+				downloaded: 3000,
+				left: 200,
+				corrupt: None,
+				compact: Some(false),
+				no_peer_id: None,
+				event: Some(TrackerEvent::STARTED),
+				ip: None,
+				numwant: Some(10),
+				trackerid: Some("abc".into()),
+			}
+		);
+	}
+
+	#[test]
+	fn test_trackerrequest_round_trip() {
+		let round_trip = |request: TrackerRequest| {
+			let encoded = String::from_utf8(crate::tracker::encode(request.into())).unwrap();
+			TrackerRequest::try_from(crate::tracker::decode(&encoded).unwrap())
+		};
+
+		let minimal = || TrackerRequest::builder([0xff; 20], [0; 20], 6881);
+		assert_eq!(round_trip(minimal().build()), Ok(minimal().build()));
+
+		let full = || {
+			let mut request = minimal()
+				.uploaded(1)
+				.downloaded(2)
+				.left(3)
+				.event(TrackerEvent::COMPLETED)
+				.numwant(50)
+				.compact(false)
+				.trackerid(Some(&b"id\x00&="[..]))
+				.build();
+			request.no_peer_id = Some(true);
+			request.ip = Some("10.0.0.1".into());
+			request.corrupt = Some(4);
+			request
+		};
+		assert_eq!(round_trip(full()), Ok(full()));
+
+		// regular announces don't send an event
+		let regular: QueryString = minimal().event(TrackerEvent::REGULAR).build().into();
+		assert!(!regular.contains_key("event"));
+	}
 }
@@ -1,16 +1,17 @@
-use std::net::{IpAddr, SocketAddrV4};
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
 
-use crate::bencode::{Data, Dictionary};
+use crate::bencode::{self, impl_try_from_data_dict, Data, Dictionary};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum IP {
 	IP(IpAddr),
 	STRING(String),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Peer {
-	pub peer_id: [u8; 20],
+	/// left out when the client asks for `no_peer_id`
+	pub peer_id: Option<[u8; 20]>,
 	pub ip: IP,
 	pub port: u16,
 }
@@ -18,7 +19,7 @@ pub struct Peer {
 impl Into<Dictionary> for Peer {
 	fn into(self) -> Dictionary {
 		let mut dict = Dictionary::new();
-		dict.insert("peer id", self.peer_id);
+		dict.insert_some("peer id", self.peer_id);
 		dict.insert(
 			"ip",
 			match self.ip {
@@ -26,40 +27,121 @@ impl Into<Dictionary> for Peer {
 				IP::STRING(s) => s,
 			},
 		);
-		dict.insert("port", Data::UInt(self.port as u64));
+		dict.insert("port", self.port);
 		dict
 	}
 }
 
-#[derive(Clone, Debug)]
+impl TryFrom<Dictionary> for Peer {
+	type Error = ();
+
+	fn try_from(mut value: Dictionary) -> Result<Self, Self::Error> {
+		let ip = String::from_utf8(value.remove_as("ip")?).map_err(|_| ())?;
+		Ok(Self {
+			peer_id: value.remove_as_opt("peer id")?,
+			// hostnames are allowed as well as addresses
+			ip: match ip.parse() {
+				Ok(ip) => IP::IP(ip),
+				Err(_) => IP::STRING(ip),
+			},
+			port: value.remove_as("port")?,
+		})
+	}
+}
+
+impl_try_from_data_dict!(Peer);
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Peers {
 	Full(Vec<Peer>),
-	/// first 4 bytes are ipv4, last 2 are port
-	Compact(Vec<[u8; 6]>),
+	/// 6 bytes per peer: first 4 bytes are ipv4, last 2 are port.  The length has to be a multiple
+	/// of 6, which decoding checks; a partial peer at the end is otherwise ignored.
+	Compact(Vec<u8>),
 }
 
 impl Into<Data> for Peers {
 	fn into(self) -> Data {
 		match self {
 			Self::Full(peers) => Data::from(peers),
-			Self::Compact(bytes) => bytes.into_iter().flatten().collect::<Vec<u8>>().into(),
+			Self::Compact(bytes) => bytes.into(),
 		}
 	}
 }
 
 impl Peers {
 	pub fn create_compact(addrs: Vec<SocketAddrV4>) -> Self {
-		let mut vec = Vec::new();
+		let mut vec = Vec::with_capacity(addrs.len() * 6);
 		for addr in addrs {
-			let ip = addr.ip().octets();
-			let port = addr.port().to_be_bytes();
-			vec.push([ip[0], ip[1], ip[2], ip[3], port[0], port[1]]);
+			vec.extend_from_slice(&addr.ip().octets());
+			vec.extend_from_slice(&addr.port().to_be_bytes());
 		}
 		Self::Compact(vec)
 	}
+
+	/// number of peers: entries of a full list, or 6 byte chunks of a compact one
+	#[allow(dead_code)]
+	pub fn len(&self) -> usize {
+		match self {
+			Self::Full(peers) => peers.len(),
+			Self::Compact(bytes) => bytes.len() / 6,
+		}
+	}
+
+	#[allow(dead_code)]
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// decodes compact peers as they're iterated.  full peer lists have none.
+	#[allow(dead_code)]
+	pub fn compact_iter(&self) -> impl Iterator<Item = SocketAddrV4> + '_ {
+		let bytes = match self {
+			Self::Compact(bytes) => &bytes[..],
+			Self::Full(_) => &[],
+		};
+		bytes.chunks_exact(6).map(|p| {
+			SocketAddrV4::new(
+				[p[0], p[1], p[2], p[3]].into(),
+				u16::from_be_bytes([p[4], p[5]]),
+			)
+		})
+	}
+
+	pub fn addrs(&self) -> Vec<SocketAddr> {
+		match self {
+			Self::Full(peers) => peers
+				.iter()
+				.filter_map(|peer| match peer.ip {
+					IP::IP(ip) => Some(SocketAddr::new(ip, peer.port)),
+					IP::STRING(_) => None,
+				})
+				.collect(),
+			Self::Compact(_) => self.compact_iter().map(SocketAddr::V4).collect(),
+		}
+	}
+}
+
+/// an entry of `peers6`
+pub fn compact6(addr: SocketAddrV6) -> [u8; 18] {
+	let mut bytes = [0; 18];
+	bytes[..16].copy_from_slice(&addr.ip().octets());
+	bytes[16..].copy_from_slice(&addr.port().to_be_bytes());
+	bytes
+}
+
+impl TryFrom<Data> for Peers {
+	type Error = ();
+
+	fn try_from(value: Data) -> Result<Self, Self::Error> {
+		match value {
+			Data::Bytes(bytes) if bytes.len() % 6 == 0 => Ok(Self::Compact(bytes)),
+			Data::List(_) => Vec::try_from(value).map(Self::Full),
+			_ => Err(()),
+		}
+	}
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TrackerResponse {
 	Ok {
 		interval: u64,
@@ -68,9 +150,11 @@ pub enum TrackerResponse {
 		complete: u64,
 		incomplete: u64,
 		peers: Peers,
+		/// compact ipv6 peers: first 16 bytes are ipv6, last 2 are port
+		peers6: Option<Vec<[u8; 18]>>,
 		warning_message: Option<String>,
 	},
-	Err(&'static str),
+	Err(String),
 }
 
 impl Into<Dictionary> for TrackerResponse {
@@ -83,6 +167,7 @@ impl Into<Dictionary> for TrackerResponse {
 				complete,
 				incomplete,
 				peers,
+				peers6,
 				warning_message,
 			} => {
 				let mut dict = Dictionary::new();
@@ -97,6 +182,7 @@ impl Into<Dictionary> for TrackerResponse {
 				dict.insert("incomplete", incomplete);
 
 				dict.insert("peers", peers);
+				dict.insert_some("peers6", peers6.map(|peers| peers.concat()));
 
 				dict.insert_some("warning message", warning_message);
 				dict
@@ -106,14 +192,94 @@ impl Into<Dictionary> for TrackerResponse {
 	}
 }
 
+/// the keys some old clients expect first in an announce response
+const LEGACY_KEY_ORDER: [&str; 5] = [
+	"interval",
+	"min interval",
+	"complete",
+	"incomplete",
+	"peers",
+];
+
+impl TrackerResponse {
+	/// encodes the response with `LEGACY_KEY_ORDER` first rather than sorted
+	pub fn encode_legacy(self) -> Vec<u8> {
+		bencode::encode_ordered(self.into(), &LEGACY_KEY_ORDER)
+	}
+}
+
+fn remove_string(dict: &mut Dictionary, key: &str) -> Result<Option<String>, ()> {
+	dict.remove_as_opt::<Vec<u8>>(key)?
+		.map(|s| String::from_utf8(s).map_err(|_| ()))
+		.transpose()
+}
+
+impl TryFrom<Dictionary> for TrackerResponse {
+	type Error = ();
+
+	fn try_from(mut value: Dictionary) -> Result<Self, Self::Error> {
+		if let Some(reason) = remove_string(&mut value, "failure reason")? {
+			return Ok(Self::Err(reason));
+		}
+
+		Ok(Self::Ok {
+			interval: value.remove_as("interval")?,
+			min_interval: value.remove_as_opt("min interval")?,
+			tracker_id: remove_string(&mut value, "tracker id")?,
+			// not every tracker sends swarm counts
+			complete: value.remove_as_opt("complete")?.unwrap_or(0),
+			incomplete: value.remove_as_opt("incomplete")?.unwrap_or(0),
+			peers: value.remove_as("peers")?,
+			peers6: match value.remove_as_opt::<Vec<u8>>("peers6")? {
+				Some(bytes) if bytes.len() % 18 == 0 => Some(
+					bytes
+						.chunks_exact(18)
+						.map(|c| c.try_into().expect("chunk is 18 bytes"))
+						.collect(),
+				),
+				Some(_) => return Err(()),
+				None => None,
+			},
+			warning_message: remove_string(&mut value, "warning message")?,
+		})
+	}
+}
+
+impl_try_from_data_dict!(TrackerResponse);
+
+impl TrackerResponse {
+	/// every peer address in the response, ipv4 and ipv6.  peers given by hostname are left out.
+	#[allow(dead_code)]
+	pub fn peer_addrs(&self) -> Vec<SocketAddr> {
+		let Self::Ok { peers, peers6, .. } = self else {
+			return vec![];
+		};
+
+		let mut addrs = peers.addrs();
+		for peer in peers6.iter().flatten() {
+			let ip: [u8; 16] = peer[..16].try_into().expect("slice is 16 bytes");
+			let port = u16::from_be_bytes([peer[16], peer[17]]);
+			addrs.push(SocketAddrV6::new(ip.into(), port, 0, 0).into());
+		}
+		addrs
+	}
+}
+
 #[cfg(test)]
 mod test {
 	use crate::{
-		bencode::encode,
+		bencode::{encode, try_decode_from},
 		bytes::assert_bytes_eq,
 		tracker::{Peer, Peers, TrackerResponse},
 	};
 
+	fn assert_round_trip(response: TrackerResponse) {
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>(encode(response.clone())),
+			Ok(Ok(response))
+		);
+	}
+
 	use super::IP;
 
 	#[test]
@@ -121,7 +287,7 @@ mod test {
 		assert_bytes_eq(
 			encode(Peer {
 				ip: IP::STRING("127.0.0.1".into()),
-				peer_id: [b'1'; 20],
+				peer_id: Some([b'1'; 20]),
 				port: 16384,
 			}),
 			"d2:ip9:127.0.0.17:peer id20:111111111111111111114:porti16384ee",
@@ -129,11 +295,25 @@ mod test {
 
 		assert_bytes_eq(encode(Peer {
 			ip: IP::STRING("127.0.0.1".into()),
-			peer_id: [0; 20],
+			peer_id: Some([0; 20]),
 			port: 25565
 		}), "d2:ip3:::17:peer id20:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x004:porti25565ee")
 	}
 
+	#[test]
+	fn test_peer_no_peer_id() {
+		let peer = Peer {
+			ip: IP::IP([127, 0, 0, 1].into()),
+			peer_id: None,
+			port: 16384,
+		};
+		assert_bytes_eq(encode(peer.clone()), "d2:ip9:127.0.0.14:porti16384ee");
+		assert_eq!(
+			try_decode_from::<Peer, _>("d2:ip9:127.0.0.14:porti16384ee"),
+			Ok(Ok(peer))
+		);
+	}
+
 	#[test]
 	fn test_trackerresponse_into() {
 		assert_bytes_eq(
@@ -145,12 +325,173 @@ mod test {
 				incomplete: 0,
 				peers: Peers::Full(vec![Peer {
 					ip: IP::STRING("127.0.0.1".into()),
-					peer_id: [b'1'; 20],
+					peer_id: Some([b'1'; 20]),
 					port: 16384,
 				}]),
+				peers6: None,
 				warning_message: None,
 			}),
 			"d8:completei1e10:incompletei0e8:intervali300e5:peersld2:ip9:127.0.0.17:peer id20:111111111111111111114:porti16384eeee"
 		);
 	}
+
+	#[test]
+	fn test_trackerresponse_from() {
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>(
+				"d8:completei1e8:intervali300e12:min intervali60e5:peers6:\x7f\x00\x00\x01\x40\x00e"
+			),
+			Ok(Ok(TrackerResponse::Ok {
+				interval: 300,
+				min_interval: Some(60),
+				tracker_id: None,
+				complete: 1,
+				incomplete: 0,
+				peers: Peers::Compact(vec![127, 0, 0, 1, 0x40, 0]),
+				peers6: None,
+				warning_message: None,
+			}))
+		);
+
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>("d14:failure reason4:nopee"),
+			Ok(Ok(TrackerResponse::Err("nope".into())))
+		);
+
+		// missing interval
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>("d5:peers0:e"),
+			Ok(Err(()))
+		);
+		// compact peers not a multiple of 6
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>("d8:intervali1e5:peers5:aaaaae"),
+			Ok(Err(()))
+		);
+	}
+
+	#[test]
+	fn test_trackerresponse_round_trip() {
+		let response = |peers| TrackerResponse::Ok {
+			interval: 300,
+			min_interval: None,
+			tracker_id: None,
+			complete: 4,
+			incomplete: 2,
+			peers,
+			peers6: None,
+			warning_message: None,
+		};
+
+		assert_round_trip(response(Peers::Full(vec![
+			Peer {
+				ip: IP::IP([127, 0, 0, 1].into()),
+				peer_id: Some([b'1'; 20]),
+				port: 16384,
+			},
+			Peer {
+				ip: IP::STRING("peer.example".into()),
+				peer_id: Some([0; 20]),
+				port: 25565,
+			},
+		])));
+		assert_round_trip(response(Peers::Full(vec![])));
+		assert_round_trip(response(Peers::create_compact(vec![
+			"127.0.0.1:16384".parse().unwrap(),
+			"10.0.0.2:6881".parse().unwrap(),
+		])));
+		assert_round_trip(TrackerResponse::Err("Invalid info hash.".into()));
+
+		// peer missing a port
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>(
+				"d8:intervali1e5:peersld2:ip9:127.0.0.17:peer id20:11111111111111111111eee"
+			),
+			Ok(Err(()))
+		);
+	}
+
+	#[test]
+	fn test_peers6() {
+		let response = try_decode_from::<TrackerResponse, _>(
+			&b"d8:intervali300e5:peers6:\x7f\x00\x00\x01\x1f\x906:peers618:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x40\x00e"[..],
+		)
+		.unwrap()
+		.unwrap();
+
+		assert_eq!(
+			response.peer_addrs(),
+			vec![
+				"127.0.0.1:8080".parse::<std::net::SocketAddr>().unwrap(),
+				"[::1]:16384".parse().unwrap()
+			]
+		);
+		assert_round_trip(response);
+
+		// entries must be 18 bytes
+		assert_eq!(
+			try_decode_from::<TrackerResponse, _>("d8:intervali1e5:peers0:6:peers65:aaaaae"),
+			Ok(Err(()))
+		);
+	}
+
+	#[test]
+	fn test_compact_iter() {
+		let peers = Peers::create_compact(vec![
+			"127.0.0.1:16384".parse().unwrap(),
+			"10.0.0.2:6881".parse().unwrap(),
+			"192.168.1.1:80".parse().unwrap(),
+		]);
+		assert_eq!(
+			peers,
+			Peers::Compact(vec![
+				127, 0, 0, 1, 0x40, 0, 10, 0, 0, 2, 0x1a, 0xe1, 192, 168, 1, 1, 0, 80
+			])
+		);
+
+		let mut iter = peers.compact_iter();
+		assert_eq!(iter.next(), Some("127.0.0.1:16384".parse().unwrap()));
+		assert_eq!(iter.next(), Some("10.0.0.2:6881".parse().unwrap()));
+		assert_eq!(iter.next(), Some("192.168.1.1:80".parse().unwrap()));
+		assert_eq!(iter.next(), None);
+
+		// encodes as the buffer itself
+		assert_eq!(
+			encode(Peers::create_compact(vec!["127.0.0.1:16384"
+				.parse()
+				.unwrap()])),
+			b"6:\x7f\x00\x00\x01\x40\x00"
+		);
+		assert_eq!(Peers::Full(vec![]).compact_iter().count(), 0);
+	}
+
+	#[test]
+	fn test_len() {
+		let full = Peers::Full(vec![
+			Peer {
+				peer_id: None,
+				ip: IP::IP("10.0.0.1".parse().unwrap()),
+				port: 6881,
+			},
+			Peer {
+				peer_id: Some([b'a'; 20]),
+				ip: IP::STRING("peer.example".into()),
+				port: 6882,
+			},
+		]);
+		assert_eq!(full.len(), 2);
+		assert!(!full.is_empty());
+
+		let compact = Peers::create_compact(vec![
+			"127.0.0.1:16384".parse().unwrap(),
+			"10.0.0.2:6881".parse().unwrap(),
+			"192.168.1.1:80".parse().unwrap(),
+		]);
+		assert_eq!(compact.len(), 3);
+		assert!(!compact.is_empty());
+
+		assert_eq!(Peers::create_compact(vec![]).len(), 0);
+		assert!(Peers::create_compact(vec![]).is_empty());
+		assert!(Peers::Full(vec![]).is_empty());
+	}
 }